@@ -0,0 +1,8 @@
+ALTER TABLE projects
+    ADD COLUMN preview_delete_closed INTEGER NOT NULL DEFAULT 1; -- 0 false 1 true
+
+ALTER TABLE projects
+    ADD COLUMN preview_retention INTEGER NOT NULL DEFAULT 3; -- 0 means keeping all
+
+ALTER TABLE projects
+    ADD COLUMN preview_ttl_days INTEGER NOT NULL DEFAULT 0; -- 0 means never expiring
@@ -0,0 +1,8 @@
+ALTER TABLE projects
+    ADD COLUMN repo TEXT NOT NULL DEFAULT ''; -- provider qualified reference, e.g. github:<repo id> or gitlab:<host>/<path>
+
+UPDATE projects
+    SET repo = 'github:' || repo_id;
+
+ALTER TABLE projects
+    DROP COLUMN repo_id;
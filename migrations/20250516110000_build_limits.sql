@@ -0,0 +1,8 @@
+ALTER TABLE deployments
+    ADD COLUMN config_build_timeout INTEGER; -- seconds
+
+ALTER TABLE deployments
+    ADD COLUMN config_build_cpus REAL;
+
+ALTER TABLE deployments
+    ADD COLUMN config_build_memory INTEGER; -- megabytes
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS attempts (
+    id INTEGER PRIMARY KEY NOT NULL,
+    phase TEXT NOT NULL, -- build or start
+    number INTEGER NOT NULL, -- starting from 1 for every build or start
+    started INTEGER NOT NULL,
+    finished INTEGER NOT NULL,
+    error TEXT, -- NULL if the attempt succeeded
+    transient INTEGER NOT NULL, -- 0 false 1 true
+    deployment TEXT NOT NULL,
+    FOREIGN KEY (deployment) REFERENCES deployments(id) ON DELETE CASCADE
+);
@@ -0,0 +1,17 @@
+ALTER TABLE deployments
+    ADD COLUMN config_health_path TEXT;
+
+ALTER TABLE deployments
+    ADD COLUMN config_health_status_min INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_health_status_max INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_health_interval INTEGER; -- seconds
+
+ALTER TABLE deployments
+    ADD COLUMN config_health_startup_timeout INTEGER; -- seconds
+
+ALTER TABLE deployments
+    ADD COLUMN config_health_failure_threshold INTEGER;
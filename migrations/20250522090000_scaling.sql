@@ -0,0 +1,19 @@
+-- idle timeouts in seconds, -1 means always on and null falls back to the default
+
+ALTER TABLE projects
+    ADD COLUMN scaling_preview INTEGER;
+
+ALTER TABLE projects
+    ADD COLUMN scaling_prod INTEGER;
+
+ALTER TABLE projects
+    ADD COLUMN scaling_db INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_scaling_preview INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_scaling_prod INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_scaling_db INTEGER;
@@ -0,0 +1,5 @@
+ALTER TABLE deployments
+    ADD COLUMN config_port INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_ports TEXT; -- json object from port name to port number
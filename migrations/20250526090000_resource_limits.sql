@@ -0,0 +1,14 @@
+ALTER TABLE deployments
+    ADD COLUMN config_resources_memory INTEGER; -- megabytes
+
+ALTER TABLE deployments
+    ADD COLUMN config_resources_memory_swap INTEGER; -- megabytes, -1 means unlimited
+
+ALTER TABLE deployments
+    ADD COLUMN config_resources_cpu_shares INTEGER;
+
+ALTER TABLE deployments
+    ADD COLUMN config_resources_cpus REAL;
+
+ALTER TABLE deployments
+    ADD COLUMN config_resources_pids INTEGER;
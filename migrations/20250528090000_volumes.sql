@@ -0,0 +1,2 @@
+ALTER TABLE deployments
+    ADD COLUMN config_volumes TEXT; -- json object from volume name to mount path
@@ -0,0 +1,2 @@
+ALTER TABLE deployments
+    ADD COLUMN config_redis INTEGER; -- 0 false 1 true
@@ -0,0 +1,6 @@
+ALTER TABLE deployments
+    ADD COLUMN config_replicas_min INTEGER;
+ALTER TABLE deployments
+    ADD COLUMN config_replicas_max INTEGER;
+ALTER TABLE deployments
+    ADD COLUMN config_replicas_target INTEGER; -- in-flight requests per replica
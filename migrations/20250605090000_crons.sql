@@ -0,0 +1,16 @@
+ALTER TABLE deployments
+    ADD COLUMN config_crons TEXT; -- json encoded
+
+CREATE TABLE IF NOT EXISTS cron_runs (
+    id INTEGER PRIMARY KEY NOT NULL,
+    job TEXT NOT NULL,
+    manual INTEGER NOT NULL, -- 0 scheduled 1 triggered through the API
+    started INTEGER NOT NULL,
+    finished INTEGER NOT NULL,
+    status INTEGER, -- exit code for commands and http status for paths, NULL if the job could not run
+    success INTEGER NOT NULL, -- 0 false 1 true
+    logs TEXT NOT NULL,
+    deployment TEXT NOT NULL,
+    project TEXT NOT NULL,
+    FOREIGN KEY (project) REFERENCES projects(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+ALTER TABLE deployments
+    ADD COLUMN config_release TEXT;
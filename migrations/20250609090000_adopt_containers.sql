@@ -0,0 +1,4 @@
+ALTER TABLE deployments
+    ADD COLUMN image TEXT; -- docker image id, set once built successfully
+ALTER TABLE deployments
+    ADD COLUMN container TEXT; -- docker container serving the deployment, NULL if not running
@@ -0,0 +1,2 @@
+ALTER TABLE attempts
+    ADD COLUMN build INTEGER NOT NULL DEFAULT 1; -- starting from 1, increased on every rebuild of the deployment
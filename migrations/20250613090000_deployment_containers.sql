@@ -0,0 +1,4 @@
+ALTER TABLE deployments
+    DROP COLUMN container;
+ALTER TABLE deployments
+    ADD COLUMN containers TEXT; -- json array with the docker container of every replica serving the deployment, NULL if not running
@@ -0,0 +1,6 @@
+-- BuildKit ignores the cpu and memory limits sent along with the build, so only the timeout is kept
+ALTER TABLE deployments
+    DROP COLUMN config_build_cpus;
+
+ALTER TABLE deployments
+    DROP COLUMN config_build_memory;
@@ -11,9 +11,12 @@ use crate::{
         utils::{
             get_all_deployments, get_prod_deployment, get_prod_deployment_id, is_app_name_valid,
         },
-        AppState, ErrorResponse, FullProjectInfo, ProjectInfo,
+        AppState, ErrorResponse, FullProjectInfo, ProjectInfo, RotateKeys,
+    },
+    db::{
+        nano_id::{IntoOptString, NanoId},
+        BuildResult, EnvVar, InsertProject, UpdateProject,
     },
-    db::{nano_id::IntoOptString, EnvVar, InsertProject, UpdateProject},
 };
 
 /// Get projects
@@ -38,9 +41,12 @@ async fn get_projects(auth: AnyRole, state: Data<AppState>) -> impl Responder {
             ProjectInfo {
                 name: project.name.clone(),
                 id: project.id.to_string(),
-                repo: project.repo_id,
+                repo: project.repo.to_string(),
                 created: project.created,
                 custom_domains: project.custom_domains,
+                backup_retention: project.backup_retention,
+                preview_rules: project.preview_rules,
+                scaling: project.scaling,
                 prod_deployment_id: prod_deployment_id.into_opt_string(),
                 prod_deployment,
             }
@@ -74,9 +80,12 @@ async fn get_project(auth: AnyRole, state: Data<AppState>, name: Path<String>) -
             HttpResponse::Ok().json(FullProjectInfo {
                 name: project.name,
                 id: project.id.into(),
-                repo: project.repo_id,
+                repo: project.repo.to_string(),
                 created: project.created,
                 custom_domains: project.custom_domains,
+                backup_retention: project.backup_retention,
+                preview_rules: project.preview_rules,
+                scaling: project.scaling,
                 prod_deployment_id: prod_deployment_id.into_opt_string(),
                 prod_deployment,
                 deployments,
@@ -106,7 +115,7 @@ async fn create_project(
 ) -> impl Responder {
     if is_app_name_valid(&project.name) {
         state.db.insert_project(project.0).await.unwrap();
-        state.manager.full_sync_with_github().await;
+        state.manager.full_sync_with_repos().await;
         HttpResponse::Ok()
     } else {
         HttpResponse::BadRequest()
@@ -141,7 +150,7 @@ async fn update_project(
     if valid_name {
         let id = id.into_inner().into();
         state.db.update_project(&id, project.0).await.unwrap();
-        state.manager.sync_with_db().await; // TODO: review if its fine not doing a full sync with github here
+        state.manager.sync_with_db().await; // TODO: review if its fine not doing a full sync with the repos here
         HttpResponse::Ok()
     } else {
         HttpResponse::BadRequest()
@@ -245,3 +254,138 @@ async fn delete_env(
     // state.manager.sync_with_db().await; // TODO: review if its fine not calling sync here
     HttpResponse::Ok()
 }
+
+/// Rotate prod db keys
+#[utoipa::path(
+    request_body = RotateKeys,
+    responses(
+        (status = 200, description = "Keys rotated successfully"),
+        (status = 404, description = "Project not found", body = ErrorResponse),
+        (status = 500, description = "Internal error when rotating the keys", body = String)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/apps/{id}/libsql/keys/rotate")]
+#[tracing::instrument]
+async fn rotate_db_keys(
+    _auth: AdminRole,
+    body: Json<RotateKeys>,
+    state: Data<AppState>,
+    id: Path<String>,
+) -> impl Responder {
+    let id = id.into_inner().into();
+    if state.db.get_project(&id).await.unwrap().is_none() {
+        return HttpResponse::NotFound().json(ErrorResponse::NotFound(format!("id = {id}")));
+    }
+    match state.manager.rotate_prod_db_keys(&id, body.0.revoke).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
+
+/// Promote deployment to prod
+#[utoipa::path(
+    request_body = String,
+    responses(
+        (status = 200, description = "Deployment pinned as prod successfully"),
+        (status = 400, description = "Deployment is not a successful build from the default branch"),
+        (status = 404, description = "Deployment not found", body = ErrorResponse)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/apps/{id}/prod")]
+#[tracing::instrument]
+async fn promote_deployment(
+    _auth: AdminRole,
+    deployment: Json<String>,
+    state: Data<AppState>,
+    id: Path<String>,
+) -> impl Responder {
+    let id: NanoId = id.into_inner().into();
+    let deployment_id = deployment.0.into();
+    let deployment = state.db.get_deployment(&deployment_id).await.unwrap();
+    match deployment {
+        Some(deployment) if deployment.project == id => {
+            // other branches run against a branch db, so they can't be served as prod
+            let valid =
+                deployment.is_default_branch() && deployment.result == Some(BuildResult::Built);
+            if valid {
+                state
+                    .db
+                    .update_prod_id(&id, Some(&deployment_id))
+                    .await
+                    .unwrap();
+                state.manager.sync_with_db().await;
+                HttpResponse::Ok().finish()
+            } else {
+                HttpResponse::BadRequest().finish()
+            }
+        }
+        _ => HttpResponse::NotFound().json(ErrorResponse::NotFound(format!(
+            "deployment = {deployment_id}"
+        ))),
+    }
+}
+
+/// Roll back prod to the previous deployment
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Previous deployment pinned as prod successfully"),
+        (status = 404, description = "No previous deployment found", body = ErrorResponse)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/apps/{id}/prod/rollback")]
+#[tracing::instrument]
+async fn rollback_prod(
+    _auth: AdminRole,
+    state: Data<AppState>,
+    id: Path<String>,
+) -> impl Responder {
+    let id = id.into_inner().into();
+    let current = state.manager.get_prod_deployment(&id).await;
+    let before = current.map_or(i64::MAX, |deployment| deployment.created);
+    let previous = state
+        .db
+        .get_previous_successful_prod_deployment(&id, before)
+        .await
+        .unwrap();
+    match previous {
+        Some(previous) => {
+            state
+                .db
+                .update_prod_id(&id, Some(&previous.id))
+                .await
+                .unwrap();
+            state.manager.sync_with_db().await;
+            HttpResponse::Ok().finish()
+        }
+        None => HttpResponse::NotFound().json(ErrorResponse::NotFound(format!(
+            "previous prod for id = {id}"
+        ))),
+    }
+}
+
+/// Unpin prod so it follows the latest successful build again
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Prod unpinned successfully"),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[delete("/api/apps/{id}/prod")]
+#[tracing::instrument]
+async fn unpin_prod(_auth: AdminRole, state: Data<AppState>, id: Path<String>) -> impl Responder {
+    let id = id.into_inner().into();
+    state.db.update_prod_id(&id, None).await.unwrap();
+    state.manager.sync_with_db().await;
+    HttpResponse::Ok()
+}
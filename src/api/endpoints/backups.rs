@@ -0,0 +1,126 @@
+use actix_web::{
+    get, post,
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+
+use crate::{
+    api::{
+        bearer::{AdminRole, AnyRole},
+        AppState, ErrorResponse,
+    },
+    backups::{create_backup, get_backup_path, list_backups, restore_backup},
+    db::nano_id::NanoId,
+};
+
+/// Get prod db backups
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Backups returned successfully, newest first", body = [Backup]),
+        (status = 500, description = "Internal error when listing the backups", body = String)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[get("/api/apps/{id}/backups")]
+#[tracing::instrument]
+async fn get_backups(_auth: AnyRole, id: Path<String>) -> impl Responder {
+    let id: NanoId = id.into_inner().into();
+    match list_backups(&id).await {
+        Ok(backups) => HttpResponse::Ok().json(backups),
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
+
+/// Create prod db backup
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Backup created successfully", body = Backup),
+        (status = 404, description = "Project not found", body = ErrorResponse),
+        (status = 500, description = "Internal error when creating the backup", body = String)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/apps/{id}/backups")]
+#[tracing::instrument]
+async fn create_project_backup(
+    _auth: AdminRole,
+    state: Data<AppState>,
+    id: Path<String>,
+) -> impl Responder {
+    let id = id.into_inner().into();
+    let Some(setup) = state.manager.get_prod_db(&id).await else {
+        return HttpResponse::NotFound().json(ErrorResponse::NotFound(format!("id = {id}")));
+    };
+    match create_backup(&id, &setup).await {
+        Ok(backup) => HttpResponse::Ok().json(backup),
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
+
+/// Download prod db backup
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Backup archive returned successfully", content_type = "application/gzip"),
+        (status = 404, description = "Backup not found", body = ErrorResponse),
+        (status = 500, description = "Internal error when reading the backup", body = String)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[get("/api/apps/{id}/backups/{backup}")]
+#[tracing::instrument]
+async fn download_backup(_auth: AdminRole, path: Path<(String, String)>) -> impl Responder {
+    let (id, backup) = path.into_inner();
+    let Some(path) = get_backup_path(&id.into(), &backup) else {
+        return HttpResponse::NotFound()
+            .json(ErrorResponse::NotFound(format!("backup = {backup}")));
+    };
+    match tokio::fs::read(path).await {
+        Ok(archive) => HttpResponse::Ok()
+            .content_type("application/gzip")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{backup}.tar.gz\""),
+            ))
+            .body(archive),
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
+
+/// Restore prod db backup
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Backup restored successfully"),
+        (status = 404, description = "Project or backup not found", body = ErrorResponse),
+        (status = 500, description = "Internal error when restoring the backup", body = String)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/apps/{id}/backups/{backup}/restore")]
+#[tracing::instrument]
+async fn restore_project_backup(
+    _auth: AdminRole,
+    state: Data<AppState>,
+    path: Path<(String, String)>,
+) -> impl Responder {
+    let (id, backup) = path.into_inner();
+    let id = id.into();
+    let Some(setup) = state.manager.get_prod_db(&id).await else {
+        return HttpResponse::NotFound().json(ErrorResponse::NotFound(format!("id = {id}")));
+    };
+    if get_backup_path(&id, &backup).is_none() {
+        return HttpResponse::NotFound()
+            .json(ErrorResponse::NotFound(format!("backup = {backup}")));
+    }
+    match restore_backup(&id, &backup, &setup).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
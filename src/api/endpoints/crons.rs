@@ -0,0 +1,61 @@
+use actix_web::{
+    get, post,
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+
+use crate::{
+    api::{
+        bearer::{AdminRole, AnyRole},
+        ApiCronRun, AppState, ErrorResponse,
+    },
+    db::nano_id::NanoId,
+};
+
+/// Get the latest cron job runs of the app
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Cron job runs returned successfully, newest first", body = [ApiCronRun]),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[get("/api/apps/{id}/crons/runs")]
+#[tracing::instrument]
+async fn get_cron_runs(_auth: AnyRole, state: Data<AppState>, id: Path<String>) -> impl Responder {
+    let id: NanoId = id.into_inner().into();
+    match state.db.get_cron_runs(&id).await {
+        Ok(runs) => {
+            let runs: Vec<ApiCronRun> = runs.into_iter().map(|run| run.into()).collect();
+            HttpResponse::Ok().json(runs)
+        }
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
+
+/// Run a cron job of the prod deployment right away
+#[utoipa::path(
+    responses(
+        (status = 202, description = "Cron job started, its run shows up once finished"),
+        (status = 404, description = "The prod deployment has no such cron job", body = ErrorResponse)
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/apps/{id}/crons/{name}/run")]
+#[tracing::instrument]
+async fn run_cron_job(
+    _auth: AdminRole,
+    state: Data<AppState>,
+    path: Path<(String, String)>,
+) -> impl Responder {
+    let (id, name) = path.into_inner();
+    let id: NanoId = id.into();
+    if state.manager.trigger_cron_job(&id, &name).await {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().json(ErrorResponse::NotFound(format!("cron job = {name}")))
+    }
+}
@@ -8,7 +8,7 @@ use crate::{
     api::{
         bearer::{AdminRole, AnyRole},
         utils::clone_deployment,
-        AppState,
+        ApiAttempt, ApiVolume, AppState,
     },
     logging::{read_request_event_logs, Log},
 };
@@ -61,7 +61,7 @@ async fn delete_deployment(
     HttpResponse::Ok()
 }
 
-/// Sync deployments with github
+/// Sync deployments with the repositories
 #[utoipa::path(
     responses(
         (status = 200, description = "Sync triggered successfully"),
@@ -73,7 +73,7 @@ async fn delete_deployment(
 #[post("/api/deployments/sync")]
 #[tracing::instrument]
 async fn sync(auth: AdminRole, state: Data<AppState>) -> impl Responder {
-    state.manager.full_sync_with_github().await;
+    state.manager.full_sync_with_repos().await;
     HttpResponse::Ok()
 }
 
@@ -105,12 +105,23 @@ async fn get_deployment_logs(
         .get_logs()
         .await
         .map(|log| Log::from_docker(log, id.clone()));
+    let process_logs =
+        app_container
+            .get_process_logs()
+            .await
+            .into_iter()
+            .flat_map(|(process, logs)| {
+                let id = id.clone();
+                logs.into_iter()
+                    .map(move |log| Log::from_process(log, id.clone(), process.clone()))
+            });
 
     match read_request_event_logs() {
         Ok(logs) => {
             let mut logs = logs
                 .filter(|log| &log.deployment == id.as_str())
                 .chain(container_logs)
+                .chain(process_logs)
                 .collect::<Vec<_>>();
             logs.sort_by_key(|log| -log.time); // from latest to oldest
             HttpResponse::Ok().json(logs)
@@ -148,3 +159,120 @@ async fn get_deployment_build_logs(
         .collect();
     HttpResponse::Ok().json(logs)
 }
+
+/// Get the build and start attempts of the deployment, oldest first
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Fetched deployment attempts", body = [ApiAttempt]),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[get("/api/deployments/{id}/attempts")]
+#[tracing::instrument]
+async fn get_deployment_attempts(
+    auth: AnyRole,
+    state: Data<AppState>,
+    id: Path<String>,
+) -> impl Responder {
+    let id = id.into_inner().into();
+    match state.db.get_deployment_attempts(&id).await {
+        Ok(attempts) => {
+            let attempts: Vec<ApiAttempt> = attempts.into_iter().map(|a| a.into()).collect();
+            HttpResponse::Ok().json(attempts)
+        }
+        Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
+
+/// Get the persistent volumes of the deployment and their size
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Fetched deployment volumes", body = [ApiVolume]),
+        (status = 404, description = "Deployment not found", body = String),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[get("/api/deployments/{id}/volumes")]
+#[tracing::instrument]
+async fn get_deployment_volumes(
+    auth: AnyRole,
+    state: Data<AppState>,
+    id: Path<String>,
+) -> impl Responder {
+    let id = id.into_inner().into();
+    let app_container = match state.manager.get_deployment(&id).await {
+        Some(deployment) => deployment.app_container,
+        None => return HttpResponse::NotFound().json("not found"),
+    };
+
+    let mut volumes = vec![];
+    for volume in app_container.get_volumes() {
+        match volume.get_size().await {
+            Ok(size) => volumes.push(ApiVolume {
+                name: volume.name.clone(),
+                path: volume.target.clone(),
+                size,
+            }),
+            Err(error) => return HttpResponse::InternalServerError().json(error.to_string()),
+        }
+    }
+    HttpResponse::Ok().json(volumes)
+}
+
+/// Reset a volume of the deployment, preview volumes get a fresh copy of the prod one
+/// while prod volumes are emptied. The app container is stopped in the meantime.
+/// Prod volumes are shared by every deployment from the default branch,
+/// so they can only be reset through the current prod deployment
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Volume reset successfully"),
+        (status = 400, description = "The volume is shared with the prod deployment", body = String),
+        (status = 404, description = "Deployment or volume not found", body = String),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+#[post("/api/deployments/{id}/volumes/{name}/reset")]
+#[tracing::instrument]
+async fn reset_deployment_volume(
+    auth: AdminRole,
+    state: Data<AppState>,
+    path: Path<(String, String)>,
+) -> impl Responder {
+    let (id, name) = path.into_inner();
+    let Some(deployment) = state.manager.get_deployment(&id.into()).await else {
+        return HttpResponse::NotFound().json("not found");
+    };
+    let volume = deployment
+        .app_container
+        .get_volumes()
+        .iter()
+        .find(|volume| volume.name == name);
+    let Some(volume) = volume else {
+        return HttpResponse::NotFound().json("not found");
+    };
+
+    // prod volumes are mounted by the prod container, which is the one to be stopped
+    let app_container = if volume.is_prod() {
+        let prod = state.manager.get_prod_deployment(&deployment.project).await;
+        match prod {
+            Some(prod) if prod.id == deployment.id => prod.app_container,
+            _ => {
+                return HttpResponse::BadRequest().json(
+                    "the volume is shared with the prod deployment, reset it through that one",
+                )
+            }
+        }
+    } else {
+        deployment.app_container.clone()
+    };
+    match app_container.with_stopped(volume.reset()).await {
+        Ok(Ok(())) => HttpResponse::Ok().finish(),
+        Ok(Err(error)) | Err(error) => HttpResponse::InternalServerError().json(error.to_string()),
+    }
+}
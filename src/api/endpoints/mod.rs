@@ -1,4 +1,7 @@
 pub(super) mod apps;
+pub(super) mod backups;
+pub(super) mod crons;
 pub(super) mod deployments;
 pub(super) mod system;
 pub(super) mod version;
+pub(super) mod webhooks;
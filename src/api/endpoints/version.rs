@@ -95,8 +95,14 @@ async fn run_update_container(version: &str) -> anyhow::Result<()> {
     pull_image(&image).await;
     let binds = vec!["/var/run/docker.sock:/var/run/docker.sock".to_owned()];
     let name = generate_unmanaged_container_name();
-    let container =
-        create_container_with_explicit_binds(name, image, Default::default(), binds, Some(command))
-            .await?;
+    let container = create_container_with_explicit_binds(
+        name,
+        image,
+        Default::default(),
+        binds,
+        Some(command),
+        Default::default(),
+    )
+    .await?;
     Ok(run_container(&container).await?)
 }
@@ -0,0 +1,290 @@
+use actix_web::{
+    post,
+    web::{Bytes, Data},
+    HttpRequest, HttpResponse, Responder,
+};
+use chrono::DateTime;
+use ring::hmac;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{
+    api::{AppState, ErrorResponse},
+    db::{InsertDeployment, Project},
+    deployments::workers::source::add_deployment_to_db_if_missing,
+    source::RepoRef,
+    utils::{now, LogError},
+    Conf,
+};
+
+const SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+const EVENT_HEADER: &str = "X-GitHub-Event";
+
+#[derive(Deserialize, Debug)]
+struct Repository {
+    id: i64,
+    default_branch: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct HeadCommit {
+    timestamp: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: String,
+    deleted: bool,
+    head_commit: Option<HeadCommit>,
+    repository: Repository,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequestHead {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    sha: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequest {
+    head: PullRequestHead,
+    updated_at: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequestEvent {
+    action: String,
+    pull_request: PullRequest,
+    repository: Repository,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeleteEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    ref_type: String,
+    repository: Repository,
+}
+
+/// Receive GitHub webhooks
+#[utoipa::path(
+    request_body = String,
+    responses(
+        (status = 202, description = "Event accepted, it is processed in the background"),
+        (status = 401, description = "Invalid signature", body = ErrorResponse),
+        (status = 404, description = "Webhooks are not configured", body = ErrorResponse)
+    )
+)]
+#[post("/api/webhooks/github")]
+#[tracing::instrument]
+async fn github_webhook(
+    request: HttpRequest,
+    body: Bytes,
+    state: Data<AppState>,
+) -> impl Responder {
+    let Conf { webhook_secret, .. } = Conf::read_async().await;
+    let Some(secret) = webhook_secret else {
+        return HttpResponse::NotFound().json(ErrorResponse::NotFound("webhook secret".to_owned()));
+    };
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok());
+    if !is_signature_valid(secret.as_bytes(), &body, signature) {
+        return HttpResponse::Unauthorized()
+            .json(ErrorResponse::Unauthorized("invalid signature".to_owned()));
+    }
+
+    let event = request
+        .headers()
+        .get(EVENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    // GitHub gives up on deliveries taking more than a few seconds,
+    // so the event is acknowledged right away instead of waiting for the sync
+    tokio::spawn(async move { handle_event(&state, &event, &body).await });
+    HttpResponse::Accepted().finish()
+}
+
+async fn handle_event(state: &AppState, event: &str, body: &[u8]) {
+    let result = match event {
+        "push" => handle_push(state, serde_json::from_slice(body)).await,
+        "pull_request" => handle_pull_request(state, serde_json::from_slice(body)).await,
+        "delete" => handle_delete(state, serde_json::from_slice(body)).await,
+        _ => Ok(false), // ping and other events are simply acknowledged
+    };
+    match result {
+        Ok(true) => state.manager.sync_with_db().await,
+        Ok(false) => {}
+        Err(error) => error!("failed to process {event} webhook: {error}"),
+    }
+}
+
+/// The returned boolean tells if the db was modified
+async fn handle_push(
+    state: &AppState,
+    event: serde_json::Result<PushEvent>,
+) -> anyhow::Result<bool> {
+    let event = event?;
+    let branch = event.git_ref.strip_prefix("refs/heads/");
+    // commits to other branches are handled through the pull_request synchronize event
+    if event.deleted || branch != Some(&event.repository.default_branch) {
+        return Ok(false);
+    }
+    let timestamp = event
+        .head_commit
+        .and_then(|commit| parse_timestamp(&commit.timestamp))
+        .unwrap_or(now());
+    for project in get_repo_projects(state, event.repository.id).await? {
+        let deployment = InsertDeployment {
+            env: project.env.clone(),
+            sha: event.after.clone(),
+            timestamp,
+            branch: event.repository.default_branch.clone(),
+            default_branch: 1, // TODO: abstract this as a bool
+            project: project.id.clone(),
+            result: None,
+        };
+        add_deployment(state, deployment, &project).await;
+    }
+    Ok(true)
+}
+
+async fn handle_pull_request(
+    state: &AppState,
+    event: serde_json::Result<PullRequestEvent>,
+) -> anyhow::Result<bool> {
+    let event = event?;
+    let head = event.pull_request.head;
+    let projects = get_repo_projects(state, event.repository.id).await?;
+    match event.action.as_str() {
+        "opened" | "reopened" | "synchronize" => {
+            let timestamp = event
+                .pull_request
+                .updated_at
+                .and_then(|updated| parse_timestamp(&updated))
+                .unwrap_or(now());
+            for project in projects {
+                let deployment = InsertDeployment {
+                    env: project.env.clone(),
+                    sha: head.sha.clone(),
+                    timestamp,
+                    branch: head.git_ref.clone(),
+                    default_branch: 0, // TODO: abstract this as a bool
+                    project: project.id.clone(),
+                    result: None,
+                };
+                add_deployment(state, deployment, &project).await;
+            }
+            Ok(true)
+        }
+        "closed" => {
+            for project in projects
+                .iter()
+                .filter(|project| project.preview_rules.delete_closed)
+            {
+                state
+                    .db
+                    .delete_branch_deployments(&project.id, &head.git_ref)
+                    .await?;
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+async fn handle_delete(
+    state: &AppState,
+    event: serde_json::Result<DeleteEvent>,
+) -> anyhow::Result<bool> {
+    let event = event?;
+    if event.ref_type != "branch" {
+        return Ok(false);
+    }
+    let projects = get_repo_projects(state, event.repository.id).await?;
+    for project in projects
+        .iter()
+        .filter(|project| project.preview_rules.delete_closed)
+    {
+        state
+            .db
+            .delete_branch_deployments(&project.id, &event.git_ref)
+            .await?;
+    }
+    Ok(true)
+}
+
+async fn get_repo_projects(state: &AppState, repo_id: i64) -> anyhow::Result<Vec<Project>> {
+    let projects = state.db.get_projects().await?;
+    let repo = RepoRef::Github(repo_id);
+    Ok(projects
+        .into_iter()
+        .filter(|project| project.repo == repo)
+        .collect())
+}
+
+async fn add_deployment(state: &AppState, deployment: InsertDeployment, project: &Project) {
+    let provider = state.sources.provider(&project.repo).await;
+    add_deployment_to_db_if_missing(
+        provider.as_ref(),
+        &state.db,
+        deployment,
+        &project.root,
+        &project.name,
+    )
+    .await
+    .ignore_logging();
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let datetime = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some(datetime.timestamp_millis())
+}
+
+/// GitHub sends the signature as `sha256=<hex encoded hmac of the body>`
+fn is_signature_valid(secret: &[u8], body: &[u8], signature: Option<&str>) -> bool {
+    let Some(tag) = signature
+        .and_then(|signature| signature.strip_prefix("sha256="))
+        .and_then(decode_hex)
+    else {
+        return false;
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hmac::verify(&key, body, &tag).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod webhooks_tests {
+    use super::is_signature_valid;
+
+    #[test]
+    fn test_signature_validation() {
+        // example taken from the GitHub docs on validating webhook deliveries
+        let secret = b"It's a Secret to Everybody";
+        let body = b"Hello, World!";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(is_signature_valid(secret, body, Some(signature)));
+        assert!(!is_signature_valid(
+            secret,
+            b"Hello, World?",
+            Some(signature)
+        ));
+        assert!(!is_signature_valid(secret, body, Some("sha256=75")));
+        assert!(!is_signature_valid(secret, body, None));
+    }
+}
@@ -1,17 +1,23 @@
 use actix_web::web::{Data, ServiceConfig};
-use endpoints::{apps, deployments, system, version};
+use endpoints::{apps, backups, crons, deployments, system, version, webhooks};
 use octocrab::models::Repository as CrabRepository;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::{OpenApi, ToSchema};
 
 use crate::{
+    backups::Backup,
     db::{
-        BuildResult, Db, DeploymentWithProject, EditedEnvVar, EnvVar, InsertProject, UpdateProject,
+        Attempt, BuildResult, CronRun, Db, DeploymentWithProject, EditedEnvVar, EnvVar,
+        InsertProject, PreviewRules, UpdateProject,
+    },
+    deployments::{
+        config::{AlwaysOn, IdleTimeout, Scaling},
+        deployment::Deployment,
+        manager::Manager,
     },
-    deployments::{deployment::Deployment, manager::Manager},
     docker::get_image,
-    github::Github,
     logging::{Level, Log},
+    source::Sources,
     sqlite_db::DbAccess,
     utils::PlusHttps,
 };
@@ -38,13 +44,27 @@ pub(crate) const API_PORT: u16 = 5045;
         apps::get_env,
         apps::upsert_env,
         apps::delete_env,
+        apps::rotate_db_keys,
+        apps::promote_deployment,
+        apps::rollback_prod,
+        apps::unpin_prod,
+        backups::get_backups,
+        backups::create_project_backup,
+        backups::download_backup,
+        backups::restore_project_backup,
+        crons::get_cron_runs,
+        crons::run_cron_job,
         deployments::redeploy,
         deployments::delete_deployment,
         deployments::sync,
         deployments::get_deployment_logs,
-        deployments::get_deployment_build_logs
+        deployments::get_deployment_build_logs,
+        deployments::get_deployment_attempts,
+        deployments::get_deployment_volumes,
+        deployments::reset_deployment_volume,
+        webhooks::github_webhook
     ),
-    components(schemas(ProjectInfo, FullProjectInfo, ErrorResponse, UpdateProject, Repository, ApiDeployment, Log, Level, Status, InsertProject, LibsqlDb, EnvVar, EditedEnvVar, Certificate)),
+    components(schemas(ProjectInfo, FullProjectInfo, ErrorResponse, UpdateProject, Repository, ApiDeployment, ApiAttempt, ApiVolume, ApiCronRun, Log, Level, Status, InsertProject, LibsqlDb, EnvVar, EditedEnvVar, Certificate, Backup, RotateKeys, PreviewRules, Scaling, IdleTimeout, AlwaysOn)),
     tags(
         (name = "prezel", description = "Prezel management endpoints.")
     ),
@@ -67,21 +87,35 @@ fn configure_service(store: Data<AppState>) -> impl FnOnce(&mut ServiceConfig) {
             .service(apps::get_env)
             .service(apps::upsert_env)
             .service(apps::delete_env)
+            .service(apps::rotate_db_keys)
+            .service(apps::promote_deployment)
+            .service(apps::rollback_prod)
+            .service(apps::unpin_prod)
+            .service(backups::get_backups)
+            .service(backups::create_project_backup)
+            .service(backups::download_backup)
+            .service(backups::restore_project_backup)
+            .service(crons::get_cron_runs)
+            .service(crons::run_cron_job)
             .service(deployments::redeploy)
             .service(deployments::delete_deployment)
             .service(deployments::sync)
             .service(deployments::get_deployment_logs)
-            .service(deployments::get_deployment_build_logs);
+            .service(deployments::get_deployment_build_logs)
+            .service(deployments::get_deployment_attempts)
+            .service(deployments::get_deployment_volumes)
+            .service(deployments::reset_deployment_volume)
+            .service(webhooks::github_webhook);
         // If I add anything here also need to add it in api/mod.rs
     }
 }
 
-// TODO: there is some duplication here, because manager holds db and github as well
+// TODO: there is some duplication here, because manager holds db and sources as well
 #[derive(Clone, Debug)]
 pub(crate) struct AppState {
     pub(crate) db: Db,
     pub(crate) manager: Manager,
-    pub(crate) github: Github,
+    pub(crate) sources: Sources,
     pub(crate) secret: Vec<u8>,
 }
 
@@ -103,6 +137,9 @@ pub(crate) enum Status {
     Building,
     Ready,
     Failed,
+    Superseded,
+    /// ready but failing its latest health check
+    Unhealthy,
 }
 
 impl ToString for Status {
@@ -114,6 +151,8 @@ impl ToString for Status {
             Self::StandBy => "stand by",
             Self::Ready => "ready",
             Self::Failed => "failed",
+            Self::Superseded => "superseded",
+            Self::Unhealthy => "unhealthy",
         };
         string.to_owned()
     }
@@ -147,6 +186,81 @@ struct ApiDeployment {
     build_finished: Option<i64>,
 }
 
+/// A single try to build or start a deployment
+#[derive(Serialize, ToSchema)]
+#[schema(title = "Attempt")]
+struct ApiAttempt {
+    /// starting from 1, increased on every rebuild of the deployment
+    build: i64,
+    /// either build or start
+    phase: String,
+    /// starting from 1, retries have higher numbers
+    number: i64,
+    started: i64,
+    finished: i64,
+    /// missing if the attempt succeeded
+    error: Option<String>,
+    /// transient errors are retried, up to 3 attempts
+    transient: bool,
+}
+
+impl From<Attempt> for ApiAttempt {
+    fn from(value: Attempt) -> Self {
+        Self {
+            build: value.build,
+            phase: value.phase,
+            number: value.number,
+            started: value.started,
+            finished: value.finished,
+            error: value.error,
+            transient: value.transient != 0,
+        }
+    }
+}
+
+/// A single run of a cron job
+#[derive(Serialize, ToSchema)]
+#[schema(title = "CronRun")]
+struct ApiCronRun {
+    id: i64,
+    job: String,
+    /// triggered through the API instead of by its schedule
+    manual: bool,
+    started: i64,
+    finished: i64,
+    /// exit code for commands and http status for paths, missing if the job could not run
+    status: Option<i64>,
+    success: bool,
+    /// output of the command or body of the response, only the end of long ones is kept
+    logs: String,
+    deployment: String,
+}
+
+impl From<CronRun> for ApiCronRun {
+    fn from(value: CronRun) -> Self {
+        Self {
+            id: value.id,
+            job: value.job,
+            manual: value.manual != 0,
+            started: value.started,
+            finished: value.finished,
+            status: value.status,
+            success: value.success != 0,
+            logs: value.logs,
+            deployment: value.deployment,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct ApiVolume {
+    name: String,
+    /// mount path inside the app container
+    path: String,
+    /// in bytes
+    size: u64,
+}
+
 // TODO: move this somewhere else
 impl ApiDeployment {
     // TODO: make info an option so deployments can show up in the API before the manager reads them
@@ -162,7 +276,10 @@ impl ApiDeployment {
         let (status, url, prod_url, custom_urls, app_container, image_size, libsql_db) =
             if let Some(deployment) = deployment {
                 let container_status = deployment.app_container.status.read().await.clone();
-                let status = container_status.to_status();
+                let status = match container_status.to_status() {
+                    Status::Ready if deployment.app_container.is_unhealthy() => Status::Unhealthy,
+                    status => status,
+                };
                 let image_name = container_status.get_image_name();
                 let image_size = if let Some(name) = image_name {
                     get_image(name).await.and_then(|image| image.size)
@@ -194,7 +311,7 @@ impl ApiDeployment {
                         token: setup.auth.generate_expiring_token(access),
                     })
                 } else {
-                    let branch_db = container_status.get_db_setup();
+                    let branch_db = container_status.get_db_setup().libsql;
                     branch_db.map(|setup| LibsqlDb {
                         url: db_url,
                         token: setup.auth.generate_expiring_token(access),
@@ -214,6 +331,7 @@ impl ApiDeployment {
                 let status = match db_deployment.result {
                     Some(BuildResult::Failed) => Status::Failed,
                     Some(BuildResult::Built) => Status::Built,
+                    Some(BuildResult::Superseded) => Status::Superseded,
                     None => Status::Queued,
                 };
                 (status, None, None, vec![], None, None, None)
@@ -266,9 +384,13 @@ impl From<CrabRepository> for Repository {
 struct ProjectInfo {
     name: String,
     id: String,
-    repo: i64,
+    /// provider qualified repository, e.g. `github:<repo id>`
+    repo: String,
     created: i64,
     custom_domains: Vec<String>,
+    backup_retention: i64,
+    preview_rules: PreviewRules,
+    scaling: Scaling,
     prod_deployment_id: Option<String>,
     prod_deployment: Option<ApiDeployment>,
 }
@@ -277,15 +399,25 @@ struct ProjectInfo {
 struct FullProjectInfo {
     name: String,
     id: String,
-    repo: i64,
+    /// provider qualified repository, e.g. `github:<repo id>`
+    repo: String,
     created: i64,
     custom_domains: Vec<String>,
+    backup_retention: i64,
+    preview_rules: PreviewRules,
+    scaling: Scaling,
     prod_deployment_id: Option<String>,
     prod_deployment: Option<ApiDeployment>,
     /// All project deployments sorted by created datetime descending
     deployments: Vec<ApiDeployment>,
 }
 
+#[derive(Deserialize, Debug, ToSchema)]
+struct RotateKeys {
+    /// stop accepting the previous keys straight away instead of after a grace period
+    revoke: bool,
+}
+
 #[derive(Serialize, ToSchema)]
 struct Certificate {
     domain: String,
@@ -16,7 +16,7 @@ use crate::{
     api::{configure_service, AppState, API_PORT},
     db::Db,
     deployments::manager::Manager,
-    github::Github,
+    source::Sources,
 };
 
 use super::ApiDoc;
@@ -38,14 +38,14 @@ pub(crate) fn get_open_api() -> OpenApiStruct {
 pub(crate) async fn run_api_server(
     manager: Manager,
     db: Db,
-    github: Github,
+    sources: Sources,
     api_hostname: &str,
     secret: Vec<u8>,
 ) -> Result<(), impl Error> {
     let state = AppState {
         db,
         manager: manager.clone(),
-        github,
+        sources,
         secret,
     };
 
@@ -0,0 +1,134 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::Serialize;
+use tar::{Archive, Builder};
+use utoipa::ToSchema;
+
+use crate::{db::nano_id::NanoId, paths::get_backups_dir, sqlite_db::SqliteDbSetup, utils::now};
+
+const EXTENSION: &str = ".tar.gz";
+
+#[derive(Serialize, ToSchema, Debug)]
+pub(crate) struct Backup {
+    pub(crate) id: String,
+    pub(crate) created: i64,
+    pub(crate) size: u64,
+}
+
+/// The sqld container is paused while the folder is archived,
+/// so the snapshot is as consistent as if the process had crashed at that point
+#[tracing::instrument]
+pub(crate) async fn create_backup(project: &NanoId, db: &SqliteDbSetup) -> anyhow::Result<Backup> {
+    let created = now();
+    let path = get_backups_dir(project.as_str()).join(format!("{created}{EXTENSION}"));
+    let tmp_path = path.with_extension("tmp");
+    let folder = db.folder.clone();
+    let tmp_path_clone = tmp_path.clone();
+    db.container
+        .with_paused(tokio::task::spawn_blocking(move || {
+            archive_folder(&folder, &tmp_path_clone)
+        }))
+        .await???;
+    tokio::fs::rename(&tmp_path, &path).await?;
+    let size = tokio::fs::metadata(&path).await?.len();
+    Ok(Backup {
+        id: created.to_string(),
+        created,
+        size,
+    })
+}
+
+/// Returns the backups for the project sorted from newest to oldest
+#[tracing::instrument]
+pub(crate) async fn list_backups(project: &NanoId) -> anyhow::Result<Vec<Backup>> {
+    let mut entries = tokio::fs::read_dir(get_backups_dir(project.as_str())).await?;
+    let mut backups = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let created = file_name
+            .strip_suffix(EXTENSION)
+            .and_then(|id| id.parse::<i64>().ok());
+        if let Some(created) = created {
+            backups.push(Backup {
+                id: created.to_string(),
+                created,
+                size: entry.metadata().await?.len(),
+            });
+        }
+    }
+    backups.sort_by_key(|backup| -backup.created);
+    Ok(backups)
+}
+
+pub(crate) fn get_backup_path(project: &NanoId, backup: &str) -> Option<PathBuf> {
+    // parsing the id makes sure it cannot be used to point outside the backups folder
+    let created: i64 = backup.parse().ok()?;
+    let path = get_backups_dir(project.as_str()).join(format!("{created}{EXTENSION}"));
+    path.exists().then_some(path)
+}
+
+/// The backup is extracted next to the db folder and only swapped in once the sqld container
+/// has been removed, so the next access starts it again on top of the restored data.
+/// The current state is saved as a new backup right before the swap, so a restore can always
+/// be undone. This happens after the extraction, as pruning to make room for the new backup
+/// could otherwise remove the one being restored
+#[tracing::instrument]
+pub(crate) async fn restore_backup(
+    project: &NanoId,
+    backup: &str,
+    db: &SqliteDbSetup,
+) -> anyhow::Result<()> {
+    let path = get_backup_path(project, backup).ok_or(anyhow!("backup {backup} not found"))?;
+    let restore_folder = db.folder.with_extension("restore");
+    let old_folder = db.folder.with_extension("old");
+    let _ = tokio::fs::remove_dir_all(&restore_folder).await;
+    let restore_folder_clone = restore_folder.clone();
+    tokio::task::spawn_blocking(move || extract_archive(&path, &restore_folder_clone)).await??;
+    create_backup(project, db).await?;
+
+    db.container
+        .with_stopped(async {
+            let _ = tokio::fs::remove_dir_all(&old_folder).await;
+            tokio::fs::rename(&db.folder, &old_folder).await?;
+            if let Err(error) = tokio::fs::rename(&restore_folder, &db.folder).await {
+                // the db is never left without a folder, the previous one is put back instead
+                tokio::fs::rename(&old_folder, &db.folder).await?;
+                return Err(error.into());
+            }
+            tokio::fs::remove_dir_all(&old_folder).await?;
+            anyhow::Ok(())
+        })
+        .await??;
+    Ok(())
+}
+
+/// Removes all backups but the newest `retention` ones
+#[tracing::instrument]
+pub(crate) async fn prune_backups(project: &NanoId, retention: usize) -> anyhow::Result<()> {
+    for backup in list_backups(project).await?.into_iter().skip(retention) {
+        if let Some(path) = get_backup_path(project, &backup.id) {
+            tokio::fs::remove_file(path).await?;
+        }
+    }
+    Ok(())
+}
+
+fn archive_folder(folder: &Path, output: &Path) -> anyhow::Result<()> {
+    let encoder = GzEncoder::new(File::create(output)?, Compression::default());
+    let mut builder = Builder::new(encoder);
+    builder.append_dir_all(".", folder)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn extract_archive(archive: &Path, folder: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(folder)?;
+    let mut archive = Archive::new(GzDecoder::new(File::open(archive)?));
+    archive.unpack(folder)?;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use std::{
+    fs::File,
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use tracing::info;
+use walkdir::WalkDir;
+
+/// How the files of a prod database folder are cloned into a branch folder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BranchStrategy {
+    /// copy-on-write clone of every file, only supported by some filesystems like btrfs or xfs
+    Reflink,
+    /// files that are never modified in place are shared through hardlinks,
+    /// while the rest (database files and WAL) are copied
+    Hardlink,
+    /// byte by byte copy of every file
+    Copy,
+}
+
+impl BranchStrategy {
+    /// Picks the cheapest strategy supported for branching from `from` into `to`
+    fn detect(from: &Path, to: &Path) -> Self {
+        let same_device = match (from.metadata(), to.metadata()) {
+            (Ok(from), Ok(to)) => from.dev() == to.dev(),
+            _ => false,
+        };
+        if !same_device {
+            Self::Copy
+        } else if supports_reflink(to) {
+            Self::Reflink
+        } else {
+            Self::Hardlink
+        }
+    }
+
+    fn clone_file(&self, from: &Path, to: &Path, immutable: bool) -> anyhow::Result<()> {
+        let result = match self {
+            Self::Reflink => reflink(from, to),
+            Self::Hardlink if immutable => std::fs::hard_link(from, to).map_err(Into::into),
+            Self::Hardlink | Self::Copy => copy(from, to),
+        };
+        // files that cannot be cloned for whatever reason are simply copied
+        if result.is_err() && *self != Self::Copy {
+            let _ = std::fs::remove_file(to);
+            copy(from, to)
+        } else {
+            result
+        }
+    }
+}
+
+/// A branch folder is only created once, later calls are a no-op
+/// `immutable` tells which files are never modified in place and can therefore be hardlinked
+#[tracing::instrument(skip(immutable))]
+pub(crate) async fn create_branch(
+    from: &Path,
+    to: &Path,
+    immutable: fn(&Path) -> bool,
+) -> anyhow::Result<bool> {
+    let marker = get_marker_path(to);
+    if marker.exists() {
+        return Ok(false);
+    }
+    let (from, to) = (from.to_owned(), to.to_owned());
+    let strategy = tokio::task::spawn_blocking(move || {
+        let strategy = BranchStrategy::detect(&from, &to);
+        branch_folder(&from, &to, strategy, immutable)?;
+        anyhow::Ok(strategy)
+    })
+    .await??;
+    info!("branch created using {strategy:?} strategy");
+    tokio::fs::write(marker, []).await?;
+    Ok(true)
+}
+
+/// Empties the branch folder and removes its marker, so the next call to `create_branch` clones it again
+#[tracing::instrument]
+pub(crate) async fn remove_branch(to: &Path) -> anyhow::Result<()> {
+    let marker = get_marker_path(to);
+    if marker.exists() {
+        tokio::fs::remove_file(marker).await?;
+    }
+    if to.exists() {
+        tokio::fs::remove_dir_all(to).await?;
+    }
+    tokio::fs::create_dir_all(to).await?;
+    Ok(())
+}
+
+/// the marker lives outside the folder so it is never visible to the db container
+fn get_marker_path(folder: &Path) -> PathBuf {
+    folder.with_extension("branched")
+}
+
+fn branch_folder(
+    from: &Path,
+    to: &Path,
+    strategy: BranchStrategy,
+    immutable: fn(&Path) -> bool,
+) -> anyhow::Result<()> {
+    for entry_result in WalkDir::new(from) {
+        let entry = entry_result?;
+        let relative = entry.path().strip_prefix(from)?;
+        let new_path = to.join(relative);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&new_path)?;
+        } else if entry.file_type().is_file() {
+            // leftovers from a previous attempt would make reflinks and hardlinks fail
+            let _ = std::fs::remove_file(&new_path);
+            strategy
+                .clone_file(entry.path(), &new_path, immutable(relative))
+                .map_err(|e| {
+                    anyhow!(
+                        "error when trying to copy file from {:?} to {new_path:?}: {e}",
+                        entry.path(),
+                    )
+                })?;
+        } else if entry.file_type().is_symlink() {
+            let points_to = std::fs::read_link(entry.path())?;
+            if points_to.is_relative() {
+                let as_absolute = entry.path().join(&points_to);
+                let inside_folder = as_absolute.strip_prefix(from);
+                if inside_folder.is_err() {
+                    bail!("trying to copy folder that contains relative link pointing outside the folder")
+                }
+                let _ = std::fs::remove_file(&new_path);
+                std::os::unix::fs::symlink(points_to, new_path)?;
+            } else {
+                bail!("trying to copy folder that contains absolute links")
+            }
+        } else {
+            bail!(
+                "trying to copy folder that contains unsupported file type {:?}",
+                entry.file_type()
+            )
+        }
+    }
+    Ok(())
+}
+
+fn supports_reflink(folder: &Path) -> bool {
+    let probe = folder.join(".reflink-probe");
+    let probe_clone = folder.join(".reflink-probe-clone");
+    let supported =
+        std::fs::write(&probe, b"probe").is_ok() && reflink(&probe, &probe_clone).is_ok();
+    let _ = std::fs::remove_file(probe);
+    let _ = std::fs::remove_file(probe_clone);
+    supported
+}
+
+fn reflink(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let source = File::open(from)?;
+    let target = File::create(to)?;
+    // SAFETY: both file descriptors are valid for the duration of the call
+    let result = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().into())
+    }
+}
+
+fn copy(from: &Path, to: &Path) -> anyhow::Result<()> {
+    std::fs::copy(from, to)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod branching_tests {
+    use std::path::Path;
+
+    use tempfile::TempDir;
+
+    use super::create_branch;
+
+    fn never(_: &Path) -> bool {
+        false
+    }
+
+    #[tokio::test]
+    async fn test_branch_is_created_only_once() {
+        let root = TempDir::new().unwrap();
+        let from = root.path().join("prod");
+        let to = root.path().join("branch");
+        std::fs::create_dir_all(from.join("nested")).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        std::fs::write(from.join("nested").join("data"), b"prod").unwrap();
+
+        assert!(create_branch(&from, &to, never).await.unwrap());
+        assert_eq!(
+            std::fs::read(to.join("nested").join("data")).unwrap(),
+            b"prod"
+        );
+
+        std::fs::write(to.join("nested").join("data"), b"branch").unwrap();
+        assert_eq!(
+            std::fs::read(from.join("nested").join("data")).unwrap(),
+            b"prod"
+        );
+        assert!(!create_branch(&from, &to, never).await.unwrap());
+        assert_eq!(
+            std::fs::read(to.join("nested").join("data")).unwrap(),
+            b"branch"
+        );
+    }
+}
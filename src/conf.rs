@@ -1,8 +1,24 @@
 use base64::{engine::general_purpose::STANDARD, Engine};
 use serde::Deserialize;
-use std::{fs, io};
+use std::{collections::HashMap, fs, io};
 
-use crate::paths::get_config_path;
+use crate::{
+    deployments::config::{BuildLimits, ResourceLimits},
+    paths::get_config_path,
+};
+
+const DEFAULT_BUILD_CONCURRENCY: usize = 2;
+
+/// How traffic moves from the current prod deployment of a project to a new one
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Cutover {
+    /// routing switches as soon as the new deployment is built
+    Immediate,
+    /// routing switches once the new deployment is started and healthy
+    #[default]
+    BlueGreen,
+}
 
 #[derive(Clone, Debug)]
 pub(crate) struct Conf {
@@ -10,6 +26,17 @@ pub(crate) struct Conf {
     pub(crate) provider: String,
     pub(crate) encoded_secret: String,
     pub(crate) secret: Vec<u8>,
+    /// secret used by GitHub to sign webhook deliveries, webhooks are disabled if missing
+    pub(crate) webhook_secret: Option<String>,
+    /// access tokens for GitLab and Gitea instances, keyed by host
+    pub(crate) source_tokens: HashMap<String, String>,
+    /// max number of images being built at the same time
+    pub(crate) build_concurrency: usize,
+    /// defaults for projects not setting their own build limits in prezel.json
+    pub(crate) build_limits: BuildLimits,
+    /// defaults for the app and libsql containers of projects not setting their own in prezel.json
+    pub(crate) resources: ResourceLimits,
+    pub(crate) prod_cutover: Cutover,
 }
 
 #[derive(Deserialize)]
@@ -17,6 +44,16 @@ struct StoredConf {
     pub(crate) hostname: String,
     pub(crate) provider: String,
     pub(crate) secret: String,
+    pub(crate) webhook_secret: Option<String>,
+    #[serde(default)]
+    pub(crate) source_tokens: HashMap<String, String>,
+    pub(crate) build_concurrency: Option<usize>,
+    #[serde(default)]
+    pub(crate) build_limits: BuildLimits,
+    #[serde(default)]
+    pub(crate) resources: ResourceLimits,
+    #[serde(default)]
+    pub(crate) prod_cutover: Cutover,
 }
 
 impl Conf {
@@ -41,6 +78,15 @@ impl Conf {
             secret: STANDARD
                 .decode(stored.secret)
                 .expect("invalid base64 encoding for secret"),
+            webhook_secret: stored.webhook_secret,
+            source_tokens: stored.source_tokens,
+            build_concurrency: stored
+                .build_concurrency
+                .unwrap_or(DEFAULT_BUILD_CONCURRENCY)
+                .max(1),
+            build_limits: stored.build_limits,
+            resources: stored.resources,
+            prod_cutover: stored.prod_cutover,
         }
     }
 
@@ -7,27 +7,36 @@ use std::{
 use tempfile::TempDir;
 
 use crate::{
+    conf::Conf,
     db::nano_id::NanoId,
     deployments::config::DeploymentConfig,
     docker::{get_managed_image_id, ImageName},
     env::EnvVars,
-    github::Github,
     hooks::StatusHooks,
     nixpacks::create_docker_image_with_nixpacks,
-    sqlite_db::{BranchSqliteDb, ProdSqliteDb, SqliteDbSetup},
+    postgres_db::{BranchPostgresDb, ProdPostgresDb},
+    redis_db::{BranchRedisDb, ProdRedisDb},
+    s3_storage::{BranchS3Storage, ProdS3Storage, REGION},
+    source::{RepoRef, Sources},
+    sqlite_db::{BranchSqliteDb, ProdSqliteDb},
+    volumes::Volume,
 };
 
 use super::{
-    build_dockerfile, BuildResult, Container, ContainerConfig, ContainerSetup, ContainerStatus,
-    DeploymentHooks, WorkerHandle,
+    build_dockerfile, BuildResult, BuildTimeout, Container, ContainerConfig, ContainerSetup,
+    ContainerStatus, DbSetup, DeploymentHooks, Probe, Release, WorkerHandle,
 };
 
 #[derive(Clone, Debug)]
 pub(crate) struct CommitContainer {
-    github: Github,
+    sources: Sources,
     deployment: NanoId,
     branch_db: Option<BranchSqliteDb>,
-    pub(crate) repo_id: i64,
+    branch_postgres: Option<BranchPostgresDb>,
+    branch_s3: Option<BranchS3Storage>,
+    branch_redis: Option<BranchRedisDb>,
+    volumes: Vec<Volume>,
+    pub(crate) repo: RepoRef,
     pub(crate) sha: String,
     env: EnvVars,
     root: String,
@@ -39,9 +48,10 @@ impl CommitContainer {
     pub(crate) fn new(
         build_queue: WorkerHandle,
         hooks: StatusHooks,
-        github: Github,
-        repo_id: i64,
+        sources: Sources,
+        repo: RepoRef,
         sha: String,
+        project: &NanoId,
         deployment: NanoId,
         env: EnvVars, // TODO: this is duplicated in ContainerConfig...
         root: String,
@@ -49,37 +59,81 @@ impl CommitContainer {
         public: bool, // TODO: should not this be in ContainerConfig
         prod_db: &ProdSqliteDb,
         db_url: &str,
+        prod_postgres: &ProdPostgresDb,
+        postgres_url: &str,
+        prod_s3: &ProdS3Storage,
+        s3_endpoint: &str,
+        // only present if the app asks for redis in prezel.json
+        redis: Option<(&ProdRedisDb, &str)>,
         // cloned_db_file: Option<HostFile>,
         initial_status: ContainerStatus,
         result: Option<BuildResult>,
         config: DeploymentConfig,
     ) -> Container {
         let (branch_db, token) = if branch {
-            let branch_db = prod_db.branch(&deployment);
+            let branch_db = prod_db.branch(&deployment, config.get_resources());
             let token = branch_db.auth.get_permanent_token().to_owned();
             (Some(branch_db), token)
         } else {
             (None, prod_db.setup.auth.get_permanent_token().to_owned())
         };
+        let branch_postgres = branch.then(|| prod_postgres.branch(&deployment));
+        let branch_s3 = branch.then(|| prod_s3.branch(&deployment));
+        let branch_redis = redis
+            .filter(|_| branch)
+            .map(|(prod_redis, _)| prod_redis.branch(&deployment));
+        let volumes: Vec<_> = config
+            .get_volumes()
+            .iter()
+            .map(|(name, target)| Volume::new(project, &deployment, branch, name, target))
+            .collect();
+        let s3_access_key = prod_s3.setup.get_access_key();
+        let s3_secret_key = prod_s3.setup.get_secret_key();
+        let port = config.get_port();
+        let port_env = port.to_string();
         let default_env = [
             ("PREZEL_DB_URL", db_url),
-            ("PREZEL_DB_AUTH_TOKEN", &token),
             ("PREZEL_LIBSQL_URL", db_url),
-            ("PREZEL_LIBSQL_AUTH_TOKEN", &token),
             ("ASTRO_DB_REMOTE_URL", db_url),
-            ("ASTRO_DB_APP_TOKEN", &token),
+            ("PREZEL_POSTGRES_URL", postgres_url),
+            ("PREZEL_S3_ENDPOINT", s3_endpoint),
+            ("PREZEL_S3_REGION", REGION),
+            ("PREZEL_S3_ACCESS_KEY_ID", s3_access_key),
+            ("PREZEL_S3_SECRET_ACCESS_KEY", s3_secret_key),
             ("HOST", "0.0.0.0"),
-            ("PORT", "80"),
+            ("PORT", &port_env),
         ]
         .as_ref()
         .into();
-        let extended_env = env + default_env;
+        let redis_env = match redis {
+            Some((_, redis_url)) => [("PREZEL_REDIS_URL", redis_url)].as_ref().into(),
+            None => EnvVars::empty(),
+        };
+        let extended_env = env + default_env + get_libsql_token_env(&token) + redis_env;
 
+        let health_check = config.health_check.clone().unwrap_or_default();
+        let ports = config.get_ports();
+        let resources = config.get_resources();
+        // previews always run a single replica
+        let replicas = if branch {
+            Default::default()
+        } else {
+            config.get_replicas()
+        };
+        let processes = config.get_processes();
+        let release = config.release.clone().map(|command| Release {
+            command,
+            build_limits: config.build_limits.unwrap_or_default(),
+        });
         let builder = Self {
-            github,
+            sources,
             branch_db,
+            branch_postgres,
+            branch_s3,
+            branch_redis,
+            volumes: volumes.clone(),
             deployment: deployment.clone(),
-            repo_id,
+            repo,
             sha,
             env: extended_env.clone(),
             root,
@@ -95,6 +149,15 @@ impl CommitContainer {
                 initial_status,
                 command: None,
                 result,
+                probe: Probe::Http,
+                health_check,
+                port,
+                ports,
+                resources,
+                volumes,
+                replicas,
+                processes,
+                release,
             },
             build_queue,
             Some(deployment),
@@ -103,13 +166,37 @@ impl CommitContainer {
         )
     }
 
-    async fn setup_db(&self) -> anyhow::Result<Option<SqliteDbSetup>> {
-        let db_setup = if let Some(branch_db) = &self.branch_db {
+    /// Volumes of preview deployments are cloned here as well, at the same time as their dbs
+    async fn setup_db(&self) -> anyhow::Result<DbSetup> {
+        for volume in &self.volumes {
+            volume.setup().await?;
+        }
+        let libsql = if let Some(branch_db) = &self.branch_db {
             Some(branch_db.setup().await?)
         } else {
             None
         };
-        Ok(db_setup)
+        let postgres = if let Some(branch_postgres) = &self.branch_postgres {
+            Some(branch_postgres.setup().await?)
+        } else {
+            None
+        };
+        let s3 = if let Some(branch_s3) = &self.branch_s3 {
+            Some(branch_s3.setup().await?)
+        } else {
+            None
+        };
+        let redis = if let Some(branch_redis) = &self.branch_redis {
+            Some(branch_redis.setup().await?)
+        } else {
+            None
+        };
+        Ok(DbSetup {
+            libsql,
+            postgres,
+            s3,
+            redis,
+        })
     }
 
     #[tracing::instrument]
@@ -122,44 +209,58 @@ impl CommitContainer {
             // the same happens with containers
             Ok(image)
         } else {
-            let tempdir = TempDir::new()?;
-            let (path, dockerfile) = self.build_context(tempdir.as_ref()).await?;
-            let image = build_dockerfile(
-                name,
-                &path,
-                dockerfile,
-                self.env.clone(),
-                &mut |chunk| async {
-                    for log in chunk.logs {
-                        hooks
-                            .on_build_log(&String::from_utf8_lossy(&log.msg), false)
-                            .await // FIXME: use time returned by docker in log.timestamp !!!!!!!!!! below as well!!
-                    }
-                    for vertex in chunk.vertexes {
-                        if vertex.completed.is_some() {
-                            if vertex.cached {
-                                let name = vertex.name;
-                                hooks.on_build_log(&format!("CACHED {name}"), false).await;
-                            } else {
-                                hooks.on_build_log(&vertex.name, false).await;
-                            }
-                        }
-                        if !vertex.error.is_empty() {
-                            hooks.on_build_log(&vertex.error, true).await
+            let Conf { build_limits, .. } = Conf::read_async().await;
+            let limits = self.config.get_build_limits(build_limits);
+            let timeout = limits.get_timeout();
+            // dropping the build future on timeout aborts the docker build as well
+            match tokio::time::timeout(timeout, self.build_image(name, hooks)).await {
+                Ok(result) => result,
+                Err(_) => Err(BuildTimeout(timeout).into()),
+            }
+        }
+    }
+
+    async fn build_image(
+        &self,
+        name: ImageName,
+        hooks: &Box<dyn DeploymentHooks>,
+    ) -> anyhow::Result<String> {
+        let tempdir = TempDir::new()?;
+        let (path, dockerfile) = self.build_context(tempdir.as_ref()).await?;
+        let image = build_dockerfile(
+            name,
+            &path,
+            dockerfile,
+            self.env.clone(),
+            &mut |chunk| async {
+                for log in chunk.logs {
+                    hooks
+                        .on_build_log(&String::from_utf8_lossy(&log.msg), false)
+                        .await // FIXME: use time returned by docker in log.timestamp !!!!!!!!!! below as well!!
+                }
+                for vertex in chunk.vertexes {
+                    if vertex.completed.is_some() {
+                        if vertex.cached {
+                            let name = vertex.name;
+                            hooks.on_build_log(&format!("CACHED {name}"), false).await;
+                        } else {
+                            hooks.on_build_log(&vertex.name, false).await;
                         }
                     }
-                },
-            )
-            .await?;
-            Ok(image)
-        }
+                    if !vertex.error.is_empty() {
+                        hooks.on_build_log(&vertex.error, true).await
+                    }
+                }
+            },
+        )
+        .await?;
+        Ok(image)
     }
 
     #[tracing::instrument]
     async fn build_context(&self, path: &Path) -> anyhow::Result<(PathBuf, String)> {
-        self.github
-            .download_commit(self.repo_id, self.sha.clone(), &path)
-            .await?;
+        let provider = self.sources.provider(&self.repo).await;
+        provider.download_commit(&self.sha, path).await?;
         ensure!(path.exists());
 
         let inner_path = path.join(&self.root);
@@ -183,10 +284,21 @@ impl CommitContainer {
     }
 }
 
+/// Env vars with the token for the libsql db, which change every time its keys are rotated
+pub(crate) fn get_libsql_token_env(token: &str) -> EnvVars {
+    [
+        ("PREZEL_DB_AUTH_TOKEN", token),
+        ("PREZEL_LIBSQL_AUTH_TOKEN", token),
+        ("ASTRO_DB_APP_TOKEN", token),
+    ]
+    .as_ref()
+    .into()
+}
+
 impl ContainerSetup for CommitContainer {
     fn setup_db<'a>(
         &'a self,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<Option<SqliteDbSetup>>> + Send + 'a>> {
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<DbSetup>> + Send + 'a>> {
         Box::pin(self.setup_db())
     }
     fn build<'a>(
@@ -1,36 +1,64 @@
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, ensure};
 use async_trait::async_trait;
+use futures::future::join_all;
 use reqwest::Client;
 use std::{
+    collections::HashMap,
     fmt,
     future::Future,
     net::SocketAddrV4,
     ops::Deref,
     path::PathBuf,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
-use tokio::{sync::RwLock, time::sleep};
+use tokio::{
+    net::TcpStream,
+    sync::{Mutex, RwLock},
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
 use tracing::error;
 
+use replicas::{Replica, ReplicaSet};
+use retry::{backoff, is_transient, MAX_ATTEMPTS};
+
 use crate::{
     api::Status,
-    db::{nano_id::NanoId, BuildResult},
-    deployments::worker::WorkerHandle,
+    conf::Conf,
+    db::{nano_id::NanoId, AttemptPhase, BuildResult, InsertAttempt},
+    deployments::{
+        config::{BuildLimits, HealthCheck, Process, Replicas, ResourceLimits},
+        worker::WorkerHandle,
+    },
     docker::{
-        build_dockerfile, create_container, generate_managed_container_name,
-        get_bollard_container_ipv4, get_container_execution_logs, pull_image, run_container,
-        DockerLog,
+        build_dockerfile, create_container, delete_container, generate_managed_container_name,
+        get_bollard_container_ipv4, get_container_execution_logs, get_container_oom_kill,
+        get_image, pause_container, pull_image, run_container, stop_container, unpause_container,
+        DockerLog, LogType,
     },
     env::EnvVars,
     hooks::DeploymentHooks,
-    listener::{Access, Listener},
+    listener::{Access, Listener, Port},
+    postgres_db::PostgresDbSetup,
+    redis_db::RedisDbSetup,
+    s3_storage::S3Setup,
     sqlite_db::SqliteDbSetup,
     utils::now,
+    volumes::Volume,
 };
 
 pub(crate) mod commit;
+pub(crate) mod postgres;
+pub(crate) mod processes;
+pub(crate) mod redis;
+pub(crate) mod replicas;
+pub(crate) mod retry;
+pub(crate) mod s3;
 pub(crate) mod sqld;
 
 #[derive(Debug)]
@@ -41,6 +69,55 @@ pub(crate) struct ContainerConfig {
     pub(crate) command: Option<String>, // TODO: review if I am using this
     pub(crate) initial_status: ContainerStatus,
     pub(crate) result: Option<BuildResult>,
+    pub(crate) probe: Probe,
+    pub(crate) health_check: HealthCheck,
+    /// port probed and receiving requests by default
+    pub(crate) port: u16,
+    /// extra ports reachable through their own hostname
+    pub(crate) ports: HashMap<String, u16>,
+    /// applied on top of the instance defaults in config.json
+    pub(crate) resources: ResourceLimits,
+    pub(crate) volumes: Vec<Volume>,
+    pub(crate) replicas: Replicas,
+    /// worker processes running from the same image, by process name
+    pub(crate) processes: HashMap<String, Process>,
+    /// command run once the image is built, failing the build if it fails
+    pub(crate) release: Option<Release>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Release {
+    pub(crate) command: String,
+    /// build limits of the deployment, as the release command is bounded by the build timeout
+    pub(crate) build_limits: BuildLimits,
+}
+
+/// How to find out if a container is able to receive traffic
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Probe {
+    /// a response to the path of the health check with an accepted status means the container is online
+    Http,
+    /// a successful TCP connection means the container is online, for non HTTP services
+    Tcp,
+}
+
+/// The branch databases of a deployment, populated only once the deployment has been set up
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DbSetup {
+    pub(crate) libsql: Option<SqliteDbSetup>,
+    pub(crate) postgres: Option<PostgresDbSetup>,
+    pub(crate) s3: Option<S3Setup>,
+    pub(crate) redis: Option<RedisDbSetup>,
+}
+
+impl DbSetup {
+    pub(crate) fn containers(&self) -> impl Iterator<Item = Arc<Container>> {
+        let libsql = self.libsql.as_ref().map(|setup| setup.container.clone());
+        let postgres = self.postgres.as_ref().map(|setup| setup.container.clone());
+        let s3 = self.s3.as_ref().map(|setup| setup.container.clone());
+        let redis = self.redis.as_ref().map(|setup| setup.container.clone());
+        libsql.into_iter().chain(postgres).chain(s3).chain(redis)
+    }
 }
 
 // pub(crate) type ContextBuilderOutput =
@@ -48,56 +125,74 @@ pub(crate) struct ContainerConfig {
 // pub(crate) type FileSystemOutput = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
 
 pub(crate) trait ContainerSetup: 'static + Send + Sync + fmt::Debug {
-    fn setup_db<'a>(
-        &'a self,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<Option<SqliteDbSetup>>> + Send + 'a>>;
+    fn setup_db<'a>(&'a self) -> Pin<Box<dyn Future<Output = anyhow::Result<DbSetup>> + Send + 'a>>;
     fn build<'a>(
         &'a self,
         hooks: &'a Box<dyn DeploymentHooks>,
     ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + 'a>>;
 }
 
+/// Error returned by builds not finishing within their timeout
+#[derive(Debug)]
+pub(crate) struct BuildTimeout(pub(crate) Duration);
+
+impl fmt::Display for BuildTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        write!(f, "Build timed out after {seconds} seconds")
+    }
+}
+
+impl std::error::Error for BuildTimeout {}
+
 #[derive(Debug, Clone)]
 pub(crate) enum ContainerStatus {
-    // FIXME ideally I would save the image in the db as well, since anyways there is no guarantee
-    // the image pointed by StandBy {image} exists
-    // I could store the image id in case the build was successful
-    // if there is build_start and build_end but not image id, I can assume the build failed
-    // To be fair, if I detect an image has been removed, I should change the status to be None...
+    // the image and the running replicas are saved in the db as well, so `adopt` can pick them up
+    // after a restart. There is still no guarantee the image pointed by StandBy {image} exists
     /// this means the container was previously built successfully but the image is not known anymore
     Built,
     Queued {
         trigger_access: Option<Instant>,
     },
     Building {
-        db_setup: Option<SqliteDbSetup>,
+        db_setup: DbSetup,
     },
     StandBy {
         image: String,
-        db_setup: Option<SqliteDbSetup>,
+        db_setup: DbSetup,
     },
     Starting {
         image: String,
         container_name: String,
-        db_setup: Option<SqliteDbSetup>,
+        db_setup: DbSetup,
     },
     Ready {
         image: String,
-        container_name: String,
-        db_setup: Option<SqliteDbSetup>,
-        socket: SocketAddrV4,
+        db_setup: DbSetup,
+        replicas: Arc<ReplicaSet>,
         last_access: Arc<RwLock<Instant>>,
     },
     Failed,
+    /// a newer commit landed on the same branch before this one was built
+    Superseded,
 }
 
 impl ContainerStatus {
     #[tracing::instrument]
     fn get_container_name(&self) -> Option<String> {
-        if let Self::Ready { container_name, .. } | Self::Starting { container_name, .. } = self {
-            Some(container_name.clone())
-        } else {
-            None
+        match self {
+            Self::Ready { replicas, .. } => Some(replicas.primary().container_name.clone()),
+            Self::Starting { container_name, .. } => Some(container_name.clone()),
+            _ => None,
+        }
+    }
+
+    #[tracing::instrument]
+    fn get_container_names(&self) -> Vec<String> {
+        match self {
+            Self::Ready { replicas, .. } => replicas.get_container_names(),
+            Self::Starting { container_name, .. } => vec![container_name.clone()],
+            _ => vec![],
         }
     }
 
@@ -122,19 +217,20 @@ impl ContainerStatus {
             Self::Queued { .. } => Status::Queued,
             Self::Ready { .. } => Status::Ready,
             Self::Failed => Status::Failed,
+            Self::Superseded => Status::Superseded,
         }
     }
 
-    // TODO: create get_db_container alternative and use it in all the appropriate places
-    // or maybe simply implement to_container for Option<SqliteDbSetup>
     #[tracing::instrument]
-    pub(crate) fn get_db_setup(&self) -> Option<SqliteDbSetup> {
+    pub(crate) fn get_db_setup(&self) -> DbSetup {
         match self {
             Self::Building { db_setup }
             | Self::StandBy { db_setup, .. }
             | Self::Ready { db_setup, .. }
             | Self::Starting { db_setup, .. } => db_setup.clone(),
-            Self::Queued { .. } | Self::Built | Self::Failed => None,
+            Self::Queued { .. } | Self::Built | Self::Failed | Self::Superseded => {
+                Default::default()
+            }
         }
     }
 }
@@ -157,6 +253,16 @@ pub(crate) struct Container {
     pub(crate) logging_deployment_id: Option<NanoId>,
     pub(crate) public: bool,
     build_queue: WorkerHandle,
+    superseded: CancellationToken,
+    /// consecutive failed health checks while Ready
+    failed_health_checks: AtomicU32,
+    last_health_check: Mutex<Instant>,
+    /// times at which previous docker containers were killed for running out of memory
+    oom_kills: Mutex<Vec<i64>>,
+    /// docker container of every worker process started, by process name
+    processes: Mutex<HashMap<String, String>>,
+    /// env vars changed after creation, like rotated db tokens, taking precedence over the config
+    env_overrides: RwLock<EnvVars>,
 }
 
 impl Container {
@@ -179,6 +285,12 @@ impl Container {
             logging_deployment_id,
             public,
             build_queue,
+            superseded: CancellationToken::new(),
+            failed_health_checks: AtomicU32::new(0),
+            last_health_check: Mutex::new(Instant::now()),
+            oom_kills: Default::default(),
+            processes: Default::default(),
+            env_overrides: Default::default(),
         }
     }
 
@@ -188,13 +300,30 @@ impl Container {
         self.status.read().await.get_container_name()
     }
 
+    /// Names of all the docker containers in use, one per replica and worker process
+    #[tracing::instrument]
+    pub(crate) async fn get_container_names(&self) -> Vec<String> {
+        let mut names = self.status.read().await.get_container_names();
+        names.extend(self.get_process_container_names().await);
+        names
+    }
+
     #[tracing::instrument]
     pub(crate) async fn get_logs(&self) -> Box<dyn Iterator<Item = DockerLog>> {
-        if let Some(container) = self.get_container_name().await {
-            Box::new(get_container_execution_logs(&container).await)
-        } else {
-            Box::new(std::iter::empty())
-        }
+        let mut oom_kills = self.oom_kills.lock().await.clone();
+        let logs: Box<dyn Iterator<Item = DockerLog>> =
+            if let Some(container) = self.get_container_name().await {
+                oom_kills.extend(get_container_oom_kill(&container).await);
+                Box::new(get_container_execution_logs(&container).await)
+            } else {
+                Box::new(std::iter::empty())
+            };
+        let oom_logs = oom_kills.into_iter().map(|time| DockerLog {
+            time,
+            message: "Container killed for running out of memory".to_owned(),
+            log_type: LogType::Err,
+        });
+        Box::new(logs.chain(oom_logs))
     }
 
     /// this function runs no sanity checks on the current status before setting the new one
@@ -205,7 +334,239 @@ impl Container {
         };
     }
 
-    // FIXME: this i pointless now, just a thin wrapper
+    /// Moves the container from Queued to Building in one go, so only the caller getting true
+    /// is allowed to build it and the same container is never built twice at the same time
+    #[tracing::instrument]
+    pub(crate) async fn claim_build(&self) -> bool {
+        let mut status = self.status.write().await;
+        if let ContainerStatus::Queued { .. } = status.deref() {
+            *status = ContainerStatus::Building {
+                db_setup: Default::default(),
+            };
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Queued containers are not built anymore, and the build is cancelled for those being built
+    #[tracing::instrument]
+    pub(crate) async fn supersede(&self) {
+        let mut status = self.status.write().await;
+        match status.deref() {
+            ContainerStatus::Queued { .. } => {
+                *status = ContainerStatus::Superseded;
+                drop(status);
+                *self.result.write().await = Some(BuildResult::Superseded);
+                self.hooks.on_build_superseded().await;
+            }
+            ContainerStatus::Building { .. } => self.superseded.cancel(),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn get_volumes(&self) -> &[Volume] {
+        &self.config.volumes
+    }
+
+    /// Socket for the given port, on the same ip as the main socket of the container
+    fn get_port_socket(&self, socket: SocketAddrV4, port: &Port) -> anyhow::Result<SocketAddrV4> {
+        match port {
+            Port::Http => Ok(socket),
+            Port::Named(name) => {
+                let port = self
+                    .config
+                    .ports
+                    .get(name)
+                    .ok_or(anyhow!("container has no port named {name}"))?;
+                Ok(SocketAddrV4::new(*socket.ip(), *port))
+            }
+        }
+    }
+
+    /// Ready containers failing their latest health check are considered unhealthy
+    pub(crate) fn is_unhealthy(&self) -> bool {
+        self.failed_health_checks.load(Ordering::Relaxed) > 0
+    }
+
+    /// Checks the health of Ready containers whose check interval is over.
+    /// Returns true once they fail too many checks in a row, meaning they need a restart
+    #[tracing::instrument]
+    pub(crate) async fn check_health(&self) -> bool {
+        let replicas = match self.status.read().await.deref() {
+            ContainerStatus::Ready { replicas, .. } => replicas.clone(),
+            _ => return false,
+        };
+        let mut last_check = self.last_health_check.lock().await;
+        if last_check.elapsed() < self.config.health_check.get_interval() {
+            return false;
+        }
+        *last_check = Instant::now();
+        // the extra replicas failing too many checks are simply replaced by new ones
+        let extra_replicas = replicas.serving().into_iter().skip(1);
+        join_all(extra_replicas.map(|replica| self.check_replica_health(&replicas, replica))).await;
+        let socket = replicas.primary().socket;
+        if is_online(&socket, self.config.probe, &self.config.health_check).await {
+            self.failed_health_checks.store(0, Ordering::Relaxed);
+            return false;
+        }
+        let failures = self.failed_health_checks.fetch_add(1, Ordering::Relaxed) + 1;
+        let unhealthy = failures >= self.config.health_check.get_failure_threshold();
+        if unhealthy {
+            error!("container at {socket} failed {failures} health checks, restarting it");
+        }
+        unhealthy
+    }
+
+    /// Stops the container and starts it again from its image
+    #[tracing::instrument]
+    pub(crate) async fn restart(&self) -> anyhow::Result<()> {
+        self.with_stopped(async {}).await?;
+        self.start().await?;
+        Ok(())
+    }
+
+    async fn check_replica_health(&self, replicas: &ReplicaSet, replica: Arc<Replica>) {
+        let socket = replica.socket;
+        if is_online(&socket, self.config.probe, &self.config.health_check).await {
+            replica.failed_health_checks.store(0, Ordering::Relaxed);
+            return;
+        }
+        let failures = replica.failed_health_checks.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.config.health_check.get_failure_threshold() {
+            error!("replica at {socket} failed {failures} health checks, replacing it");
+            replicas.remove(&replica);
+            self.save_replicas(replicas).await;
+        }
+    }
+
+    /// Starts or drains replicas of Ready containers depending on their in-flight requests,
+    /// within the range set in the config. Drained replicas are removed by the docker worker
+    #[tracing::instrument]
+    pub(crate) async fn scale_replicas(&self) {
+        let (image, replicas) = match self.status.read().await.deref() {
+            ContainerStatus::Ready {
+                image, replicas, ..
+            } => (image.clone(), replicas.clone()),
+            _ => return,
+        };
+        replicas.remove_drained();
+        let current = replicas.len();
+        let desired = self
+            .config
+            .replicas
+            .get_desired(current, replicas.get_in_flight());
+        if desired < current {
+            replicas.drain_one();
+        }
+        join_all((current..desired).map(|_| self.start_replica(&image, &replicas))).await;
+        if desired != current {
+            self.save_replicas(&replicas).await;
+        }
+    }
+
+    /// Records the replicas serving requests, so they can be adopted after a restart
+    async fn save_replicas(&self, replicas: &ReplicaSet) {
+        let names: Vec<_> = replicas
+            .serving()
+            .iter()
+            .map(|replica| replica.container_name.clone())
+            .collect();
+        self.hooks.on_containers_changed(&names).await;
+    }
+
+    async fn start_replica(&self, image: &str, replicas: &ReplicaSet) {
+        let name = generate_managed_container_name();
+        replicas.add_starting(name.clone());
+        match self.try_start(&name, image).await {
+            Ok(socket) => {
+                let replica = Replica::new(name.clone(), socket);
+                replicas.finish_starting(&name, Some(replica));
+            }
+            Err(error) => {
+                error!("failed to start replica: {error}");
+                replicas.finish_starting(&name, None);
+                let _ = delete_container(&name).await;
+            }
+        }
+    }
+
+    /// Picks up the image built and the replicas started by a previous run of prezel, so the
+    /// container doesn't need to be built or started again. Replicas not responding are left
+    /// for the docker worker to remove, going to StandBy if none of them is left.
+    /// Containers already picked up by the build worker or started in the meantime are skipped,
+    /// and the status is locked until done so that doesn't happen halfway
+    #[tracing::instrument]
+    pub(crate) async fn adopt(&self, image: &str, containers: &[String]) -> anyhow::Result<()> {
+        let mut status = self.status.write().await;
+        if !matches!(
+            *status,
+            ContainerStatus::Built | ContainerStatus::Queued { .. }
+        ) {
+            return Ok(());
+        }
+        ensure!(get_image(image).await.is_some(), "image {image} not found");
+        // the dbs and volumes were already set up, so this only creates their containers
+        let db_setup = self.setup.setup_db().await?;
+        let probes = containers
+            .iter()
+            .map(|name| self.get_adoptable_socket(name));
+        let sockets = join_all(probes).await;
+        let mut adopted = containers
+            .iter()
+            .zip(sockets)
+            .filter_map(|(name, socket)| Some(Replica::new(name.clone(), socket?)));
+        let image = image.to_owned();
+        *status = match adopted.next() {
+            Some(primary) => {
+                let replicas = ReplicaSet::new(primary);
+                adopted.for_each(|replica| replicas.add(replica));
+                *self.last_health_check.lock().await = Instant::now();
+                ContainerStatus::Ready {
+                    image,
+                    db_setup,
+                    replicas: replicas.into(),
+                    last_access: RwLock::new(Instant::now()).into(),
+                }
+            }
+            None => ContainerStatus::StandBy { image, db_setup },
+        };
+        let serving = status.get_container_names();
+        if serving.len() != containers.len() {
+            self.hooks.on_containers_changed(&serving).await;
+        }
+        Ok(())
+    }
+
+    /// Socket of the given docker container, as long as it is running and healthy
+    async fn get_adoptable_socket(&self, container: &str) -> Option<SocketAddrV4> {
+        let ip = get_bollard_container_ipv4(container).await?;
+        let socket = SocketAddrV4::new(ip, self.config.port);
+        let online = is_online(&socket, self.config.probe, &self.config.health_check).await;
+        online.then_some(socket)
+    }
+
+    /// Sets the given env vars on top of the config ones, restarting the container
+    /// if it was running so they get picked up. Worker processes are restarted as well
+    #[tracing::instrument]
+    pub(crate) async fn restart_with_env(&self, env: EnvVars) -> anyhow::Result<()> {
+        *self.env_overrides.write().await = env;
+        let running = matches!(
+            *self.status.read().await,
+            ContainerStatus::Ready { .. } | ContainerStatus::Starting { .. }
+        );
+        self.with_stopped(async {}).await?;
+        if running {
+            self.start().await?;
+        }
+        Ok(())
+    }
+
+    async fn get_env(&self) -> EnvVars {
+        self.config.env.clone() + self.env_overrides.read().await.clone()
+    }
+
+    /// The build needs to be claimed first through `claim_build`
     #[tracing::instrument]
     pub(crate) async fn setup_as_standby(&self) -> anyhow::Result<()> {
         self.build().await?;
@@ -213,7 +574,7 @@ impl Container {
     }
 
     #[tracing::instrument]
-    pub(crate) async fn downgrade_if_unused(&self) {
+    pub(crate) async fn downgrade_if_unused(&self, idle_timeout: Duration) {
         let new_status = if let ContainerStatus::Ready {
             image,
             last_access,
@@ -223,7 +584,7 @@ impl Container {
         {
             let last_access = last_access.read().await;
             let elapsed = Instant::now().checked_duration_since(*last_access);
-            if elapsed.is_some_and(|elapsed| elapsed > Duration::from_secs(30)) {
+            if elapsed.is_some_and(|elapsed| elapsed > idle_timeout) {
                 Some(ContainerStatus::StandBy {
                     image: image.clone(),
                     db_setup: db_setup.clone(),
@@ -237,31 +598,115 @@ impl Container {
 
         if let Some(new_status) = new_status {
             *self.status.write().await = new_status;
+            self.hooks.on_containers_changed(&[]).await;
+        }
+    }
+
+    /// Runs the given task making sure no process is running inside the container in the meantime,
+    /// by pausing the docker container if there is one. The status is locked until the task is done.
+    /// Worker processes are stopped instead, and get started again by the processes worker
+    #[tracing::instrument(skip(task))]
+    pub(crate) async fn with_paused<T>(&self, task: impl Future<Output = T>) -> anyhow::Result<T> {
+        let status = self.status.write().await;
+        self.stop_processes().await;
+        let container_names = status.get_container_names();
+        for name in &container_names {
+            pause_container(name).await?;
+        }
+        let output = task.await;
+        for name in &container_names {
+            unpause_container(name).await?;
+        }
+        Ok(output)
+    }
+
+    /// Runs the given task after removing the docker container, if any, as well as the ones of
+    /// the worker processes, so the next access starts it again from scratch. The status is locked
+    /// until the task is done
+    #[tracing::instrument(skip(task))]
+    pub(crate) async fn with_stopped<T>(&self, task: impl Future<Output = T>) -> anyhow::Result<T> {
+        let mut status = self.status.write().await;
+        self.stop_processes().await;
+        let container_names = status.get_container_names();
+        if let ContainerStatus::Ready {
+            image, db_setup, ..
         }
+        | ContainerStatus::Starting {
+            image, db_setup, ..
+        } = status.clone()
+        {
+            for container_name in container_names {
+                if let Some(time) = get_container_oom_kill(&container_name).await {
+                    self.oom_kills.lock().await.push(time);
+                }
+                stop_container(&container_name).await?;
+                delete_container(&container_name).await?;
+            }
+            *status = ContainerStatus::StandBy { image, db_setup };
+            self.hooks.on_containers_changed(&[]).await;
+        }
+        Ok(task.await)
     }
 
     #[tracing::instrument]
     async fn build(&self) -> anyhow::Result<()> {
-        // FIXME: I think there might be a race condition here where the container build is started twice
-        // at the same time...
         self.hooks.on_build_started().await;
 
-        let db_setup = self.setup.setup_db().await?;
+        let build = async {
+            let mut number = 1;
+            let (image, db_setup) = loop {
+                let started = now();
+                let result = self.try_build().await;
+                let transient = result.as_ref().is_err_and(is_transient);
+                self.record_attempt(AttemptPhase::Build, number, started, &result, transient)
+                    .await;
+                match result {
+                    Err(error) if transient && number < MAX_ATTEMPTS => {
+                        let delay = backoff(number);
+                        let seconds = delay.as_secs();
+                        let message = format!("{error}\nRetrying build in {seconds} seconds");
+                        self.hooks.on_build_log(&message, true).await;
+                        sleep(delay).await;
+                        number += 1;
+                    }
+                    result => break result,
+                }
+            }?;
+            // builds failing their release are never promoted, as they are not considered successful
+            self.release(&image).await?;
+            anyhow::Ok((image, db_setup))
+        };
 
-        *self.status.write().await = ContainerStatus::Building {
-            db_setup: db_setup.clone(),
+        // dropping the build future closes the connection with the docker daemon, aborting the build,
+        // and removes the container of the release command if it was running
+        let result = tokio::select! {
+            result = build => Some(result),
+            _ = self.superseded.cancelled() => None,
         };
 
-        match self.setup.build(&self.hooks).await {
-            Ok(image) => {
-                self.hooks.on_build_finished().await;
+        match result {
+            None => {
+                let message = "Build cancelled, a newer commit landed on the same branch";
+                self.hooks.on_build_log(message, false).await;
+                self.hooks.on_build_superseded().await;
+                *self.status.write().await = ContainerStatus::Superseded;
+                *self.result.write().await = Some(BuildResult::Superseded);
+            }
+            Some(Ok((image, db_setup))) => {
+                self.hooks.on_build_finished(&image).await;
                 *self.result.write().await = Some(BuildResult::Built);
                 *self.status.write().await = ContainerStatus::StandBy { image, db_setup };
             }
-            Err(error) => {
+            // a failing db setup is treated as a failed build as well,
+            // otherwise the container would be left in the Building status forever
+            Some(Err(error)) => {
                 error!("{}", error);
                 self.hooks.on_build_log(&error.to_string(), true).await;
-                self.hooks.on_build_failed().await;
+                if error.is::<BuildTimeout>() {
+                    self.hooks.on_build_timed_out().await;
+                } else {
+                    self.hooks.on_build_failed().await;
+                }
                 *self.status.write().await = ContainerStatus::Failed;
                 *self.result.write().await = Some(BuildResult::Failed);
             }
@@ -269,6 +714,64 @@ impl Container {
         Ok(())
     }
 
+    /// Runs the release command, if any, in a one-off container from the built image,
+    /// appending its output to the build logs
+    async fn release(&self, image: &str) -> anyhow::Result<()> {
+        let Some(Release {
+            command,
+            build_limits,
+        }) = &self.config.release
+        else {
+            return Ok(());
+        };
+        let message = format!("Running release command: {command}");
+        self.hooks.on_build_log(&message, false).await;
+        let Conf {
+            build_limits: defaults,
+            ..
+        } = Conf::read_async().await;
+        let timeout = build_limits.or(defaults).get_timeout();
+        let output = tokio::time::timeout(timeout, self.run_one_off(image, command)).await;
+        let (code, logs) = match output {
+            Ok(output) => output?,
+            Err(_) => return Err(BuildTimeout(timeout).into()),
+        };
+        for log in logs {
+            let error = log.log_type == LogType::Err;
+            self.hooks.on_build_log(&log.message, error).await;
+        }
+        ensure!(code == 0, "Release command failed with exit code {code}");
+        Ok(())
+    }
+
+    async fn try_build(&self) -> anyhow::Result<(String, DbSetup)> {
+        let db_setup = self.setup.setup_db().await?;
+        *self.status.write().await = ContainerStatus::Building {
+            db_setup: db_setup.clone(),
+        };
+        let image = self.setup.build(&self.hooks).await?;
+        Ok((image, db_setup))
+    }
+
+    async fn record_attempt<T>(
+        &self,
+        phase: AttemptPhase,
+        number: i64,
+        started: i64,
+        result: &anyhow::Result<T>,
+        transient: bool,
+    ) {
+        let attempt = InsertAttempt {
+            phase,
+            number,
+            started,
+            finished: now(),
+            error: result.as_ref().err().map(ToString::to_string),
+            transient,
+        };
+        self.hooks.on_attempt_finished(attempt).await;
+    }
+
     #[tracing::instrument]
     pub(crate) async fn start(&self) -> anyhow::Result<SocketAddrV4> {
         let (owned_start, image, name, db_setup) = {
@@ -293,80 +796,123 @@ impl Container {
                     container_name.clone(),
                     db_setup.clone(),
                 )
-            } else if let ContainerStatus::Ready { socket, .. } = current.deref() {
-                return Ok(socket.clone());
+            } else if let ContainerStatus::Ready { replicas, .. } = current.deref() {
+                return Ok(replicas.primary().socket);
             } else {
                 bail!("Tried to start container in a state different than StandBy or Starting")
             }
         };
 
         if owned_start {
-            if self.config.pull {
-                pull_image(&image).await;
-            }
-            let container = create_container(
-                name.clone(),
-                image.clone(),
-                self.config.env.clone(),
-                self.config.host_folders.iter(),
-                self.config.command.clone(),
-            )
-            .await?;
-            run_container(&container).await?;
-
-            let ip = get_bollard_container_ipv4(&container)
-                .await
-                .ok_or(anyhow!("Could not get IP for container"))?;
-            let socket = SocketAddrV4::new(ip, 80);
-            let timeout = now() + 60 * 1000; // 60 seconds
-            while !is_online(&socket.to_string()).await {
-                if now() > timeout {
-                    let logs: String = get_container_execution_logs(&container)
-                        .await
-                        .map(|log| log.message)
-                        .collect();
-                    bail!("Container {container} start timed out. See the logs below:\n{logs}");
+            let mut number = 1;
+            let socket = loop {
+                let started = now();
+                let result = self.try_start(&name, &image).await;
+                let transient = result.as_ref().is_err_and(is_transient);
+                // successful starts happen all the time, so they are only recorded after a failure
+                if result.is_err() || number > 1 {
+                    self.record_attempt(AttemptPhase::Start, number, started, &result, transient)
+                        .await;
                 }
-                sleep(Duration::from_millis(200)).await;
-            }
-
-            // TODO: this is better, but doesnt compile
-            // let online = stream::iter(0..(5 * 30))
-            //     .then(|_| async {
-            //         if is_online(&socket.to_string()).await {
-            //             true
-            //         } else {
-            //             sleep(Duration::from_millis(200)).await;
-            //             false
-            //         }
-            //     })
-            //     .take_while(|online| future::ready(!online))
-            //     .collect::<Vec<_>>()
-            //     .await
-            //     .last();
-            // if online !== Some(true) {
-            //     bail!("Container start timed out");
-            // }
+                match result {
+                    Ok(socket) => break socket,
+                    Err(error) => {
+                        // the container might have been created, so it needs to go away before retrying
+                        let _ = delete_container(&name).await;
+                        if transient && number < MAX_ATTEMPTS {
+                            sleep(backoff(number)).await;
+                            number += 1;
+                        } else {
+                            // going back to StandBy so the next access tries again from scratch
+                            *self.status.write().await =
+                                ContainerStatus::StandBy { image, db_setup };
+                            return Err(error);
+                        }
+                    }
+                }
+            };
 
+            // the first check of the new container happens after a full interval
+            *self.last_health_check.lock().await = Instant::now();
+            self.failed_health_checks.store(0, Ordering::Relaxed);
+            // the rest of replicas, if any, are started by the replicas worker
             *self.status.write().await = ContainerStatus::Ready {
                 image: image.clone(),
-                container_name: name,
                 db_setup,
-                socket,
+                replicas: ReplicaSet::new(Replica::new(name.clone(), socket)).into(),
                 last_access: RwLock::new(Instant::now()).into(),
             };
+            self.hooks.on_containers_changed(&[name.clone()]).await;
 
             Ok(socket)
         } else {
-            // FIXME: unbounded loop
             loop {
-                if let ContainerStatus::Ready { socket, .. } = self.status.read().await.clone() {
-                    return Ok(socket);
+                match self.status.read().await.deref() {
+                    ContainerStatus::Ready { replicas, .. } => {
+                        return Ok(replicas.primary().socket)
+                    }
+                    ContainerStatus::Starting { .. } => {}
+                    _ => bail!("Container failed to start"),
                 }
                 sleep(Duration::from_millis(200)).await;
             }
         }
     }
+
+    async fn try_start(&self, name: &str, image: &str) -> anyhow::Result<SocketAddrV4> {
+        if self.config.pull {
+            pull_image(image).await;
+        }
+        let Conf { resources, .. } = Conf::read_async().await;
+        let container = create_container(
+            name.to_owned(),
+            image.to_owned(),
+            self.get_env().await,
+            self.config.host_folders.iter(),
+            &self.config.volumes,
+            self.config.command.clone(),
+            self.config.resources.or(resources),
+        )
+        .await?;
+        run_container(&container).await?;
+
+        let ip = get_bollard_container_ipv4(&container)
+            .await
+            .ok_or(anyhow!("Could not get IP for container"))?;
+        let socket = SocketAddrV4::new(ip, self.config.port);
+        let startup_timeout = self.config.health_check.get_startup_timeout();
+        let timeout = now() + startup_timeout.as_millis() as i64;
+        while !is_online(&socket, self.config.probe, &self.config.health_check).await {
+            if now() > timeout {
+                let logs: String = get_container_execution_logs(&container)
+                    .await
+                    .map(|log| log.message)
+                    .collect();
+                bail!("Container {container} start timed out. See the logs below:\n{logs}");
+            }
+            sleep(Duration::from_millis(200)).await;
+        }
+
+        // TODO: this is better, but doesnt compile
+        // let online = stream::iter(0..(5 * 30))
+        //     .then(|_| async {
+        //         if is_online(&socket.to_string()).await {
+        //             true
+        //         } else {
+        //             sleep(Duration::from_millis(200)).await;
+        //             false
+        //         }
+        //     })
+        //     .take_while(|online| future::ready(!online))
+        //     .collect::<Vec<_>>()
+        //     .await
+        //     .last();
+        // if online !== Some(true) {
+        //     bail!("Container start timed out");
+        // }
+
+        Ok(socket)
+    }
 }
 
 #[async_trait]
@@ -376,15 +922,15 @@ impl Listener for Arc<Container> {
     }
 
     #[tracing::instrument]
-    async fn access(&self) -> anyhow::Result<Access> {
+    async fn access(&self, port: &Port) -> anyhow::Result<Access> {
         let socket = match self.status.read().await.deref() {
             ContainerStatus::Ready {
-                socket,
+                replicas,
                 last_access,
                 ..
             } => {
                 *last_access.write().await = Instant::now();
-                Some(socket.clone())
+                Some(replicas.pick())
             }
             _ => None,
         };
@@ -399,22 +945,27 @@ impl Listener for Arc<Container> {
         // FIXME: instead of AtomicStatus, I don't think it is the end of the world aquiring a write lock on the status for a container that is not in prod in ready mode
 
         match socket {
-            Some(socket) => Ok(Access::Socket(socket.clone())),
+            Some((socket, in_flight)) => {
+                let socket = self.get_port_socket(socket, port)?;
+                Ok(Access::Socket(socket, in_flight))
+            }
             None => {
                 let status = self.status.read().await.clone();
                 match status {
                     ContainerStatus::Ready {
-                        socket,
+                        replicas,
                         last_access,
                         ..
                     } => {
                         // FIXME: boilerplate in here
                         *last_access.write().await = Instant::now();
-                        Ok(Access::Socket(socket.clone()))
+                        let (socket, in_flight) = replicas.pick();
+                        let socket = self.get_port_socket(socket, port)?;
+                        Ok(Access::Socket(socket, in_flight))
                     }
                     ContainerStatus::StandBy { .. } | ContainerStatus::Starting { .. } => {
                         let socket = self.start().await?;
-                        Ok(Access::Socket(socket))
+                        Ok(self.get_port_socket(socket, port)?.into())
                     }
                     ContainerStatus::Built => {
                         *self.status.write().await = ContainerStatus::Queued {
@@ -429,27 +980,33 @@ impl Listener for Arc<Container> {
                     ContainerStatus::Failed => {
                         bail!("container failed to build")
                     }
+                    ContainerStatus::Superseded => {
+                        bail!("container build was superseded by a newer commit")
+                    }
                 }
             }
         }
     }
 }
 
-// FIXME: this might fail, especially for some API server with no / route
-// there has to be another way
 #[tracing::instrument]
-async fn is_online(host: &str) -> bool {
-    let url = format!("http://{host}");
+async fn is_online(socket: &SocketAddrV4, probe: Probe, health_check: &HealthCheck) -> bool {
+    match probe {
+        Probe::Http => is_http_online(&socket.to_string(), health_check).await,
+        Probe::Tcp => TcpStream::connect(socket).await.is_ok(),
+    }
+}
+
+#[tracing::instrument]
+async fn is_http_online(host: &str, health_check: &HealthCheck) -> bool {
+    let url = format!("http://{host}{}", health_check.get_path());
     let client = Client::builder()
         .timeout(Duration::from_secs(5))
         .build()
         .unwrap();
     let response = client.get(url).send().await;
-    // let response = reqwest::get(url).await;
-    // TODO: review if this is actually enough or I need to do the thing below instead
-    response.is_ok()
-    // match response {
-    //     Ok(response) => response.status() == StatusCode::OK,
-    //     Err(_) => false,
-    // }
+    match response {
+        Ok(response) => health_check.accepts(response.status().as_u16()),
+        Err(_) => false,
+    }
 }
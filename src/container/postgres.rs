@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure};
+
+use crate::{
+    deployments::worker::WorkerHandle,
+    docker::{pull_image, LogType},
+    hooks::NoopHooks,
+};
+
+use super::{
+    BuildResult, Container, ContainerConfig, ContainerSetup, ContainerStatus, DbSetup, Probe,
+};
+
+const VERSION: &str = "16";
+/// postgres refuses to run as root, and the copied files might not belong to its user yet.
+/// The password is read from the env so it never shows up in the container command
+const RESET_PASSWORD_COMMAND: &str = r#"chown -R postgres "$PGDATA" && chmod 700 "$PGDATA" && echo "ALTER ROLE postgres PASSWORD '$POSTGRES_PASSWORD'" | su-exec postgres postgres --single -D "$PGDATA" postgres"#;
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresContainer;
+
+impl PostgresContainer {
+    #[tracing::instrument]
+    pub(crate) fn new(db_folder: PathBuf, password: &str, build_queue: WorkerHandle) -> Container {
+        let builder = Self {};
+        // postgres refuses to init a folder that is a mount point, so we use a subfolder
+        let data_path = db_folder.join("data").display().to_string();
+        Container::new(
+            builder,
+            ContainerConfig {
+                host_folders: vec![db_folder.clone()],
+                pull: true,
+                env: [
+                    ("POSTGRES_PASSWORD", password),
+                    ("PGDATA", &data_path),
+                    ("PGPORT", "80"),
+                ]
+                .as_ref()
+                .into(),
+                initial_status: ContainerStatus::StandBy {
+                    image: get_image(),
+                    db_setup: Default::default(),
+                },
+                command: None,
+                result: Some(BuildResult::Built),
+                probe: Probe::Tcp,
+                health_check: Default::default(),
+                port: 80,
+                ports: Default::default(),
+                resources: Default::default(),
+                volumes: vec![],
+                replicas: Default::default(),
+                processes: Default::default(),
+                release: None,
+            },
+            build_queue,
+            None,
+            true,
+            NoopHooks,
+        )
+    }
+}
+
+/// Sets the password of the postgres user in a data folder copied from another database
+/// to the one in the env of the container. Postgres runs in single user mode,
+/// so the old password is never accepted meanwhile
+#[tracing::instrument]
+pub(crate) async fn reset_postgres_password(container: &Container) -> anyhow::Result<()> {
+    let image = get_image();
+    pull_image(&image).await;
+    let (code, logs) = container
+        .run_one_off(&image, RESET_PASSWORD_COMMAND)
+        .await?;
+    let errors = logs
+        .into_iter()
+        .filter(|log| log.log_type == LogType::Err)
+        .map(|log| log.message)
+        .collect::<Vec<_>>();
+    ensure!(
+        code == 0,
+        "failed to reset the postgres password with exit code {code}: {}",
+        errors.join("\n")
+    );
+    Ok(())
+}
+
+fn get_image() -> String {
+    format!("postgres:{VERSION}-alpine")
+}
+
+// the image is pulled instead of built, and postgres has no dbs of its own to set up
+impl ContainerSetup for PostgresContainer {
+    fn setup_db<'a>(
+        &'a self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<DbSetup>> + Send + 'a>>
+    {
+        Box::pin(async { Ok(DbSetup::default()) })
+    }
+    fn build<'a>(
+        &'a self,
+        _hooks: &'a Box<dyn super::DeploymentHooks>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<String>> + Send + 'a>>
+    {
+        Box::pin(async { bail!("postgres containers run a pulled image and are never built") })
+    }
+}
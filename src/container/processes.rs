@@ -0,0 +1,150 @@
+use anyhow::anyhow;
+use tracing::error;
+
+use crate::{
+    conf::Conf,
+    deployments::config::Process,
+    docker::{
+        create_container, delete_container, generate_managed_container_name,
+        generate_unmanaged_container_name, get_container_execution_logs, run_container,
+        set_restart_policy, stop_container, wait_container, DockerLog,
+    },
+    env::EnvVars,
+    utils::LogError,
+};
+
+use super::Container;
+
+impl Container {
+    /// Starts the worker processes that are not running yet, as long as the image is built.
+    /// Processes exiting afterwards are only restarted by docker, according to their policy
+    #[tracing::instrument]
+    pub(crate) async fn run_processes(&self) {
+        let image = match self.status.read().await.get_image_name() {
+            Some(image) => image.to_owned(),
+            None => return,
+        };
+        let mut processes = self.processes.lock().await;
+        for (name, process) in &self.config.processes {
+            if processes.contains_key(name) {
+                continue;
+            }
+            // registered before being created so the docker worker doesn't remove it meanwhile
+            let container_name = generate_managed_container_name();
+            processes.insert(name.clone(), container_name.clone());
+            if let Err(error) = self.start_process(&container_name, &image, process).await {
+                error!("failed to start process {name}: {error}");
+                processes.remove(name);
+                let _ = delete_container(&container_name).await;
+            }
+        }
+    }
+
+    /// Stops and removes the docker containers of all the worker processes
+    #[tracing::instrument]
+    pub(crate) async fn stop_processes(&self) {
+        let mut processes = self.processes.lock().await;
+        for (_, container_name) in processes.drain() {
+            // processes not restarted by docker might not be running anymore
+            let _ = stop_container(&container_name).await;
+            delete_container(&container_name).await.ignore_logging();
+        }
+    }
+
+    pub(crate) async fn get_process_container_names(&self) -> Vec<String> {
+        self.processes.lock().await.values().cloned().collect()
+    }
+
+    /// Execution logs of every worker process, along with the process name
+    #[tracing::instrument]
+    pub(crate) async fn get_process_logs(&self) -> Vec<(String, Vec<DockerLog>)> {
+        let processes = self.processes.lock().await.clone();
+        let mut logs = vec![];
+        for (name, container_name) in processes {
+            let process_logs = get_container_execution_logs(&container_name).await;
+            logs.push((name, process_logs.collect()));
+        }
+        logs
+    }
+
+    /// Runs the command until it exits in a one-off container from the image,
+    /// returning its exit code and output
+    #[tracing::instrument]
+    pub(crate) async fn run_command(&self, command: &str) -> anyhow::Result<(i64, Vec<DockerLog>)> {
+        let image = self
+            .status
+            .read()
+            .await
+            .get_image_name()
+            .ok_or(anyhow!("the image of the deployment is not built"))?
+            .to_owned();
+        self.run_one_off(&image, command).await
+    }
+
+    pub(crate) async fn run_one_off(
+        &self,
+        image: &str,
+        command: &str,
+    ) -> anyhow::Result<(i64, Vec<DockerLog>)> {
+        // unmanaged so the docker worker doesn't remove it while running
+        let container_name = generate_unmanaged_container_name();
+        let _one_off = OneOffContainer(container_name.clone());
+        let container = self
+            .create_from_image(&container_name, image, command, EnvVars::empty())
+            .await?;
+        run_container(&container).await?;
+        let code = wait_container(&container).await?;
+        let logs = get_container_execution_logs(&container).await.collect();
+        Ok((code, logs))
+    }
+
+    async fn start_process(
+        &self,
+        container_name: &str,
+        image: &str,
+        process: &Process,
+    ) -> anyhow::Result<()> {
+        let process_env = process.env.clone().unwrap_or_default().into();
+        let container = self
+            .create_from_image(container_name, image, &process.command, process_env)
+            .await?;
+        set_restart_policy(&container, process.restart.unwrap_or_default()).await?;
+        run_container(&container).await?;
+        Ok(())
+    }
+
+    /// Creates a docker container like the app one but running a different command
+    async fn create_from_image(
+        &self,
+        container_name: &str,
+        image: &str,
+        command: &str,
+        extra_env: EnvVars,
+    ) -> anyhow::Result<String> {
+        let Conf { resources, .. } = Conf::read_async().await;
+        create_container(
+            container_name.to_owned(),
+            image.to_owned(),
+            self.get_env().await + extra_env,
+            self.config.host_folders.iter(),
+            &self.config.volumes,
+            Some(command.to_owned()),
+            self.config.resources.or(resources),
+        )
+        .await
+    }
+}
+
+/// Removes the one-off container once dropped, so it doesn't keep running
+/// if the future waiting for it is cancelled or times out
+struct OneOffContainer(String);
+
+impl Drop for OneOffContainer {
+    fn drop(&mut self) {
+        let name = self.0.clone();
+        tokio::spawn(async move {
+            let _ = stop_container(&name).await;
+            let _ = delete_container(&name).await;
+        });
+    }
+}
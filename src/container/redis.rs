@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+
+use crate::{deployments::worker::WorkerHandle, hooks::NoopHooks};
+
+use super::{
+    BuildResult, Container, ContainerConfig, ContainerSetup, ContainerStatus, DbSetup, Probe,
+};
+
+const VERSION: &str = "7";
+
+#[derive(Clone, Debug)]
+pub(crate) struct RedisContainer;
+
+impl RedisContainer {
+    #[tracing::instrument]
+    pub(crate) fn new(
+        data_folder: PathBuf,
+        password: &str,
+        build_queue: WorkerHandle,
+    ) -> Container {
+        let builder = Self {};
+        let data_path = data_folder.display();
+        Container::new(
+            builder,
+            ContainerConfig {
+                host_folders: vec![data_folder.clone()],
+                pull: true,
+                env: Default::default(),
+                initial_status: ContainerStatus::StandBy {
+                    image: format!("redis:{VERSION}-alpine"),
+                    db_setup: Default::default(),
+                },
+                // the data is saved to the folder when the container is stopped for being idle
+                command: Some(format!(
+                    "redis-server --port 80 --requirepass {password} --dir {data_path}"
+                )),
+                result: Some(BuildResult::Built),
+                probe: Probe::Tcp,
+                health_check: Default::default(),
+                port: 80,
+                ports: Default::default(),
+                resources: Default::default(),
+                volumes: vec![],
+                replicas: Default::default(),
+                processes: Default::default(),
+                release: None,
+            },
+            build_queue,
+            None,
+            true,
+            NoopHooks,
+        )
+    }
+}
+
+// the image is pulled instead of built, and redis has no dbs of its own to set up
+impl ContainerSetup for RedisContainer {
+    fn setup_db<'a>(
+        &'a self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<DbSetup>> + Send + 'a>>
+    {
+        Box::pin(async { Ok(DbSetup::default()) })
+    }
+    fn build<'a>(
+        &'a self,
+        _hooks: &'a Box<dyn super::DeploymentHooks>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<String>> + Send + 'a>>
+    {
+        Box::pin(async { bail!("redis containers run a pulled image and are never built") })
+    }
+}
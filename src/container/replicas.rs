@@ -0,0 +1,186 @@
+use std::{
+    net::SocketAddrV4,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::listener::InFlight;
+
+/// Docker container serving a Ready container, one of possibly several
+#[derive(Debug)]
+pub(crate) struct Replica {
+    pub(crate) container_name: String,
+    pub(crate) socket: SocketAddrV4,
+    in_flight: Arc<AtomicUsize>,
+    /// consecutive failed health checks, the ones of the first replica are tracked by the container
+    pub(crate) failed_health_checks: AtomicU32,
+}
+
+impl Replica {
+    pub(crate) fn new(container_name: String, socket: SocketAddrV4) -> Self {
+        Self {
+            container_name,
+            socket,
+            in_flight: Default::default(),
+            failed_health_checks: AtomicU32::new(0),
+        }
+    }
+
+    fn get_in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    /// never empty, the first replica is the one started on access
+    serving: Vec<Arc<Replica>>,
+    /// names of the docker containers being started, so they are not removed in the meantime
+    starting: Vec<String>,
+    /// replicas not receiving new requests, removed once their in-flight requests are done
+    draining: Vec<Arc<Replica>>,
+}
+
+/// The replicas of a Ready container, requests are sent to the one with the least
+/// in-flight requests, going round robin among the ones with the same number
+#[derive(Debug)]
+pub(crate) struct ReplicaSet {
+    state: Mutex<State>,
+    next: AtomicUsize,
+}
+
+impl ReplicaSet {
+    pub(crate) fn new(primary: Replica) -> Self {
+        Self {
+            state: Mutex::new(State {
+                serving: vec![primary.into()],
+                starting: vec![],
+                draining: vec![],
+            }),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn primary(&self) -> Arc<Replica> {
+        self.state.lock().unwrap().serving[0].clone()
+    }
+
+    pub(crate) fn serving(&self) -> Vec<Arc<Replica>> {
+        self.state.lock().unwrap().serving.clone()
+    }
+
+    /// Replicas serving requests or about to
+    pub(crate) fn len(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.serving.len() + state.starting.len()
+    }
+
+    pub(crate) fn get_in_flight(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .serving
+            .iter()
+            .map(|replica| replica.get_in_flight())
+            .sum()
+    }
+
+    pub(crate) fn pick(&self) -> (SocketAddrV4, InFlight) {
+        let state = self.state.lock().unwrap();
+        let len = state.serving.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % len;
+        let replica = (0..len)
+            .map(|offset| &state.serving[(start + offset) % len])
+            .min_by_key(|replica| replica.get_in_flight())
+            .unwrap_or(&state.serving[0]);
+        (replica.socket, InFlight::new(replica.in_flight.clone()))
+    }
+
+    /// Adds a replica that is already running, like the ones adopted after a restart
+    pub(crate) fn add(&self, replica: Replica) {
+        self.state.lock().unwrap().serving.push(replica.into());
+    }
+
+    pub(crate) fn add_starting(&self, name: String) {
+        self.state.lock().unwrap().starting.push(name);
+    }
+
+    /// The replica only starts receiving requests if it was started successfully
+    pub(crate) fn finish_starting(&self, name: &str, replica: Option<Replica>) {
+        let mut state = self.state.lock().unwrap();
+        state.starting.retain(|starting| starting != name);
+        state.serving.extend(replica.map(Arc::new));
+    }
+
+    /// Stops sending requests to the least busy replica, other than the first one
+    pub(crate) fn drain_one(&self) {
+        let mut state = self.state.lock().unwrap();
+        let index =
+            (1..state.serving.len()).min_by_key(|index| state.serving[*index].get_in_flight());
+        if let Some(index) = index {
+            let replica = state.serving.remove(index);
+            state.draining.push(replica);
+        }
+    }
+
+    /// Replicas failing their health checks are removed straight away, other than the first one
+    pub(crate) fn remove(&self, replica: &Arc<Replica>) {
+        let mut state = self.state.lock().unwrap();
+        let primary = state.serving[0].clone();
+        state
+            .serving
+            .retain(|serving| Arc::ptr_eq(serving, &primary) || !Arc::ptr_eq(serving, replica));
+    }
+
+    pub(crate) fn remove_drained(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.draining.retain(|replica| replica.get_in_flight() > 0);
+    }
+
+    /// Names of all the docker containers in use, including the ones starting and draining
+    pub(crate) fn get_container_names(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let serving = state.serving.iter().chain(&state.draining);
+        serving
+            .map(|replica| replica.container_name.clone())
+            .chain(state.starting.iter().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod replicas_tests {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use super::{Replica, ReplicaSet};
+
+    fn replica(index: u8) -> Replica {
+        let socket = SocketAddrV4::new(Ipv4Addr::new(172, 18, 0, index), 80);
+        Replica::new(format!("replica-{index}"), socket)
+    }
+
+    #[test]
+    fn test_least_in_flight() {
+        let replicas = ReplicaSet::new(replica(1));
+        replicas.add_starting("replica-2".to_owned());
+        replicas.finish_starting("replica-2", Some(replica(2)));
+        assert_eq!(replicas.len(), 2);
+
+        let (first, first_in_flight) = replicas.pick();
+        let (second, second_in_flight) = replicas.pick();
+        assert_ne!(first, second);
+        assert_eq!(replicas.get_in_flight(), 2);
+
+        drop(first_in_flight);
+        let (third, _third_in_flight) = replicas.pick();
+        assert_eq!(third, first);
+        drop(second_in_flight);
+
+        replicas.drain_one();
+        assert_eq!(replicas.serving().len(), 1);
+        assert_eq!(replicas.get_container_names().len(), 2);
+        replicas.remove_drained();
+        assert_eq!(replicas.get_container_names(), vec!["replica-1".to_owned()]);
+    }
+}
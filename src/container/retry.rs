@@ -0,0 +1,103 @@
+use std::{io, time::Duration};
+
+use http::StatusCode;
+
+/// Builds and starts are tried at most this number of times
+pub(crate) const MAX_ATTEMPTS: i64 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Time to wait before trying again after the given failed attempt, doubling every time
+pub(crate) fn backoff(attempt: i64) -> Duration {
+    let exponent = (attempt - 1).clamp(0, 10) as u32;
+    INITIAL_BACKOFF * 2u32.pow(exponent)
+}
+
+/// Errors coming from network issues or from the docker daemon or the repository host
+/// being temporarily unavailable are worth retrying. Anything else, like a failing
+/// Dockerfile step or a build timeout, would fail again the same way
+pub(crate) fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(error) = cause.downcast_ref::<bollard::errors::Error>() {
+            match error {
+                bollard::errors::Error::DockerResponseServerError { status_code, .. } => {
+                    is_transient_status(StatusCode::from_u16(*status_code).ok())
+                }
+                bollard::errors::Error::RequestTimeoutError
+                | bollard::errors::Error::HyperResponseError { .. }
+                | bollard::errors::Error::IOError { .. } => true,
+                _ => false,
+            }
+        } else if let Some(error) = cause.downcast_ref::<octocrab::Error>() {
+            match error {
+                octocrab::Error::GitHub { source, .. } => {
+                    is_transient_status(Some(source.status_code))
+                }
+                octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. } => true,
+                _ => false,
+            }
+        } else if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+            error.is_timeout()
+                || error.is_connect()
+                || error.is_body()
+                || is_transient_status(error.status())
+        } else if let Some(error) = cause.downcast_ref::<io::Error>() {
+            matches!(
+                error.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::UnexpectedEof
+            )
+        } else {
+            cause.is::<hyper::Error>()
+        }
+    })
+}
+
+fn is_transient_status(status: Option<StatusCode>) -> bool {
+    status.is_some_and(|status| status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS)
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use std::{io, time::Duration};
+
+    use anyhow::anyhow;
+
+    use crate::container::BuildTimeout;
+
+    use super::{backoff, is_transient};
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(10));
+        assert_eq!(backoff(2), Duration::from_secs(20));
+        assert_eq!(backoff(3), Duration::from_secs(40));
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let daemon_down = bollard::errors::Error::DockerResponseServerError {
+            status_code: 503,
+            message: "unavailable".to_owned(),
+        };
+        assert!(is_transient(&daemon_down.into()));
+
+        let missing_image = bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            message: "No such image".to_owned(),
+        };
+        assert!(!is_transient(&missing_image.into()));
+
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert!(is_transient(
+            &anyhow::Error::from(reset).context("downloading")
+        ));
+
+        assert!(!is_transient(&BuildTimeout(Duration::from_secs(1)).into()));
+        assert!(!is_transient(&anyhow!("No start command could be found")));
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+
+use crate::{deployments::worker::WorkerHandle, hooks::NoopHooks};
+
+use super::{
+    BuildResult, Container, ContainerConfig, ContainerSetup, ContainerStatus, DbSetup, Probe,
+};
+
+const VERSION: &str = "RELEASE.2025-04-22T22-12-26Z";
+
+#[derive(Clone, Debug)]
+pub(crate) struct S3Container;
+
+impl S3Container {
+    #[tracing::instrument]
+    pub(crate) fn new(
+        data_folder: PathBuf,
+        access_key: &str,
+        secret_key: &str,
+        build_queue: WorkerHandle,
+    ) -> Container {
+        let builder = Self {};
+        let data_path = data_folder.display();
+        Container::new(
+            builder,
+            ContainerConfig {
+                host_folders: vec![data_folder.clone()],
+                pull: true,
+                env: [
+                    ("MINIO_ROOT_USER", access_key),
+                    ("MINIO_ROOT_PASSWORD", secret_key),
+                    ("MINIO_BROWSER", "off"),
+                ]
+                .as_ref()
+                .into(),
+                initial_status: ContainerStatus::StandBy {
+                    image: format!("minio/minio:{VERSION}"),
+                    db_setup: Default::default(),
+                },
+                command: Some(format!("minio server {data_path} --address :80")),
+                result: Some(BuildResult::Built),
+                probe: Probe::Http,
+                health_check: Default::default(),
+                port: 80,
+                ports: Default::default(),
+                resources: Default::default(),
+                volumes: vec![],
+                replicas: Default::default(),
+                processes: Default::default(),
+                release: None,
+            },
+            build_queue,
+            None,
+            true,
+            NoopHooks,
+        )
+    }
+}
+
+// the image is pulled instead of built, and the object store has no dbs of its own to set up
+impl ContainerSetup for S3Container {
+    fn setup_db<'a>(
+        &'a self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<DbSetup>> + Send + 'a>>
+    {
+        Box::pin(async { Ok(DbSetup::default()) })
+    }
+    fn build<'a>(
+        &'a self,
+        _hooks: &'a Box<dyn super::DeploymentHooks>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<String>> + Send + 'a>>
+    {
+        Box::pin(async {
+            bail!("object storage containers run a pulled image and are never built")
+        })
+    }
+}
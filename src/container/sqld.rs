@@ -1,17 +1,29 @@
 use std::path::PathBuf;
 
-use crate::{deployments::worker::WorkerHandle, hooks::NoopHooks, sqlite_db::SqliteDbSetup};
+use crate::{
+    deployments::{config::ResourceLimits, worker::WorkerHandle},
+    hooks::NoopHooks,
+};
 
-use super::{BuildResult, Container, ContainerConfig, ContainerSetup, ContainerStatus};
+use super::{
+    BuildResult, Container, ContainerConfig, ContainerSetup, ContainerStatus, DbSetup, Probe,
+};
 
 const VERSION: &str = "0.24.28";
+/// public keys accepted by sqld to verify the tokens
+pub(crate) const JWT_KEY_VAR: &str = "SQLD_AUTH_JWT_KEY";
 
 #[derive(Clone, Debug)]
 pub(crate) struct SqldContainer;
 
 impl SqldContainer {
     #[tracing::instrument]
-    pub(crate) fn new(db_folder: PathBuf, key: &str, build_queue: WorkerHandle) -> Container {
+    pub(crate) fn new(
+        db_folder: PathBuf,
+        key: &str,
+        build_queue: WorkerHandle,
+        resources: ResourceLimits,
+    ) -> Container {
         let builder = Self {};
         let db_path = db_folder.display().to_string();
         Container::new(
@@ -22,16 +34,25 @@ impl SqldContainer {
                 env: [
                     ("SQLD_HTTP_LISTEN_ADDR", "0.0.0.0:80"),
                     ("SQLD_DB_PATH", &db_path),
-                    ("SQLD_AUTH_JWT_KEY", key),
+                    (JWT_KEY_VAR, key),
                 ]
                 .as_ref() // FIXME: should not need this
                 .into(),
                 initial_status: ContainerStatus::StandBy {
                     image: format!("ghcr.io/tursodatabase/libsql-server:v{VERSION}"),
-                    db_setup: None,
+                    db_setup: Default::default(),
                 },
                 command: None,
                 result: Some(BuildResult::Built),
+                probe: Probe::Http,
+                health_check: Default::default(),
+                port: 80,
+                ports: Default::default(),
+                resources,
+                volumes: vec![],
+                replicas: Default::default(),
+                processes: Default::default(),
+                release: None,
             },
             build_queue,
             None,
@@ -45,9 +66,8 @@ impl SqldContainer {
 impl ContainerSetup for SqldContainer {
     fn setup_db<'a>(
         &'a self,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = anyhow::Result<Option<SqliteDbSetup>>> + Send + 'a>,
-    > {
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<DbSetup>> + Send + 'a>>
+    {
         todo!()
     }
     fn build<'a>(
@@ -0,0 +1,119 @@
+use anyhow::{bail, ensure};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+/// Cron expression with the usual five fields: minute, hour, day of month, month and day of week.
+/// Every field accepts `*`, values, ranges like `1-5`, steps like `*/15` and lists of them
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Schedule {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    /// as in cron, if both days and weekdays are restricted matching either of them is enough
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub(crate) fn parse(expression: &str) -> anyhow::Result<Self> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            bail!("invalid schedule {expression}, expected 5 fields");
+        };
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            // 7 is accepted as sunday as well
+            weekdays: parse_field(weekdays, 0, 7)?
+                .into_iter()
+                .map(|weekday| weekday % 7)
+                .collect(),
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+
+    /// Whether the schedule fires during the minute of the given time
+    pub(crate) fn matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days.contains(&time.day());
+        let weekday = self
+            .weekdays
+            .contains(&time.weekday().num_days_from_sunday());
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes.contains(&time.minute())
+            && self.hours.contains(&time.hour())
+            && self.months.contains(&time.month())
+            && day_matches
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> anyhow::Result<Vec<u32>> {
+    let mut values = vec![];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        ensure!(step > 0, "invalid step in {field}");
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                // a single value with a step goes until the end, as in 5/15
+                None if part.contains('/') => (range.parse()?, max),
+                None => {
+                    let value = range.parse()?;
+                    (value, value)
+                }
+            },
+        };
+        ensure!(
+            start >= min && end <= max && start <= end,
+            "{part} out of range {min}-{max}"
+        );
+        values.extend((start..=end).step_by(step as usize));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod cron_tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        // 2025-06-02 is a monday
+        let time = Utc.with_ymd_and_hms(2025, 6, 2, 3, 30, 0).unwrap();
+
+        assert!(Schedule::parse("* * * * *").unwrap().matches(&time));
+        assert!(Schedule::parse("*/15 3 * * *").unwrap().matches(&time));
+        assert!(Schedule::parse("30 1-5 * * 1-5").unwrap().matches(&time));
+        assert!(!Schedule::parse("0 * * * *").unwrap().matches(&time));
+        assert!(!Schedule::parse("30 3 * * 0,6").unwrap().matches(&time));
+        // either the day of month or the day of week is enough
+        assert!(Schedule::parse("30 3 15 * 1").unwrap().matches(&time));
+        assert!(Schedule::parse("@hourly")
+            .unwrap()
+            .matches(&time.with_minute(0).unwrap()));
+
+        assert!(Schedule::parse("* * * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+    }
+}
@@ -9,9 +9,14 @@ use tracing::info;
 use utoipa::ToSchema;
 
 use crate::{
-    deployments::config::{from_opt_str, DeploymentConfig, FlatDeploymentConfig},
+    deployments::config::{
+        from_opt_str, DeploymentConfig, FlatDeploymentConfig, IdleTimeout, Scaling,
+    },
     label::Label,
     paths::get_instance_db_path,
+    postgres_db::format_postgres_url,
+    redis_db::format_redis_url,
+    source::RepoRef,
     utils::{now, PlusHttps, LOWERCASE_PLUS_NUMBERS},
 };
 
@@ -23,16 +28,25 @@ pub(crate) mod nano_id;
 pub(crate) enum BuildResult {
     Built,
     Failed,
+    /// the build was skipped or cancelled because a newer commit landed on the same branch
+    Superseded,
 }
 
 #[derive(Clone, Debug)]
 struct PlainProject {
     pub(crate) id: NanoId,
     pub(crate) name: String,
-    pub(crate) repo_id: i64,
+    pub(crate) repo: String,
     pub(crate) created: i64,
     pub(crate) root: String,
     pub(crate) prod_id: MaybeNanoId,
+    pub(crate) backup_retention: i64,
+    pub(crate) preview_delete_closed: i64,
+    pub(crate) preview_retention: i64,
+    pub(crate) preview_ttl_days: i64,
+    pub(crate) scaling_preview: Option<i64>,
+    pub(crate) scaling_prod: Option<i64>,
+    pub(crate) scaling_db: Option<i64>,
 }
 
 #[derive(FromRow, Debug)]
@@ -52,6 +66,33 @@ struct PlainDeployment {
     pub(crate) config_build_backend: Option<String>,
     pub(crate) config_dockerfile_path: Option<String>,
     pub(crate) deleted: Option<i64>, // ignored, only used for filtering in the SQL select
+    pub(crate) config_build_timeout: Option<i64>,
+    pub(crate) config_health_path: Option<String>,
+    pub(crate) config_health_status_min: Option<i64>,
+    pub(crate) config_health_status_max: Option<i64>,
+    pub(crate) config_health_interval: Option<i64>,
+    pub(crate) config_health_startup_timeout: Option<i64>,
+    pub(crate) config_health_failure_threshold: Option<i64>,
+    pub(crate) config_scaling_preview: Option<i64>,
+    pub(crate) config_scaling_prod: Option<i64>,
+    pub(crate) config_scaling_db: Option<i64>,
+    pub(crate) config_port: Option<i64>,
+    pub(crate) config_ports: Option<String>,
+    pub(crate) config_resources_memory: Option<i64>,
+    pub(crate) config_resources_memory_swap: Option<i64>,
+    pub(crate) config_resources_cpu_shares: Option<i64>,
+    pub(crate) config_resources_cpus: Option<f64>,
+    pub(crate) config_resources_pids: Option<i64>,
+    pub(crate) config_volumes: Option<String>,
+    pub(crate) config_redis: Option<i64>,
+    pub(crate) config_replicas_min: Option<i64>,
+    pub(crate) config_replicas_max: Option<i64>,
+    pub(crate) config_replicas_target: Option<i64>,
+    pub(crate) config_processes: Option<String>,
+    pub(crate) config_crons: Option<String>,
+    pub(crate) config_release: Option<String>,
+    pub(crate) image: Option<String>,
+    pub(crate) containers: Option<String>,
 }
 
 #[derive(Debug)]
@@ -69,6 +110,10 @@ pub(crate) struct Deployment {
     pub(crate) project: NanoId,
     pub(crate) config: DeploymentConfig,
     pub(crate) env: Vec<EnvVar>,
+    /// left by a previous run of prezel, to be adopted on startup
+    pub(crate) image: Option<String>,
+    /// docker container of every replica, left by a previous run of prezel as well
+    pub(crate) containers: Vec<String>,
 }
 
 impl Deployment {
@@ -94,18 +139,37 @@ pub(crate) struct EnvVar {
 pub(crate) struct Project {
     pub(crate) id: NanoId,
     pub(crate) name: String,
-    pub(crate) repo_id: i64,
+    pub(crate) repo: RepoRef,
     pub(crate) created: i64,
     pub(crate) env: Vec<EditedEnvVar>,
     pub(crate) root: String,
     pub(crate) prod_id: Option<NanoId>,
     pub(crate) custom_domains: Vec<String>,
+    /// number of daily backups kept for the prod libsql db
+    pub(crate) backup_retention: i64,
+    pub(crate) preview_rules: PreviewRules,
+    /// defaults for the scale-to-zero policy of every deployment
+    pub(crate) scaling: Scaling,
+}
+
+/// Lifecycle rules for the preview deployments of a project
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct PreviewRules {
+    /// delete the previews of a branch once its pull request is closed
+    pub(crate) delete_closed: bool,
+    /// number of previews kept per branch, 0 means keeping all of them
+    pub(crate) retention: i64,
+    /// days after which previews are deleted, 0 means they never expire
+    pub(crate) ttl_days: i64,
 }
 
 #[derive(Deserialize, Debug, ToSchema)]
 pub(crate) struct InsertProject {
     pub(crate) name: String,
-    pub(crate) repo_id: i64,
+    /// provider qualified repository, e.g. `github:<repo id>`, `gitlab:<host>/<namespace>/<project>`,
+    /// `gitea:<host>/<owner>/<repo>` or `git:<url>`
+    #[schema(value_type = String)]
+    pub(crate) repo: RepoRef,
     pub(crate) env: Vec<EnvVar>,
     pub(crate) root: String,
 }
@@ -114,6 +178,62 @@ pub(crate) struct InsertProject {
 pub(crate) struct UpdateProject {
     pub(crate) name: Option<String>,
     custom_domains: Option<Vec<String>>,
+    backup_retention: Option<i64>,
+    preview_rules: Option<PreviewRules>,
+    scaling: Option<Scaling>,
+}
+
+#[derive(sqlx::Type, PartialEq, Clone, Copy, Debug)]
+#[sqlx(rename_all = "lowercase")]
+pub(crate) enum AttemptPhase {
+    Build,
+    Start,
+}
+
+#[derive(Debug)]
+pub(crate) struct InsertAttempt {
+    pub(crate) phase: AttemptPhase,
+    pub(crate) number: i64,
+    pub(crate) started: i64,
+    pub(crate) finished: i64,
+    pub(crate) error: Option<String>,
+    pub(crate) transient: bool,
+}
+
+#[derive(FromRow)]
+pub(crate) struct Attempt {
+    pub(crate) build: i64,
+    pub(crate) phase: String,
+    pub(crate) number: i64,
+    pub(crate) started: i64,
+    pub(crate) finished: i64,
+    pub(crate) error: Option<String>,
+    pub(crate) transient: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct InsertCronRun {
+    pub(crate) job: String,
+    pub(crate) manual: bool,
+    pub(crate) started: i64,
+    pub(crate) finished: i64,
+    pub(crate) status: Option<i64>,
+    pub(crate) success: bool,
+    pub(crate) logs: String,
+    pub(crate) deployment: NanoId,
+}
+
+#[derive(FromRow)]
+pub(crate) struct CronRun {
+    pub(crate) id: i64,
+    pub(crate) job: String,
+    pub(crate) manual: i64,
+    pub(crate) started: i64,
+    pub(crate) finished: i64,
+    pub(crate) status: Option<i64>,
+    pub(crate) success: i64,
+    pub(crate) logs: String,
+    pub(crate) deployment: String,
 }
 
 #[derive(FromRow)]
@@ -168,6 +288,48 @@ impl DeploymentWithProject {
             .plus_https()
         }
     }
+
+    pub(crate) fn get_postgres_url(&self, password: &str) -> String {
+        let label = if self.default_branch == 1 {
+            Label::ProdPostgres {
+                project: self.project.id.clone(),
+            }
+        } else {
+            Label::BranchPostgres {
+                project: self.project.id.clone(),
+                deployment: self.url_id.clone(),
+            }
+        };
+        format_postgres_url(&label, password)
+    }
+
+    pub(crate) fn get_s3_endpoint(&self, box_domain: &str) -> String {
+        let label = if self.default_branch == 1 {
+            Label::ProdS3 {
+                project: self.project.id.clone(),
+            }
+        } else {
+            Label::BranchS3 {
+                project: self.project.id.clone(),
+                deployment: self.url_id.clone(),
+            }
+        };
+        label.format_hostname(box_domain).plus_https()
+    }
+
+    pub(crate) fn get_redis_url(&self, password: &str) -> String {
+        let label = if self.default_branch == 1 {
+            Label::ProdRedis {
+                project: self.project.id.clone(),
+            }
+        } else {
+            Label::BranchRedis {
+                project: self.project.id.clone(),
+                deployment: self.url_id.clone(),
+            }
+        };
+        format_redis_url(&label, password)
+    }
 }
 
 impl Deref for DeploymentWithProject {
@@ -279,12 +441,23 @@ impl Db {
         Ok(Project {
             id: project.id,
             name: project.name,
-            repo_id: project.repo_id,
+            repo: project.repo.parse()?,
             created: project.created,
             env,
             root: project.root,
             prod_id: project.prod_id.0,
             custom_domains,
+            backup_retention: project.backup_retention,
+            preview_rules: PreviewRules {
+                delete_closed: project.preview_delete_closed != 0,
+                retention: project.preview_retention,
+                ttl_days: project.preview_ttl_days,
+            },
+            scaling: Scaling {
+                preview: project.scaling_preview.map(IdleTimeout::from),
+                prod: project.scaling_prod.map(IdleTimeout::from),
+                db: project.scaling_db.map(IdleTimeout::from),
+            },
         })
     }
 
@@ -293,18 +466,19 @@ impl Db {
         &self,
         InsertProject {
             name,
-            repo_id,
+            repo,
             env,
             root,
         }: InsertProject,
     ) -> anyhow::Result<()> {
         let id = NanoId::random();
         let created = now();
+        let repo = repo.to_string();
         let query = sqlx::query!(
-            "insert into projects (id, name, repo_id, created, root) values (?, ?, ?, ?, ?)",
+            "insert into projects (id, name, repo, created, root) values (?, ?, ?, ?, ?)",
             id,
             name,
-            repo_id,
+            repo,
             created,
             root
         );
@@ -333,6 +507,9 @@ impl Db {
         UpdateProject {
             name,
             custom_domains,
+            backup_retention,
+            preview_rules,
+            scaling,
         }: UpdateProject,
     ) -> anyhow::Result<()> {
         if let Some(name) = name {
@@ -340,6 +517,45 @@ impl Db {
             query.execute(&self.conn).await?;
         }
 
+        if let Some(retention) = backup_retention {
+            let query = sqlx::query!(
+                "update projects set backup_retention = ? where id = ?",
+                retention,
+                id
+            );
+            query.execute(&self.conn).await?;
+        }
+
+        if let Some(PreviewRules {
+            delete_closed,
+            retention,
+            ttl_days,
+        }) = preview_rules
+        {
+            let query = sqlx::query!(
+                "update projects set preview_delete_closed = ?, preview_retention = ?, preview_ttl_days = ? where id = ?",
+                delete_closed,
+                retention,
+                ttl_days,
+                id
+            );
+            query.execute(&self.conn).await?;
+        }
+
+        if let Some(Scaling { preview, prod, db }) = scaling {
+            let preview = preview.map(i64::from);
+            let prod = prod.map(i64::from);
+            let db = db.map(i64::from);
+            let query = sqlx::query!(
+                "update projects set scaling_preview = ?, scaling_prod = ?, scaling_db = ? where id = ?",
+                preview,
+                prod,
+                db,
+                id
+            );
+            query.execute(&self.conn).await?;
+        }
+
         if let Some(custom_domains) = custom_domains {
             let mut tx = self.conn.begin().await?;
             let query = sqlx::query!("delete from domains WHERE project = ?", id);
@@ -358,6 +574,22 @@ impl Db {
         Ok(())
     }
 
+    /// Pins the given deployment as prod, or unpins the current one if `None`
+    #[tracing::instrument]
+    pub(crate) async fn update_prod_id(
+        &self,
+        project: &NanoId,
+        prod_id: Option<&NanoId>,
+    ) -> anyhow::Result<()> {
+        let query = sqlx::query!(
+            "update projects set prod_id = ? where id = ?",
+            prod_id,
+            project
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
     #[tracing::instrument]
     pub(crate) async fn delete_project(&self, id: &NanoId) -> anyhow::Result<()> {
         let query = sqlx::query!("delete from projects where id = ?", id);
@@ -449,6 +681,31 @@ impl Db {
             visibility: deployment.config_visibility,
             backend: deployment.config_build_backend,
             dockerfile_path: deployment.config_dockerfile_path,
+            build_timeout: deployment.config_build_timeout,
+            health_path: deployment.config_health_path,
+            health_status_min: deployment.config_health_status_min,
+            health_status_max: deployment.config_health_status_max,
+            health_interval: deployment.config_health_interval,
+            health_startup_timeout: deployment.config_health_startup_timeout,
+            health_failure_threshold: deployment.config_health_failure_threshold,
+            resources_memory: deployment.config_resources_memory,
+            resources_memory_swap: deployment.config_resources_memory_swap,
+            resources_cpu_shares: deployment.config_resources_cpu_shares,
+            resources_cpus: deployment.config_resources_cpus,
+            resources_pids: deployment.config_resources_pids,
+            volumes: deployment.config_volumes,
+            scaling_preview: deployment.config_scaling_preview,
+            scaling_prod: deployment.config_scaling_prod,
+            scaling_db: deployment.config_scaling_db,
+            port: deployment.config_port,
+            ports: deployment.config_ports,
+            redis: deployment.config_redis,
+            replicas_min: deployment.config_replicas_min,
+            replicas_max: deployment.config_replicas_max,
+            replicas_target: deployment.config_replicas_target,
+            processes: deployment.config_processes,
+            crons: deployment.config_crons,
+            release: deployment.config_release,
         }
         .try_into()?;
         Ok(Deployment {
@@ -465,6 +722,12 @@ impl Db {
             project: deployment.project,
             config,
             env,
+            image: deployment.image,
+            // containers that can not be parsed are simply not adopted
+            containers: deployment
+                .containers
+                .and_then(|containers| serde_json::from_str(&containers).ok())
+                .unwrap_or_default(),
         })
     }
 
@@ -475,6 +738,43 @@ impl Db {
         Ok(())
     }
 
+    /// Deletes the preview deployments of a branch, default branch deployments are never affected
+    #[tracing::instrument]
+    pub(crate) async fn delete_branch_deployments(
+        &self,
+        project: &NanoId,
+        branch: &str,
+    ) -> anyhow::Result<()> {
+        let query = sqlx::query!(
+            "update deployments set deleted = 1 where project = ? and branch = ? and default_branch = 0",
+            project,
+            branch
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
+    /// Marks the deployments of the branch that are not built yet and point to an older commit
+    /// as superseded, so their builds are skipped or cancelled on the next sync
+    #[tracing::instrument]
+    pub(crate) async fn supersede_older_deployments(
+        &self,
+        project: &NanoId,
+        branch: &str,
+        timestamp: i64,
+    ) -> anyhow::Result<()> {
+        let superseded = BuildResult::Superseded;
+        let query = sqlx::query!(
+            "update deployments set result = ? where project = ? and branch = ? and timestamp < ? and result is null and deleted is null",
+            superseded,
+            project,
+            branch,
+            timestamp
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
     // TODO: implement this using SQL
     #[tracing::instrument]
     pub(crate) async fn get_latest_successful_prod_deployment_for_project(
@@ -486,12 +786,36 @@ impl Db {
             .await?
             .into_iter()
             .filter(|deployment| &deployment.project == project && deployment.is_default_branch())
-            .filter(|deployment| deployment.result != Some(BuildResult::Failed))
+            .filter(|deployment| {
+                !matches!(
+                    deployment.result,
+                    Some(BuildResult::Failed | BuildResult::Superseded)
+                )
+            })
             .collect();
         deployments.sort_by_key(|deployment| deployment.timestamp);
         Ok(deployments.pop())
     }
 
+    /// Returns the latest successful default branch deployment created before `before`
+    #[tracing::instrument]
+    pub(crate) async fn get_previous_successful_prod_deployment(
+        &self,
+        project: &NanoId,
+        before: i64,
+    ) -> anyhow::Result<Option<Deployment>> {
+        let mut deployments: Vec<_> = self
+            .get_deployments()
+            .await?
+            .into_iter()
+            .filter(|deployment| &deployment.project == project && deployment.is_default_branch())
+            .filter(|deployment| deployment.result == Some(BuildResult::Built))
+            .filter(|deployment| deployment.created < before)
+            .collect();
+        deployments.sort_by_key(|deployment| deployment.created);
+        Ok(deployments.pop())
+    }
+
     #[tracing::instrument]
     pub(crate) async fn get_deployment_with_project(
         &self,
@@ -540,7 +864,7 @@ impl Db {
         let id = NanoId::random();
         let url_id = create_deployment_url_id();
         let insert_query = sqlx::query!(
-            "insert into deployments (id, slug, timestamp, created, sha, branch, default_branch, project, result, config_visibility, config_build_backend, config_dockerfile_path) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "insert into deployments (id, slug, timestamp, created, sha, branch, default_branch, project, result, config_visibility, config_build_backend, config_dockerfile_path, config_build_timeout, config_health_path, config_health_status_min, config_health_status_max, config_health_interval, config_health_startup_timeout, config_health_failure_threshold, config_scaling_preview, config_scaling_prod, config_scaling_db, config_port, config_ports, config_resources_memory, config_resources_memory_swap, config_resources_cpu_shares, config_resources_cpus, config_resources_pids, config_volumes, config_redis, config_replicas_min, config_replicas_max, config_replicas_target, config_processes, config_crons, config_release) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             id,
             url_id,
             deployment.timestamp,
@@ -553,6 +877,31 @@ impl Db {
             config.visibility,
             config.backend,
             config.dockerfile_path,
+            config.build_timeout,
+            config.health_path,
+            config.health_status_min,
+            config.health_status_max,
+            config.health_interval,
+            config.health_startup_timeout,
+            config.health_failure_threshold,
+            config.scaling_preview,
+            config.scaling_prod,
+            config.scaling_db,
+            config.port,
+            config.ports,
+            config.resources_memory,
+            config.resources_memory_swap,
+            config.resources_cpu_shares,
+            config.resources_cpus,
+            config.resources_pids,
+            config.volumes,
+            config.redis,
+            config.replicas_min,
+            config.replicas_max,
+            config.replicas_target,
+            config.processes,
+            config.crons,
+            config.release,
         );
 
         let mut tx = self.conn.begin().await?;
@@ -582,6 +931,51 @@ impl Db {
         Ok(())
     }
 
+    /// Same as `supersede_older_deployments`, a result already recorded is never overwritten,
+    /// so a deployment that finished building right before being cancelled stays built
+    #[tracing::instrument]
+    pub(crate) async fn supersede_deployment(&self, id: &NanoId) -> anyhow::Result<()> {
+        let superseded = BuildResult::Superseded;
+        let query = sqlx::query!(
+            "update deployments set result = ? where id = ? and result is null",
+            superseded,
+            id
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn update_deployment_image(
+        &self,
+        id: &NanoId,
+        image: &str,
+    ) -> anyhow::Result<()> {
+        let query = sqlx::query!("update deployments set image = ? where id = ?", image, id);
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn update_deployment_containers(
+        &self,
+        id: &NanoId,
+        containers: &[String],
+    ) -> anyhow::Result<()> {
+        let containers = if containers.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(containers)?)
+        };
+        let query = sqlx::query!(
+            "update deployments set containers = ? where id = ?",
+            containers,
+            id
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
     #[tracing::instrument]
     pub(crate) async fn update_deployment_build_start(
         &self,
@@ -665,6 +1059,79 @@ impl Db {
         Ok(())
     }
 
+    #[tracing::instrument]
+    pub(crate) async fn get_deployment_attempts(
+        &self,
+        deployment: &NanoId,
+    ) -> anyhow::Result<Vec<Attempt>> {
+        let query = sqlx::query_as!(
+            Attempt,
+            "select build, phase, number, started, finished, error, transient from attempts where deployment = ? order by id",
+            deployment
+        );
+        Ok(query.fetch_all(&self.conn).await?)
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn insert_deployment_attempt(
+        &self,
+        deployment: &NanoId,
+        attempt: InsertAttempt,
+    ) -> anyhow::Result<()> {
+        let transient = attempt.transient as i64;
+        // the first build attempt belongs to a new build, while the rest go with the latest one
+        let new_build = (attempt.phase == AttemptPhase::Build && attempt.number == 1) as i64;
+        let query = sqlx::query!(
+            "insert into attempts (phase, number, started, finished, error, transient, deployment, build) values (?, ?, ?, ?, ?, ?, ?, max(1, (select coalesce(max(build), 0) from attempts where deployment = ?) + ?))",
+            attempt.phase,
+            attempt.number,
+            attempt.started,
+            attempt.finished,
+            attempt.error,
+            transient,
+            deployment,
+            deployment,
+            new_build
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
+    /// Latest runs of the cron jobs of the project, from newest to oldest
+    #[tracing::instrument]
+    pub(crate) async fn get_cron_runs(&self, project: &NanoId) -> anyhow::Result<Vec<CronRun>> {
+        let query = sqlx::query_as!(
+            CronRun,
+            "select id, job, manual, started, finished, status, success, logs, deployment from cron_runs where project = ? order by id desc limit 100",
+            project
+        );
+        Ok(query.fetch_all(&self.conn).await?)
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn insert_cron_run(
+        &self,
+        project: &NanoId,
+        run: InsertCronRun,
+    ) -> anyhow::Result<()> {
+        let manual = run.manual as i64;
+        let success = run.success as i64;
+        let query = sqlx::query!(
+            "insert into cron_runs (job, manual, started, finished, status, success, logs, deployment, project) values (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            run.job,
+            manual,
+            run.started,
+            run.finished,
+            run.status,
+            success,
+            run.logs,
+            run.deployment,
+            project
+        );
+        query.execute(&self.conn).await?;
+        Ok(())
+    }
+
     #[tracing::instrument]
     pub(crate) async fn hash_exists_for_project(
         &self,
@@ -1,9 +1,24 @@
-use std::path::{Component, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Component, PathBuf},
+    time::Duration,
+};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, ensure};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::Github;
+use crate::{cron::Schedule, source::SourceProvider};
+
+const DEFAULT_PORT: u16 = 80;
+const DEFAULT_BUILD_TIMEOUT_SECS: u64 = 30 * 60;
+const DEFAULT_HEALTH_INTERVAL_SECS: u64 = 10;
+const DEFAULT_HEALTH_STARTUP_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_HEALTH_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_TARGET_IN_FLIGHT: u32 = 10;
+/// suffixes already used by the hostnames of a deployment, see the label module
+const RESERVED_PORT_NAMES: [&str; 6] = ["insert", "libsql", "postgres", "s3", "redis", "port"];
 
 #[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -27,10 +42,293 @@ pub(crate) enum Build {
     Nixpacks { provider: Option<String> },
 }
 
+/// Bounds for image builds. Missing values fall back to the ones in config.json.
+/// There are no cpu nor memory limits, as BuildKit ignores the ones sent along with the build
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone, Copy)]
+pub(crate) struct BuildLimits {
+    /// in seconds, 30 minutes if not set anywhere
+    pub(crate) timeout: Option<u64>,
+}
+
+impl BuildLimits {
+    pub(crate) fn or(self, defaults: Self) -> Self {
+        Self {
+            timeout: self.timeout.or(defaults.timeout),
+        }
+    }
+
+    pub(crate) fn get_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_BUILD_TIMEOUT_SECS))
+    }
+}
+
+/// Resources available to running containers. Missing values fall back to the ones in config.json
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone, Copy)]
+pub(crate) struct ResourceLimits {
+    /// in megabytes
+    pub(crate) memory: Option<u64>,
+    /// memory plus swap in megabytes, -1 means unlimited swap
+    pub(crate) memory_swap: Option<i64>,
+    /// relative cpu weight when the cpus are contended, 1024 for docker
+    pub(crate) cpu_shares: Option<u64>,
+    pub(crate) cpus: Option<f64>,
+    /// max number of processes
+    pub(crate) pids: Option<i64>,
+}
+
+impl ResourceLimits {
+    pub(crate) fn or(self, defaults: Self) -> Self {
+        Self {
+            memory: self.memory.or(defaults.memory),
+            memory_swap: self.memory_swap.or(defaults.memory_swap),
+            cpu_shares: self.cpu_shares.or(defaults.cpu_shares),
+            cpus: self.cpus.or(defaults.cpus),
+            pids: self.pids.or(defaults.pids),
+        }
+    }
+}
+
+/// Number of containers serving the app, either fixed or scaled within a range
+/// depending on the number of requests being served
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(untagged)]
+pub(crate) enum Replicas {
+    Fixed(u32),
+    Range {
+        min: u32,
+        max: u32,
+        /// in-flight requests per replica above which more replicas are started
+        target_in_flight: Option<u32>,
+    },
+}
+
+impl Default for Replicas {
+    fn default() -> Self {
+        Self::Fixed(1)
+    }
+}
+
+impl Replicas {
+    pub(crate) fn get_min(&self) -> usize {
+        match self {
+            Self::Fixed(replicas) => *replicas as usize,
+            Self::Range { min, .. } => *min as usize,
+        }
+    }
+
+    pub(crate) fn get_max(&self) -> usize {
+        match self {
+            Self::Fixed(replicas) => *replicas as usize,
+            Self::Range { max, .. } => *max as usize,
+        }
+    }
+
+    fn get_target_in_flight(&self) -> usize {
+        match self {
+            Self::Fixed(_) => DEFAULT_TARGET_IN_FLIGHT as usize,
+            Self::Range {
+                target_in_flight, ..
+            } => target_in_flight.unwrap_or(DEFAULT_TARGET_IN_FLIGHT).max(1) as usize,
+        }
+    }
+
+    /// Replicas needed to serve the given number of in-flight requests. Going up happens
+    /// in one go, while going down happens one replica at a time to avoid flapping
+    pub(crate) fn get_desired(&self, current: usize, in_flight: usize) -> usize {
+        let needed = in_flight
+            .div_ceil(self.get_target_in_flight())
+            .clamp(self.get_min(), self.get_max());
+        if needed < current {
+            current - 1
+        } else {
+            needed
+        }
+    }
+}
+
+/// What happens when the command of a worker process exits
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RestartPolicy {
+    #[default]
+    Always,
+    OnFailure,
+    Never,
+}
+
+/// Process running from the image of the deployment next to the app, like a queue consumer
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub(crate) struct Process {
+    /// run through `sh -c`
+    pub(crate) command: String,
+    /// added on top of the env vars of the deployment
+    pub(crate) env: Option<HashMap<String, String>>,
+    pub(crate) restart: Option<RestartPolicy>,
+}
+
+/// Job run on a schedule against the prod deployment, either a command or a request to a path
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub(crate) struct CronJob {
+    /// cron expression with five fields, in UTC
+    pub(crate) schedule: String,
+    /// run through `sh -c` in a one-off container from the image of the deployment
+    pub(crate) command: Option<String>,
+    /// requested with GET to the app
+    pub(crate) path: Option<String>,
+}
+
+/// How to tell if a container is able to serve requests, both when starting and while running
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+pub(crate) struct HealthCheck {
+    /// path requested with GET, `/` by default
+    pub(crate) path: Option<String>,
+    /// inclusive range of accepted status codes, any response is accepted if not set
+    pub(crate) status: Option<[u16; 2]>,
+    /// seconds between checks of running containers
+    pub(crate) interval: Option<u64>,
+    /// seconds a container has to become healthy after being started
+    pub(crate) startup_timeout: Option<u64>,
+    /// consecutive failed checks after which a running container is restarted
+    pub(crate) failure_threshold: Option<u32>,
+}
+
+impl HealthCheck {
+    pub(crate) fn get_path(&self) -> String {
+        let path = self.path.as_deref().unwrap_or("/");
+        if path.starts_with('/') {
+            path.to_owned()
+        } else {
+            format!("/{path}")
+        }
+    }
+
+    pub(crate) fn accepts(&self, status: u16) -> bool {
+        match self.status {
+            Some([min, max]) => (min..=max).contains(&status),
+            None => true,
+        }
+    }
+
+    pub(crate) fn get_interval(&self) -> Duration {
+        Duration::from_secs(self.interval.unwrap_or(DEFAULT_HEALTH_INTERVAL_SECS))
+    }
+
+    pub(crate) fn get_startup_timeout(&self) -> Duration {
+        let timeout = self.startup_timeout;
+        Duration::from_secs(timeout.unwrap_or(DEFAULT_HEALTH_STARTUP_TIMEOUT_SECS))
+    }
+
+    pub(crate) fn get_failure_threshold(&self) -> u32 {
+        self.failure_threshold
+            .unwrap_or(DEFAULT_HEALTH_FAILURE_THRESHOLD)
+            .max(1)
+    }
+}
+
+/// Seconds without requests after which a container is stopped, or `"always_on"`
+#[derive(Deserialize, Serialize, ToSchema, Debug, PartialEq, Clone, Copy)]
+#[serde(untagged)]
+pub(crate) enum IdleTimeout {
+    Seconds(u64),
+    AlwaysOn(AlwaysOn),
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AlwaysOn {
+    AlwaysOn,
+}
+
+impl IdleTimeout {
+    /// None means the container is never stopped
+    fn get(self) -> Option<Duration> {
+        match self {
+            Self::Seconds(seconds) => Some(Duration::from_secs(seconds)),
+            Self::AlwaysOn(_) => None,
+        }
+    }
+}
+
+/// Stored as seconds, with -1 meaning always on
+impl From<IdleTimeout> for i64 {
+    fn from(value: IdleTimeout) -> Self {
+        match value {
+            IdleTimeout::Seconds(seconds) => seconds.try_into().unwrap_or(i64::MAX),
+            IdleTimeout::AlwaysOn(_) => -1,
+        }
+    }
+}
+
+impl From<i64> for IdleTimeout {
+    fn from(value: i64) -> Self {
+        match u64::try_from(value) {
+            Ok(seconds) => Self::Seconds(seconds),
+            Err(_) => Self::AlwaysOn(AlwaysOn::AlwaysOn),
+        }
+    }
+}
+
+/// Scale-to-zero policy, stopping containers that stop receiving requests. Values set
+/// in prezel.json take precedence over the ones set for the project through the API
+#[derive(Deserialize, Serialize, ToSchema, Default, Debug, PartialEq, Clone, Copy)]
+pub(crate) struct Scaling {
+    /// app containers of preview deployments, 30 seconds by default
+    pub(crate) preview: Option<IdleTimeout>,
+    /// app container of the prod deployment, always on by default
+    pub(crate) prod: Option<IdleTimeout>,
+    /// libsql, postgres, object storage and redis containers, the prod ones included,
+    /// 30 seconds by default
+    pub(crate) db: Option<IdleTimeout>,
+}
+
+impl Scaling {
+    pub(crate) fn or(self, defaults: Self) -> Self {
+        Self {
+            preview: self.preview.or(defaults.preview),
+            prod: self.prod.or(defaults.prod),
+            db: self.db.or(defaults.db),
+        }
+    }
+
+    pub(crate) fn get_preview_timeout(&self) -> Option<Duration> {
+        let default = IdleTimeout::Seconds(DEFAULT_IDLE_TIMEOUT_SECS);
+        self.preview.unwrap_or(default).get()
+    }
+
+    pub(crate) fn get_prod_timeout(&self) -> Option<Duration> {
+        self.prod.and_then(IdleTimeout::get)
+    }
+
+    pub(crate) fn get_db_timeout(&self) -> Option<Duration> {
+        let default = IdleTimeout::Seconds(DEFAULT_IDLE_TIMEOUT_SECS);
+        self.db.unwrap_or(default).get()
+    }
+}
+
 #[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
 pub(crate) struct DeploymentConfig {
     pub(crate) visibility: Option<Visibility>,
     pub(crate) build: Option<Build>,
+    pub(crate) build_limits: Option<BuildLimits>,
+    pub(crate) health_check: Option<HealthCheck>,
+    pub(crate) resources: Option<ResourceLimits>,
+    pub(crate) scaling: Option<Scaling>,
+    /// port the app listens on for HTTP requests
+    pub(crate) port: Option<u16>,
+    /// extra HTTP ports, reachable through a hostname including their name
+    pub(crate) ports: Option<HashMap<String, u16>>,
+    /// persistent folders mounted into the app container, from volume name to mount path
+    pub(crate) volumes: Option<HashMap<String, String>>,
+    /// creates a redis-compatible key-value service for the app
+    pub(crate) redis: Option<bool>,
+    /// only applied to deployments from the default branch
+    pub(crate) replicas: Option<Replicas>,
+    /// worker processes not receiving requests, from process name to its definition
+    pub(crate) processes: Option<HashMap<String, Process>>,
+    /// scheduled jobs, only run for the prod deployment
+    pub(crate) crons: Option<HashMap<String, CronJob>>,
+    /// command run once after the build, like db migrations, the build fails if it does
+    pub(crate) release: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +336,35 @@ pub(crate) struct FlatDeploymentConfig {
     pub(crate) visibility: Option<String>,
     pub(crate) backend: Option<String>,
     pub(crate) dockerfile_path: Option<String>,
+    pub(crate) build_timeout: Option<i64>,
+    pub(crate) health_path: Option<String>,
+    pub(crate) health_status_min: Option<i64>,
+    pub(crate) health_status_max: Option<i64>,
+    pub(crate) health_interval: Option<i64>,
+    pub(crate) health_startup_timeout: Option<i64>,
+    pub(crate) health_failure_threshold: Option<i64>,
+    pub(crate) resources_memory: Option<i64>,
+    pub(crate) resources_memory_swap: Option<i64>,
+    pub(crate) resources_cpu_shares: Option<i64>,
+    pub(crate) resources_cpus: Option<f64>,
+    pub(crate) resources_pids: Option<i64>,
+    /// json encoded
+    pub(crate) volumes: Option<String>,
+    pub(crate) scaling_preview: Option<i64>,
+    pub(crate) scaling_prod: Option<i64>,
+    pub(crate) scaling_db: Option<i64>,
+    pub(crate) port: Option<i64>,
+    /// json encoded
+    pub(crate) ports: Option<String>,
+    pub(crate) redis: Option<i64>,
+    pub(crate) replicas_min: Option<i64>,
+    pub(crate) replicas_max: Option<i64>,
+    pub(crate) replicas_target: Option<i64>,
+    /// json encoded
+    pub(crate) processes: Option<String>,
+    /// json encoded
+    pub(crate) crons: Option<String>,
+    pub(crate) release: Option<String>,
 }
 
 impl From<DeploymentConfig> for FlatDeploymentConfig {
@@ -47,10 +374,60 @@ impl From<DeploymentConfig> for FlatDeploymentConfig {
             Some(Build::Nixpacks { .. }) => (Some(BuildBackend::Dockerfile), None), // TODO: provider !!!!!!!!!!!!!!!
             None => (None, None),
         };
+        let limits = value.build_limits.unwrap_or_default();
+        let health = value.health_check.unwrap_or_default();
+        let resources = value.resources.unwrap_or_default();
+        let scaling = value.scaling.unwrap_or_default();
+        let (replicas_min, replicas_max, replicas_target) = match value.replicas {
+            Some(Replicas::Fixed(replicas)) => (Some(replicas), Some(replicas), None),
+            Some(Replicas::Range {
+                min,
+                max,
+                target_in_flight,
+            }) => (Some(min), Some(max), target_in_flight),
+            None => (None, None, None),
+        };
+        let [health_status_min, health_status_max] = match health.status {
+            Some([min, max]) => [Some(min as i64), Some(max as i64)],
+            None => [None, None],
+        };
         Self {
             visibility: into_opt_str(value.visibility),
             backend: into_opt_str(backend),
             dockerfile_path,
+            build_timeout: limits.timeout.map(|timeout| timeout as i64),
+            health_path: health.path,
+            health_status_min,
+            health_status_max,
+            health_interval: health.interval.map(|interval| interval as i64),
+            health_startup_timeout: health.startup_timeout.map(|timeout| timeout as i64),
+            health_failure_threshold: health.failure_threshold.map(|threshold| threshold as i64),
+            resources_memory: resources.memory.map(|memory| memory as i64),
+            resources_memory_swap: resources.memory_swap,
+            resources_cpu_shares: resources.cpu_shares.map(|shares| shares as i64),
+            resources_cpus: resources.cpus,
+            resources_pids: resources.pids,
+            volumes: value
+                .volumes
+                .map(|volumes| serde_json::to_string(&volumes).unwrap()),
+            scaling_preview: scaling.preview.map(i64::from),
+            scaling_prod: scaling.prod.map(i64::from),
+            scaling_db: scaling.db.map(i64::from),
+            port: value.port.map(i64::from),
+            ports: value
+                .ports
+                .map(|ports| serde_json::to_string(&ports).unwrap()),
+            redis: value.redis.map(i64::from),
+            replicas_min: replicas_min.map(i64::from),
+            replicas_max: replicas_max.map(i64::from),
+            replicas_target: replicas_target.map(i64::from),
+            processes: value
+                .processes
+                .map(|processes| serde_json::to_string(&processes).unwrap()),
+            crons: value
+                .crons
+                .map(|crons| serde_json::to_string(&crons).unwrap()),
+            release: value.release,
         }
     }
 }
@@ -70,9 +447,77 @@ impl TryFrom<FlatDeploymentConfig> for DeploymentConfig {
         } else {
             None
         };
+        let build_limits = BuildLimits {
+            timeout: value.build_timeout.map(u64::try_from).transpose()?,
+        };
+        let status = match (value.health_status_min, value.health_status_max) {
+            (Some(min), Some(max)) => Some([min.try_into()?, max.try_into()?]),
+            _ => None,
+        };
+        let health_check = HealthCheck {
+            path: value.health_path,
+            status,
+            interval: value.health_interval.map(u64::try_from).transpose()?,
+            startup_timeout: value
+                .health_startup_timeout
+                .map(u64::try_from)
+                .transpose()?,
+            failure_threshold: value
+                .health_failure_threshold
+                .map(u32::try_from)
+                .transpose()?,
+        };
+        let resources = ResourceLimits {
+            memory: value.resources_memory.map(u64::try_from).transpose()?,
+            memory_swap: value.resources_memory_swap,
+            cpu_shares: value.resources_cpu_shares.map(u64::try_from).transpose()?,
+            cpus: value.resources_cpus,
+            pids: value.resources_pids,
+        };
+        let scaling = Scaling {
+            preview: value.scaling_preview.map(IdleTimeout::from),
+            prod: value.scaling_prod.map(IdleTimeout::from),
+            db: value.scaling_db.map(IdleTimeout::from),
+        };
+        let replicas_min = value.replicas_min.map(u32::try_from).transpose()?;
+        let replicas_max = value.replicas_max.map(u32::try_from).transpose()?;
+        let target_in_flight = value.replicas_target.map(u32::try_from).transpose()?;
+        let replicas = match (replicas_min, replicas_max, target_in_flight) {
+            (Some(min), Some(max), None) if min == max => Some(Replicas::Fixed(min)),
+            (Some(min), Some(max), target_in_flight) => Some(Replicas::Range {
+                min,
+                max,
+                target_in_flight,
+            }),
+            _ => None,
+        };
         Ok(Self {
             visibility: from_opt_str(value.visibility)?,
             build,
+            build_limits: (build_limits != BuildLimits::default()).then_some(build_limits),
+            health_check: (health_check != HealthCheck::default()).then_some(health_check),
+            resources: (resources != ResourceLimits::default()).then_some(resources),
+            scaling: (scaling != Scaling::default()).then_some(scaling),
+            port: value.port.map(u16::try_from).transpose()?,
+            ports: value
+                .ports
+                .map(|ports| serde_json::from_str(&ports))
+                .transpose()?,
+            volumes: value
+                .volumes
+                .map(|volumes| serde_json::from_str(&volumes))
+                .transpose()?,
+            redis: value.redis.map(|redis| redis != 0),
+            replicas,
+            processes: value
+                .processes
+                .map(|processes| serde_json::from_str(&processes))
+                .transpose()?,
+            crons: value
+                .crons
+                .map(|crons| serde_json::from_str(&crons))
+                .transpose()?,
+            release: value.release,
         })
     }
 }
@@ -104,6 +549,106 @@ impl DeploymentConfig {
         self.visibility.clone().unwrap_or(Visibility::Standard)
     }
 
+    pub(crate) fn get_build_limits(&self, defaults: BuildLimits) -> BuildLimits {
+        self.build_limits.unwrap_or_default().or(defaults)
+    }
+
+    pub(crate) fn get_resources(&self) -> ResourceLimits {
+        self.resources.unwrap_or_default()
+    }
+
+    pub(crate) fn get_scaling(&self) -> Scaling {
+        self.scaling.unwrap_or_default()
+    }
+
+    pub(crate) fn get_port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    pub(crate) fn get_ports(&self) -> HashMap<String, u16> {
+        self.ports.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn get_volumes(&self) -> HashMap<String, String> {
+        self.volumes.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn is_redis_enabled(&self) -> bool {
+        self.redis.unwrap_or(false)
+    }
+
+    pub(crate) fn get_replicas(&self) -> Replicas {
+        self.replicas.unwrap_or_default()
+    }
+
+    pub(crate) fn get_processes(&self) -> HashMap<String, Process> {
+        self.processes.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn get_crons(&self) -> HashMap<String, CronJob> {
+        self.crons.clone().unwrap_or_default()
+    }
+
+    /// Checks the values whose constraints cannot be expressed through their types
+    fn validate(&self) -> anyhow::Result<()> {
+        let replicas = self.get_replicas();
+        ensure!(
+            replicas.get_min() >= 1 && replicas.get_min() <= replicas.get_max(),
+            "replicas should be at least 1, and min can not be greater than max"
+        );
+        // port names end up in hostnames, where - is already used as a separator
+        for (name, port) in self.get_ports() {
+            let valid_name = name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+            ensure!(
+                !name.is_empty() && valid_name,
+                "invalid port name {name}, only lowercase letters and numbers are allowed"
+            );
+            ensure!(
+                !RESERVED_PORT_NAMES.contains(&name.as_str()),
+                "port name {name} is reserved"
+            );
+            ensure!(
+                port != self.get_port(),
+                "port {name} can not be the same as the main port of the app"
+            );
+        }
+        // volume names end up being folder names, so they are limited to a safe set of characters
+        for (name, target) in self.get_volumes() {
+            let valid_name = name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+            ensure!(
+                !name.is_empty() && valid_name,
+                "invalid volume name {name}, only lowercase letters, numbers, - and _ are allowed"
+            );
+            ensure!(
+                target.starts_with('/'),
+                "the mount path of volume {name} should be absolute"
+            );
+        }
+        for (name, process) in self.get_processes() {
+            ensure!(!name.is_empty(), "process names can not be empty");
+            ensure!(
+                !process.command.trim().is_empty(),
+                "the command of process {name} can not be empty"
+            );
+        }
+        for (name, job) in self.get_crons() {
+            Schedule::parse(&job.schedule)?;
+            match (&job.command, &job.path) {
+                (Some(_), None) => {}
+                (None, Some(path)) => ensure!(
+                    path.starts_with('/'),
+                    "the path of cron job {name} should start with /"
+                ),
+                _ => bail!("cron job {name} should have either a command or a path"),
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn get_forced_dockerfile(&self) -> Option<&str> {
         if let Some(Build::Dockerfile { path }) = &self.build {
             Some(path.as_deref().unwrap_or("Dockerfile"))
@@ -121,26 +666,24 @@ impl DeploymentConfig {
     }
 
     pub(crate) async fn fetch_from_repo(
-        github: &Github,
-        repo_id: i64,
+        provider: &dyn SourceProvider,
         sha: &str,
         root: &str,
         app_name: &str,
     ) -> anyhow::Result<Option<Self>> {
         let custom_path = format!("{app_name}.prezel.json");
-        let app_config = fetch_from_path(github, repo_id, sha, root, &custom_path).await?;
+        let app_config = fetch_from_path(provider, sha, root, &custom_path).await?;
         if let Some(config) = app_config {
             Ok(Some(config))
         } else {
-            let config = fetch_from_path(github, repo_id, sha, root, "prezel.json").await?;
+            let config = fetch_from_path(provider, sha, root, "prezel.json").await?;
             Ok(config)
         }
     }
 }
 
 async fn fetch_from_path(
-    github: &Github,
-    repo_id: i64,
+    provider: &dyn SourceProvider,
     sha: &str,
     root: &str,
     config_file_name: &str,
@@ -152,16 +695,24 @@ async fn fetch_from_path(
     let valid_path: PathBuf = valid_components.collect();
     let err_msg = "Could not construct a valid path for prezel.json";
     let path_str = valid_path.to_str().ok_or(anyhow!(err_msg))?;
-    let content = github.download_file(repo_id, &sha, path_str).await?;
-    Ok(content.map(|c| serde_json::from_str(&c)).transpose()?)
+    let content = provider.download_file(sha, path_str).await?;
+    let config: Option<DeploymentConfig> = content.map(|c| serde_json::from_str(&c)).transpose()?;
+    if let Some(config) = &config {
+        config.validate()?;
+    }
+    Ok(config)
 }
 
 #[cfg(test)]
 mod config_tests {
+    use std::time::Duration;
 
     use crate::deployments::config::Visibility;
 
-    use super::{Build, DeploymentConfig, FlatDeploymentConfig};
+    use super::{
+        AlwaysOn, Build, BuildLimits, CronJob, DeploymentConfig, FlatDeploymentConfig, HealthCheck,
+        IdleTimeout, Process, Replicas, ResourceLimits, RestartPolicy, Scaling,
+    };
 
     // TODO: add a test with an unknown field and double check it fails
 
@@ -186,6 +737,54 @@ mod config_tests {
         )
     }
 
+    #[test]
+    fn test_scaling() {
+        let content = r#"{
+            "scaling": {
+                "preview": "always_on",
+                "prod": 600
+            }
+        }"#;
+
+        let scaling = serde_json::from_str::<DeploymentConfig>(&content)
+            .unwrap()
+            .get_scaling();
+
+        assert_eq!(scaling.get_preview_timeout(), None);
+        assert_eq!(scaling.get_prod_timeout(), Some(Duration::from_secs(600)));
+        assert_eq!(scaling.get_db_timeout(), Some(Duration::from_secs(30)));
+        assert!(serde_json::from_str::<Scaling>(r#"{ "prod": "sometimes" }"#).is_err());
+    }
+
+    #[test]
+    fn test_replicas() {
+        let fixed: Replicas = serde_json::from_str("3").unwrap();
+        assert_eq!(fixed.get_desired(1, 100), 3);
+
+        let range: Replicas =
+            serde_json::from_str(r#"{ "min": 1, "max": 4, "target_in_flight": 5 }"#).unwrap();
+        assert_eq!(range.get_desired(1, 0), 1);
+        assert_eq!(range.get_desired(1, 12), 3);
+        assert_eq!(range.get_desired(2, 100), 4);
+        assert_eq!(range.get_desired(4, 0), 3);
+    }
+
+    #[test]
+    fn test_ports() {
+        let parse = |ports: &str| {
+            let content = format!(r#"{{ "port": 3000, "ports": {ports} }}"#);
+            serde_json::from_str::<DeploymentConfig>(&content)
+                .unwrap()
+                .validate()
+        };
+        assert!(parse(r#"{ "admin": 3001, "api2": 3002 }"#).is_ok());
+        assert!(parse(r#"{ "api-v2": 3001 }"#).is_err());
+        assert!(parse(r#"{ "Admin": 3001 }"#).is_err());
+        assert!(parse(r#"{ "admin": 3000 }"#).is_err());
+        assert!(parse(r#"{ "postgres": 3001 }"#).is_err());
+        assert!(parse(r#"{ "port": 3001 }"#).is_err());
+    }
+
     #[test]
     fn test_two_way_conversion() {
         let config = DeploymentConfig {
@@ -193,6 +792,58 @@ mod config_tests {
             build: Some(Build::Dockerfile {
                 path: Some("some/path".to_owned()),
             }),
+            build_limits: Some(BuildLimits { timeout: Some(600) }),
+            health_check: Some(HealthCheck {
+                path: Some("/health".to_owned()),
+                status: Some([200, 299]),
+                interval: None,
+                startup_timeout: Some(120),
+                failure_threshold: Some(5),
+            }),
+            resources: Some(ResourceLimits {
+                memory: Some(512),
+                memory_swap: Some(-1),
+                cpu_shares: None,
+                cpus: Some(0.5),
+                pids: Some(256),
+            }),
+            scaling: Some(Scaling {
+                preview: Some(IdleTimeout::AlwaysOn(AlwaysOn::AlwaysOn)),
+                prod: Some(IdleTimeout::Seconds(300)),
+                db: None,
+            }),
+            port: Some(8080),
+            ports: Some([("metrics".to_owned(), 9090)].into()),
+            volumes: Some([("uploads".to_owned(), "/app/uploads".to_owned())].into()),
+            redis: Some(true),
+            replicas: Some(Replicas::Range {
+                min: 2,
+                max: 6,
+                target_in_flight: Some(20),
+            }),
+            processes: Some(
+                [(
+                    "worker".to_owned(),
+                    Process {
+                        command: "npm run worker".to_owned(),
+                        env: Some([("QUEUE".to_owned(), "emails".to_owned())].into()),
+                        restart: Some(RestartPolicy::OnFailure),
+                    },
+                )]
+                .into(),
+            ),
+            crons: Some(
+                [(
+                    "cleanup".to_owned(),
+                    CronJob {
+                        schedule: "0 3 * * *".to_owned(),
+                        command: None,
+                        path: Some("/api/cleanup".to_owned()),
+                    },
+                )]
+                .into(),
+            ),
+            release: Some("npm run migrate".to_owned()),
         };
         let flat: FlatDeploymentConfig = config.clone().into();
         let back: DeploymentConfig = flat.try_into().unwrap();
@@ -0,0 +1,97 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::bail;
+use reqwest::Client;
+use tokio::time::{sleep, Instant};
+use tracing::error;
+
+use crate::{
+    container::Container,
+    db::{Db, InsertCronRun},
+    listener::{Access, Listener, Port},
+    utils::now,
+};
+
+use super::{config::CronJob, deployment::Deployment};
+
+/// Time the app has to get built or started before requests to a path give up
+const START_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Only the end of longer outputs is recorded
+const MAX_LOGS_LENGTH: usize = 64 * 1024;
+
+/// Runs the job against the given deployment and records the run in the db
+#[tracing::instrument(skip(db))]
+pub(crate) async fn run_cron_job(
+    db: &Db,
+    deployment: &Deployment,
+    name: &str,
+    job: &CronJob,
+    manual: bool,
+) {
+    let started = now();
+    let output = match (&job.command, &job.path) {
+        (Some(command), _) => run_command(&deployment.app_container, command).await,
+        (None, Some(path)) => request_path(&deployment.app_container, path).await,
+        (None, None) => Err(anyhow::anyhow!("the job has neither a command nor a path")),
+    };
+    let (status, success, logs) = match output {
+        Ok((status, success, logs)) => (Some(status), success, logs),
+        Err(error) => (None, false, error.to_string()),
+    };
+    let run = InsertCronRun {
+        job: name.to_owned(),
+        manual,
+        started,
+        finished: now(),
+        status,
+        success,
+        logs: keep_end(logs, MAX_LOGS_LENGTH),
+        deployment: deployment.id.clone(),
+    };
+    if let Err(error) = db.insert_cron_run(&deployment.project, run).await {
+        error!("failed to record run of cron job {name}: {error}");
+    }
+}
+
+async fn run_command(container: &Container, command: &str) -> anyhow::Result<(i64, bool, String)> {
+    let (code, logs) = container.run_command(command).await?;
+    let logs = logs
+        .into_iter()
+        .map(|log| log.message)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((code, code == 0, logs))
+}
+
+/// The request counts as activity of the app, same as the ones going through the proxy
+async fn request_path(
+    container: &Arc<Container>,
+    path: &str,
+) -> anyhow::Result<(i64, bool, String)> {
+    let deadline = Instant::now() + START_TIMEOUT;
+    let (socket, _in_flight) = loop {
+        match container.access(&Port::Http).await? {
+            Access::Socket(socket, in_flight) => break (socket, in_flight),
+            Access::Loading if Instant::now() < deadline => sleep(Duration::from_secs(1)).await,
+            Access::Loading => bail!("the deployment took too long to start"),
+        }
+    };
+    let response = Client::new()
+        .get(format!("http://{socket}{path}"))
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    Ok((status.as_u16().into(), status.is_success(), body))
+}
+
+fn keep_end(mut logs: String, max_length: usize) -> String {
+    if logs.len() > max_length {
+        let mut start = logs.len() - max_length;
+        while !logs.is_char_boundary(start) {
+            start += 1;
+        }
+        logs.drain(..start);
+    }
+    logs
+}
@@ -1,6 +1,4 @@
-use std::future::Future;
-use std::pin::Pin;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use futures::{stream, Stream, StreamExt};
 
@@ -8,15 +6,18 @@ use crate::container::commit::CommitContainer;
 use crate::container::ContainerStatus;
 use crate::db::{nano_id::NanoId, BuildResult, Deployment as DbDeployment};
 use crate::hooks::StatusHooks;
+use crate::postgres_db::ProdPostgresDb;
+use crate::redis_db::ProdRedisDb;
+use crate::s3_storage::ProdS3Storage;
 use crate::sqlite_db::ProdSqliteDb;
 use crate::Conf;
 use crate::{
     container::Container,
     db::{Db, DeploymentWithProject},
-    github::Github,
+    source::Sources,
 };
 
-use super::config::Visibility;
+use super::config::{CronJob, Scaling, Visibility};
 use super::worker::WorkerHandle;
 
 #[derive(Debug, Clone)]
@@ -29,35 +30,42 @@ pub(crate) struct Deployment {
     pub(crate) url_id: String,
     pub(crate) timestamp: i64,
     pub(crate) created: i64,
-    pub(crate) forced_prod: bool, // TODO: review if im using this
+    /// scale-to-zero policy from prezel.json, the project one is applied on top
+    pub(crate) scaling: Scaling,
+    /// scheduled jobs from prezel.json, only run while the deployment is prod
+    pub(crate) crons: HashMap<String, CronJob>,
     pub(crate) app_container: Arc<Container>, // FIXME: try to remove Arc, only needed to make access to socket/public generic
 }
 
 impl Deployment {
     pub(crate) fn iter_arc_containers(&self) -> impl Stream<Item = Arc<Container>> + Send + '_ {
-        let containers: [Pin<Box<dyn Future<Output = Option<Arc<Container>>> + Send>>; 2] = [
-            Box::pin(async { Some(self.app_container.clone()) }),
-            Box::pin(async {
-                self.app_container
-                    .status
-                    .read()
-                    .await
-                    .get_db_setup()
-                    .map(|setup| setup.container.clone())
-            }),
-        ];
-        stream::iter(containers).filter_map(|container| container)
+        let app_container = stream::iter([self.app_container.clone()]);
+        let db_containers = stream::once(Box::pin(self.get_db_containers())).flat_map(stream::iter);
+        app_container.chain(db_containers)
+    }
+
+    pub(crate) async fn get_db_containers(&self) -> Vec<Arc<Container>> {
+        let status = self.app_container.status.read().await;
+        status.get_db_setup().containers().collect()
     }
 
     pub(crate) async fn new(
         deployment: DeploymentWithProject,
         build_queue: WorkerHandle,
-        github: Github,
+        sources: Sources,
         db: Db,
         project_db: &ProdSqliteDb,
+        project_postgres: &ProdPostgresDb,
+        project_s3: &ProdS3Storage,
+        project_redis: Option<&ProdRedisDb>,
     ) -> Self {
         let Conf { hostname, .. } = Conf::read_async().await; // TODO: take this from args?
         let db_url = deployment.get_libsql_url(&hostname);
+        let postgres_url = deployment.get_postgres_url(project_postgres.setup.get_password());
+        let s3_endpoint = deployment.get_s3_endpoint(&hostname);
+        let redis_url = project_redis
+            .filter(|_| deployment.config.is_redis_enabled())
+            .map(|redis| (redis, deployment.get_redis_url(redis.setup.get_password())));
         let DeploymentWithProject {
             deployment,
             project,
@@ -81,12 +89,17 @@ impl Deployment {
             Visibility::Private => false,
         };
 
+        let scaling = config.get_scaling();
+        let crons = config.get_crons();
         let env = env.into();
-        let hooks = StatusHooks::new(id.clone(), db, github.clone());
+        let hooks = StatusHooks::new(id.clone(), db, sources.clone());
 
         let (inistial_status, build_result) = match deployment.result {
             Some(BuildResult::Failed) => (ContainerStatus::Failed, Some(BuildResult::Failed)),
             Some(BuildResult::Built) => (ContainerStatus::Built, Some(BuildResult::Built)),
+            Some(BuildResult::Superseded) => {
+                (ContainerStatus::Superseded, Some(BuildResult::Superseded))
+            }
             _ => (
                 ContainerStatus::Queued {
                     trigger_access: None,
@@ -99,9 +112,10 @@ impl Deployment {
         let commit_container = CommitContainer::new(
             build_queue.clone(),
             hooks,
-            github,
-            project.repo_id,
+            sources,
+            project.repo.clone(),
             sha.clone(),
+            &project.id,
             id.clone(),
             env,
             project.root.clone(),
@@ -109,15 +123,18 @@ impl Deployment {
             is_public,
             project_db,
             &db_url,
+            project_postgres,
+            &postgres_url,
+            project_s3,
+            &s3_endpoint,
+            redis_url
+                .as_ref()
+                .map(|(redis, url)| (*redis, url.as_str())),
             inistial_status,
             build_result,
             config,
         );
 
-        let forced_prod = project
-            .prod_id
-            .as_ref()
-            .is_some_and(|prod_id| &id == prod_id);
         Self {
             branch,
             default_branch,
@@ -127,7 +144,8 @@ impl Deployment {
             url_id,
             timestamp,
             created,
-            forced_prod,
+            scaling,
+            crons,
             app_container: commit_container.into(),
         }
     }
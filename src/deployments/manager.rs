@@ -1,23 +1,38 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
 use pingora::tls;
-use tokio::sync::RwLock;
+use tokio::{
+    sync::{OnceCell, RwLock},
+    time::sleep,
+};
+use tracing::error;
 
 use crate::{
-    container::Container,
-    db::{nano_id::NanoId, Db},
-    github::Github,
+    container::{commit::get_libsql_token_env, Container},
+    cron::Schedule,
+    db::{nano_id::NanoId, BuildResult, Db},
     label::Label,
-    sqlite_db::SqliteDbSetup,
+    listener::Port,
+    paths::get_prod_libsql_keys_dir,
+    source::Sources,
+    sqlite_db::{rotate_keys, SqliteDbSetup, KEY_GRACE_PERIOD},
     tls::{CertificateStore, TlsState},
-    utils::LogError,
+    utils::{now, LogError},
 };
 
 use super::{
+    config::CronJob,
+    cron::run_cron_job,
     deployment::Deployment,
     map::DeploymentMap,
     worker::{Worker, WorkerHandle},
-    workers::{build::BuildWorker, docker::DockerWorker, files::FilesWorker, github::GithubWorker},
+    workers::{
+        backup::BackupWorker, build::BuildWorker, cutover::CutoverWorker, docker::DockerWorker,
+        files::FilesWorker, health::HealthWorker, processes::ProcessesWorker,
+        replicas::ReplicasWorker, source::SourceWorker,
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -25,15 +40,22 @@ pub(crate) struct Manager {
     pub(crate) box_domain: String,
     deployments: Arc<InstrumentedRwLock<DeploymentMap>>,
     build_worker: Arc<WorkerHandle>,
-    github_worker: Arc<WorkerHandle>,
+    cutover_worker: Arc<WorkerHandle>,
+    source_worker: Arc<WorkerHandle>,
     docker_worker: Arc<WorkerHandle>,
     files_worker: Arc<WorkerHandle>,
+    backup_worker: Arc<WorkerHandle>,
+    health_worker: Arc<WorkerHandle>,
+    replicas_worker: Arc<WorkerHandle>,
+    processes_worker: Arc<WorkerHandle>,
     db: Db,
-    github: Github,
+    sources: Sources,
+    /// set once the containers left by the previous run have been adopted
+    adopted: Arc<OnceCell<()>>,
 }
 
 // workers:
-// - github worker
+// - source worker
 // - db worker
 // - build worker
 
@@ -41,25 +63,33 @@ impl Manager {
     #[tracing::instrument]
     pub(crate) fn new(
         box_domain: String,
-        github: Github,
+        sources: Sources,
         db: Db,
         certificates: CertificateStore,
     ) -> Self {
         let deployments: Arc<_> = InstrumentedRwLock::new(DeploymentMap::new(certificates)).into();
 
-        let github_clone = github.clone();
+        let deployments_clone = deployments.clone();
+        let cutover_worker: Arc<_> = CutoverWorker::start(|_| CutoverWorker {
+            map: deployments_clone,
+        })
+        .into();
+
+        let sources_clone = sources.clone();
         let db_clone = db.clone();
         let deployments_clone = deployments.clone();
+        let cutover_queue = cutover_worker.as_ref().clone();
         let build_worker: Arc<_> = BuildWorker::start(move |build_queue| BuildWorker {
             map: deployments_clone,
             db: db_clone,
-            github: github_clone,
+            sources: sources_clone,
             build_queue,
+            cutover_queue,
         })
         .into();
 
-        let github_worker = GithubWorker::start(|_| GithubWorker {
-            github: github.clone(),
+        let source_worker = SourceWorker::start(|_| SourceWorker {
+            sources: sources.clone(),
             db: db.clone(),
         })
         .into();
@@ -76,30 +106,145 @@ impl Manager {
         })
         .into();
 
+        let deployments_clone = deployments.clone();
+        let backup_worker = BackupWorker::start(|_| BackupWorker {
+            map: deployments_clone,
+            db: db.clone(),
+        })
+        .into();
+
+        let deployments_clone = deployments.clone();
+        let health_worker = HealthWorker::start(|_| HealthWorker {
+            map: deployments_clone,
+        })
+        .into();
+
+        let deployments_clone = deployments.clone();
+        let replicas_worker = ReplicasWorker::start(|_| ReplicasWorker {
+            map: deployments_clone,
+        })
+        .into();
+
+        let deployments_clone = deployments.clone();
+        let processes_worker = ProcessesWorker::start(|_| ProcessesWorker {
+            map: deployments_clone,
+        })
+        .into();
+
         let manager = Self {
             deployments,
             box_domain,
             build_worker,
-            github_worker,
+            cutover_worker,
+            source_worker,
             docker_worker,
             files_worker,
+            backup_worker,
+            health_worker,
+            replicas_worker,
+            processes_worker,
             db,
-            github,
+            sources,
+            adopted: Default::default(),
         };
 
-        // TODO: reset the timer every time full_sync_with_github is executed triggered by something else
+        // TODO: reset the timer every time full_sync_with_repos is executed triggered by something else
         let cloned_manager = manager.clone();
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(60 * 5)); // Every 5 minutes
             loop {
                 interval.tick().await;
-                cloned_manager.full_sync_with_github().await;
+                cloned_manager.full_sync_with_repos().await;
+            }
+        });
+
+        // the worker only backs up the dbs whose latest backup is old enough
+        let cloned_manager = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60)); // Every hour
+            loop {
+                interval.tick().await;
+                cloned_manager.backup_worker.trigger();
+            }
+        });
+
+        let cloned_manager = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1)); // Every second
+            loop {
+                interval.tick().await;
+                cloned_manager.health_worker.trigger();
+            }
+        });
+
+        let cloned_manager = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5)); // Every 5 seconds
+            loop {
+                interval.tick().await;
+                cloned_manager.replicas_worker.trigger();
+            }
+        });
+
+        let cloned_manager = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(10)); // Every 10 seconds
+            loop {
+                interval.tick().await;
+                cloned_manager.processes_worker.trigger();
+            }
+        });
+
+        // cron jobs are checked right after every minute starts
+        let cloned_manager = manager.clone();
+        tokio::spawn(async move {
+            loop {
+                let until_next_minute = 60_000 - now() % 60_000;
+                sleep(Duration::from_millis(until_next_minute as u64)).await;
+                if let Some(time) = DateTime::from_timestamp_millis(now()) {
+                    cloned_manager.run_due_cron_jobs(time).await;
+                }
             }
         });
 
         manager
     }
 
+    /// Starts the jobs of the prod deployments scheduled for the minute of the given time,
+    /// without waiting for them to finish
+    #[tracing::instrument]
+    async fn run_due_cron_jobs(&self, time: DateTime<Utc>) {
+        let map = self.deployments.read().await;
+        for deployment in map.iter_prod_deployments() {
+            for (name, job) in &deployment.crons {
+                let schedule = Schedule::parse(&job.schedule);
+                if schedule.is_ok_and(|schedule| schedule.matches(&time)) {
+                    self.spawn_cron_job(deployment.clone(), name.clone(), job.clone(), false);
+                }
+            }
+        }
+    }
+
+    /// Runs the job of the prod deployment right away, returning false if there is no such job
+    #[tracing::instrument]
+    pub(crate) async fn trigger_cron_job(&self, project: &NanoId, name: &str) -> bool {
+        let Some(deployment) = self.get_prod_deployment(project).await else {
+            return false;
+        };
+        let Some(job) = deployment.crons.get(name).cloned() else {
+            return false;
+        };
+        self.spawn_cron_job(deployment, name.to_owned(), job, true);
+        true
+    }
+
+    fn spawn_cron_job(&self, deployment: Deployment, name: String, job: CronJob, manual: bool) {
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            run_cron_job(&db, &deployment, &name, &job, manual).await;
+        });
+    }
+
     pub(crate) async fn get_main_certificate(&self) -> anyhow::Result<tls::x509::X509> {
         let main_cert = self
             .deployments
@@ -126,23 +271,26 @@ impl Manager {
             .collect()
     }
 
+    /// Returns the container for the hostname, whether the insert script is enabled
+    /// and the port of the container requests should be routed to
     #[tracing::instrument]
     pub(crate) async fn get_container_by_hostname(
         &self,
         hostname: &str,
-    ) -> Option<(Arc<Container>, bool)> {
+    ) -> Option<(Arc<Container>, bool, Port)> {
         let container = {
             let deployments = self.deployments.read().await;
             let deployment = deployments.get_custom_domain(hostname);
             deployment.map(|deployment| deployment.app_container.clone())
         };
         if let Some(container) = container {
-            Some((container, false))
+            Some((container, false, Port::Http))
         } else {
             let label = Label::strip_from_domain(hostname, &self.box_domain).ok()?;
             let insert_enabled = label.insert_enabled();
+            let port = label.port();
             let container = self.get_container_by_label(label).await?;
-            Some((container, insert_enabled))
+            Some((container, insert_enabled, port))
         }
     }
 
@@ -150,7 +298,7 @@ impl Manager {
     async fn get_container_by_label(&self, label: Label) -> Option<Arc<Container>> {
         let map = self.deployments.read().await;
         match label {
-            Label::Prod { project } => {
+            Label::Prod { project } | Label::ProdPort { project, .. } => {
                 let deployment = map.get_prod(&project)?;
                 Some(deployment.app_container.clone())
             }
@@ -161,6 +309,11 @@ impl Manager {
             | Label::DeploymentInsert {
                 project,
                 deployment,
+            }
+            | Label::DeploymentPort {
+                project,
+                deployment,
+                ..
             } => {
                 let deployment = map.get_deployment_by_name(&project, deployment)?;
                 Some(deployment.app_container.clone())
@@ -175,11 +328,82 @@ impl Manager {
                     .read()
                     .await
                     .get_db_setup()
+                    .libsql
                     .map(|setup| setup.container.clone())
             }
             Label::ProdDb { project } => map
                 .get_prod_db(&project)
                 .map(|setup| setup.container.clone()),
+            Label::BranchPostgres {
+                project,
+                deployment,
+            } => {
+                let deployment = map.get_deployment_by_id(project, deployment)?;
+                let status = &deployment.app_container.status;
+                status
+                    .read()
+                    .await
+                    .get_db_setup()
+                    .postgres
+                    .map(|setup| setup.container.clone())
+            }
+            Label::ProdPostgres { project } => map
+                .get_prod_postgres(&project)
+                .map(|setup| setup.container.clone()),
+            Label::BranchS3 {
+                project,
+                deployment,
+            } => {
+                let deployment = map.get_deployment_by_id(project, deployment)?;
+                let status = &deployment.app_container.status;
+                status
+                    .read()
+                    .await
+                    .get_db_setup()
+                    .s3
+                    .map(|setup| setup.container.clone())
+            }
+            Label::ProdS3 { project } => map
+                .get_prod_s3(&project)
+                .map(|setup| setup.container.clone()),
+            Label::BranchRedis {
+                project,
+                deployment,
+            } => {
+                let deployment = map.get_deployment_by_id(project, deployment)?;
+                let status = &deployment.app_container.status;
+                status
+                    .read()
+                    .await
+                    .get_db_setup()
+                    .redis
+                    .map(|setup| setup.container.clone())
+            }
+            Label::ProdRedis { project } => map
+                .get_prod_redis(&project)
+                .map(|setup| setup.container.clone()),
+        }
+    }
+
+    /// the postgres proxy only routes connections to postgres containers
+    #[tracing::instrument]
+    pub(crate) async fn get_postgres_container(&self, label: &str) -> Option<Arc<Container>> {
+        match Label::parse(label).ok()? {
+            label @ (Label::ProdPostgres { .. } | Label::BranchPostgres { .. }) => {
+                self.get_container_by_label(label).await
+            }
+            _ => None,
+        }
+    }
+
+    /// the redis proxy only routes connections to redis containers
+    #[tracing::instrument]
+    pub(crate) async fn get_redis_container(&self, label: &str) -> Option<Arc<Container>> {
+        match Label::parse(label).ok()? {
+            label @ (Label::ProdRedis { .. } | Label::BranchRedis { .. }) => {
+                self.get_container_by_label(label).await
+            }
+            _ => None,
         }
     }
 
@@ -206,6 +430,66 @@ impl Manager {
         self.deployments.read().await.get_prod_db(project)
     }
 
+    /// The prod sqld container and the deployments using the prod db are restarted
+    /// so they pick up the new key. Once the grace period is over, the sqld container
+    /// is restarted again so it stops accepting the previous keys
+    #[tracing::instrument]
+    pub(crate) async fn rotate_prod_db_keys(
+        &self,
+        project: &NanoId,
+        revoke: bool,
+    ) -> anyhow::Result<()> {
+        rotate_keys(&get_prod_libsql_keys_dir(project.as_str()), revoke)?;
+        self.reload_prod_db(project, true).await?;
+        if !revoke {
+            let manager = self.clone();
+            let project = project.clone();
+            tokio::spawn(async move {
+                let grace_period = Duration::from_millis(KEY_GRACE_PERIOD as u64);
+                tokio::time::sleep(grace_period).await;
+                manager
+                    .reload_prod_db(&project, false)
+                    .await
+                    .ignore_logging();
+            });
+        }
+        Ok(())
+    }
+
+    /// Restarts the prod sqld container with the keys currently on disk and, if `redeploy` is set,
+    /// the app containers using the prod db with a token signed by the new key. The containers
+    /// are restarted outside of the map lock, as that involves waiting for them to be online
+    #[tracing::instrument]
+    async fn reload_prod_db(&self, project: &NanoId, redeploy: bool) -> anyhow::Result<()> {
+        let (db, key_env, apps) = {
+            let mut map = self.deployments.write().await;
+            let Some(prod_db) = map.dbs.get_mut(project) else {
+                return Ok(());
+            };
+            let key_env = prod_db.reload_keys(project);
+            let db = prod_db.setup.clone();
+            // branch deployments are left alone as they have their own keys
+            let apps: Vec<_> = map
+                .deployments
+                .iter()
+                .filter(|((deployment_project, _), deployment)| {
+                    redeploy && deployment_project == project && deployment.default_branch
+                })
+                .map(|(_, deployment)| deployment.app_container.clone())
+                .collect();
+            (db, key_env, apps)
+        };
+        db.container.restart_with_env(key_env).await?;
+        let token_env = get_libsql_token_env(db.auth.get_permanent_token());
+        let restarts = apps
+            .iter()
+            .map(|app| app.restart_with_env(token_env.clone()));
+        for result in join_all(restarts).await {
+            result.ignore_logging();
+        }
+        Ok(())
+    }
+
     #[tracing::instrument]
     pub(crate) async fn get_prod_url_id(&self, project: &NanoId) -> Option<String> {
         let map = self.deployments.read().await;
@@ -214,21 +498,67 @@ impl Manager {
 
     #[tracing::instrument]
     pub(crate) async fn sync_with_db(&self) {
+        // nothing gets built or removed by the workers before the first sync
+        self.adopted.get_or_init(|| self.adopt_containers()).await;
         self.deployments
             .write()
             .await
-            .read_db_and_build_updates(&self.build_worker, &self.github, &self.db)
+            .read_db_and_build_updates(&self.build_worker, &self.sources, &self.db)
             .await
             .ignore_logging();
         self.build_worker.trigger();
+        self.cutover_worker.trigger();
         self.docker_worker.trigger();
         self.files_worker.trigger();
     }
 
+    /// Picks up the images and containers left by the previous run of prezel, so prod keeps
+    /// serving across restarts without being built or started again. Deployments are adopted
+    /// concurrently and outside of the map lock, as that involves probing their containers
+    #[tracing::instrument]
+    async fn adopt_containers(&self) {
+        let deployments = match self.db.get_deployments().await {
+            Ok(deployments) => deployments,
+            Err(error) => {
+                error!("could not read the deployments to adopt: {error}");
+                return;
+            }
+        };
+        let adoptable: HashMap<_, _> = deployments
+            .into_iter()
+            .filter(|deployment| deployment.result == Some(BuildResult::Built))
+            .filter_map(|deployment| {
+                Some((deployment.id, (deployment.image?, deployment.containers)))
+            })
+            .collect();
+        let containers: Vec<_> = {
+            let mut map = self.deployments.write().await;
+            map.read_db_and_build_updates(&self.build_worker, &self.sources, &self.db)
+                .await
+                .ignore_logging();
+            map.deployments
+                .values()
+                .filter_map(|deployment| {
+                    let (image, containers) = adoptable.get(&deployment.id)?;
+                    let container = deployment.app_container.clone();
+                    Some((deployment.id.clone(), container, image, containers))
+                })
+                .collect()
+        };
+        let adoptions = containers
+            .into_iter()
+            .map(|(id, container, image, names)| async move {
+                if let Err(error) = container.adopt(image, names).await {
+                    error!("could not adopt the image of deployment {id}: {error}");
+                }
+            });
+        join_all(adoptions).await;
+    }
+
     /// this triggers all the sync workflows downstream
     #[tracing::instrument]
-    pub(crate) async fn full_sync_with_github(&self) {
-        self.github_worker.trigger_and_wait().await;
+    pub(crate) async fn full_sync_with_repos(&self) {
+        self.source_worker.trigger_and_wait().await;
         self.sync_with_db().await;
     }
 }
@@ -1,29 +1,39 @@
 use std::{
     collections::{HashMap, HashSet},
-    future::Future,
     sync::Arc,
+    time::Duration,
 };
 
 use futures::{stream, Stream, StreamExt};
 use tracing::error;
 
 use crate::{
+    conf::{Conf, Cutover},
     container::{Container, ContainerStatus},
     db::{nano_id::NanoId, BuildResult, Db},
-    github::Github,
+    postgres_db::{PostgresDbSetup, ProdPostgresDb},
+    redis_db::{ProdRedisDb, RedisDbSetup},
+    s3_storage::{ProdS3Storage, S3Setup},
+    source::Sources,
     sqlite_db::{ProdSqliteDb, SqliteDbSetup},
     tls::CertificateStore,
 };
 
-use super::{deployment::Deployment, worker::WorkerHandle};
+use super::{config::Scaling, deployment::Deployment, worker::WorkerHandle};
 
 #[derive(Debug)]
 pub(crate) struct DeploymentMap {
     pub(crate) dbs: HashMap<NanoId, ProdSqliteDb>, // project id -> prod db
+    pub(crate) postgres: HashMap<NanoId, ProdPostgresDb>, // project id -> prod postgres
+    pub(crate) s3: HashMap<NanoId, ProdS3Storage>, // project id -> prod object storage
+    /// only for projects with deployments asking for redis
+    pub(crate) redis: HashMap<NanoId, ProdRedisDb>, // project id -> prod redis
     /// FIXME: this having a tuple (NanoId, String) as the key means every time I access I need to clone two strings. There has to be another way
     pub(crate) deployments: HashMap<(NanoId, String), Deployment>, // project id + deployment slug -> deployment
     /// values here used to be options, but removing them from the map should be enough
     pub(crate) prod: HashMap<NanoId, String>, // project id -> deployment slug
+    /// deployments being started to replace the prod one, which keeps serving in the meantime
+    pub(crate) pending_prod: HashMap<NanoId, String>, // project id -> deployment slug
     pub(crate) names: HashMap<String, NanoId>, // project name -> project id
     pub(crate) certificates: CertificateStore,
     pub(crate) custom_domains: HashMap<String, NanoId>, // domain -> project id
@@ -33,8 +43,12 @@ impl DeploymentMap {
     pub(crate) fn new(store: CertificateStore) -> Self {
         Self {
             dbs: Default::default(),
+            postgres: Default::default(),
+            s3: Default::default(),
+            redis: Default::default(),
             deployments: Default::default(),
             prod: Default::default(),
+            pending_prod: Default::default(),
             names: Default::default(),
             custom_domains: Default::default(),
             certificates: store,
@@ -44,9 +58,16 @@ impl DeploymentMap {
     #[tracing::instrument]
     pub(crate) fn iter_containers(&self) -> impl Stream<Item = Arc<Container>> + Send + '_ {
         let prod_dbs = self.dbs.values().map(|db| db.setup.container.clone());
+        let prod_postgres = self.postgres.values().map(|db| db.setup.container.clone());
+        let prod_s3 = self.s3.values().map(|s3| s3.setup.container.clone());
+        let prod_redis = self.redis.values().map(|db| db.setup.container.clone());
+        let prod_containers = prod_dbs
+            .chain(prod_postgres)
+            .chain(prod_s3)
+            .chain(prod_redis);
         let deployments = stream::iter(self.deployments.iter())
             .flat_map(|(_, deployment)| deployment.iter_arc_containers());
-        stream::iter(prod_dbs).chain(deployments)
+        stream::iter(prod_containers).chain(deployments)
     }
 
     #[tracing::instrument]
@@ -87,11 +108,64 @@ impl DeploymentMap {
         self.get_prod_from_id(project_id)
     }
 
+    #[tracing::instrument]
+    pub(crate) fn is_prod_container(&self, container: &Arc<Container>) -> bool {
+        self.iter_prod_deployments()
+            .any(|deployment| Arc::ptr_eq(&deployment.app_container, container))
+    }
+
+    #[tracing::instrument]
+    pub(crate) fn get_pending_cutovers(&self) -> Vec<(NanoId, String, Arc<Container>)> {
+        self.pending_prod
+            .iter()
+            .filter_map(|(project, slug)| {
+                let deployment = self.deployments.get(&(project.clone(), slug.clone()))?;
+                Some((
+                    project.clone(),
+                    slug.clone(),
+                    deployment.app_container.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Routes prod traffic to the pending deployment, returning the container of the previous prod
+    /// deployment. Nothing happens if the pending deployment changed in the meantime
+    #[tracing::instrument]
+    pub(crate) fn complete_cutover(
+        &mut self,
+        project: &NanoId,
+        slug: &str,
+    ) -> Option<Arc<Container>> {
+        if self.pending_prod.get(project).map(String::as_str) != Some(slug) {
+            return None;
+        }
+        self.pending_prod.remove(project);
+        let previous = self.prod.insert(project.clone(), slug.to_owned())?;
+        let deployment = self.deployments.get(&(project.clone(), previous))?;
+        Some(deployment.app_container.clone())
+    }
+
     #[tracing::instrument]
     pub(crate) fn get_prod_db(&self, id: &NanoId) -> Option<SqliteDbSetup> {
         self.dbs.get(id).map(|db| db.setup.clone())
     }
 
+    #[tracing::instrument]
+    pub(crate) fn get_prod_postgres(&self, id: &NanoId) -> Option<PostgresDbSetup> {
+        self.postgres.get(id).map(|db| db.setup.clone())
+    }
+
+    #[tracing::instrument]
+    pub(crate) fn get_prod_s3(&self, id: &NanoId) -> Option<S3Setup> {
+        self.s3.get(id).map(|s3| s3.setup.clone())
+    }
+
+    #[tracing::instrument]
+    pub(crate) fn get_prod_redis(&self, id: &NanoId) -> Option<RedisDbSetup> {
+        self.redis.get(id).map(|db| db.setup.clone())
+    }
+
     #[tracing::instrument]
     pub(crate) fn get_custom_domain(&self, domain: &str) -> Option<&Deployment> {
         let project = self.custom_domains.get(domain)?;
@@ -104,7 +178,7 @@ impl DeploymentMap {
     pub(crate) async fn read_db_and_build_updates(
         &mut self,
         build_queue: &WorkerHandle,
-        github: &Github,
+        sources: &Sources,
         db: &Db,
     ) -> anyhow::Result<()> {
         let required_deployments = db.get_deployments_with_project().await?.collect::<Vec<_>>();
@@ -123,6 +197,10 @@ impl DeploymentMap {
             .iter()
             .map(|(id, project)| (project.name.clone(), id.clone()))
             .collect();
+        let scaling = projects
+            .iter()
+            .map(|(id, project)| (id.clone(), project.scaling))
+            .collect::<HashMap<_, _>>();
 
         // sync map.custom_domains
         self.custom_domains = projects
@@ -144,6 +222,30 @@ impl DeploymentMap {
                     ProdSqliteDb::new(&project_id, build_queue.clone()).unwrap(),
                 );
             }
+            if !self.postgres.contains_key(project_id) {
+                self.postgres.insert(
+                    project_id.clone(),
+                    ProdPostgresDb::new(&project_id, build_queue.clone()),
+                );
+            }
+            if !self.s3.contains_key(project_id) {
+                self.s3.insert(
+                    project_id.clone(),
+                    ProdS3Storage::new(&project_id, build_queue.clone()),
+                );
+            }
+        }
+        let redis_projects = required_deployments
+            .iter()
+            .filter(|deployment| deployment.config.is_redis_enabled())
+            .map(|deployment| &deployment.project.id);
+        for project_id in redis_projects {
+            if !self.redis.contains_key(project_id) {
+                self.redis.insert(
+                    project_id.clone(),
+                    ProdRedisDb::new(project_id, build_queue.clone()),
+                );
+            }
         }
 
         // sync map.certificates
@@ -155,6 +257,16 @@ impl DeploymentMap {
             // TODO: should also remove unneeded certificates?
         }
 
+        // cancel the builds superseded by newer commits
+        for deployment in &required_deployments {
+            if deployment.result == Some(BuildResult::Superseded) {
+                let key = (deployment.project.id.clone(), deployment.url_id.clone());
+                if let Some(existing) = self.deployments.get(&key) {
+                    existing.app_container.supersede().await;
+                }
+            }
+        }
+
         // sync map.deployments
         for deployment in required_deployments {
             if !self.deployments.contains_key(&(
@@ -163,13 +275,20 @@ impl DeploymentMap {
             )) {
                 let project = deployment.project.id.clone();
                 let url_id = deployment.deployment.url_id.clone();
-                if let Some(prod_db) = self.dbs.get(&project) {
+                if let (Some(prod_db), Some(prod_postgres), Some(prod_s3)) = (
+                    self.dbs.get(&project),
+                    self.postgres.get(&project),
+                    self.s3.get(&project),
+                ) {
                     let deployment = Deployment::new(
                         deployment,
                         build_queue.clone(),
-                        github.clone(),
+                        sources.clone(),
                         db.clone(),
                         prod_db,
+                        prod_postgres,
+                        prod_s3,
+                        self.redis.get(&project),
                     )
                     .await;
                     self.deployments.insert((project, url_id), deployment);
@@ -186,8 +305,8 @@ impl DeploymentMap {
         }
 
         // sync map.prod
-        self.prod = stream::iter(projects)
-            .map(|(id, _)| {
+        let next_prod: HashMap<NanoId, String> = stream::iter(projects)
+            .map(|(id, project)| {
                 let project_deployments = self
                     .deployments
                     .iter()
@@ -198,26 +317,34 @@ impl DeploymentMap {
                             deployment.app_container.clone(),
                             deployment.created,
                             deployment.url_id.clone(),
+                            deployment.id.clone(),
                         )
                     })
                     .collect::<Vec<_>>();
-                (id, project_deployments)
+                (id, project.prod_id.clone(), project_deployments)
             })
-            .filter_map(|(id, project_deployments)| async move {
-                // TODO: bear in mind prod id saved in the db
+            .filter_map(|(id, pinned, project_deployments)| async move {
+                // a pinned deployment takes precedence as long as it still exists
+                let pinned_prod_id = project_deployments
+                    .iter()
+                    .find(|(_, _, _, deployment_id)| Some(deployment_id) == pinned.as_ref())
+                    .map(|(_, _, slug, _)| slug.clone());
+                if let Some(prod_id) = pinned_prod_id {
+                    return Some((id, prod_id));
+                }
                 let latest_prod_id = project_deployments
                     .iter()
-                    .max_by_key(|(_, created, _)| created)
-                    .map(|(_, _, slug)| slug.clone());
+                    .max_by_key(|(_, created, _, _)| created)
+                    .map(|(_, _, slug, _)| slug.clone());
                 let latest_successful_prod_id =
                         stream::iter(project_deployments)
-                            .filter(|(app_container, _, _)| {
+                            .filter(|(app_container, _, _, _)| {
                                 let app_container = app_container.clone();
                                 async move {
                                     *app_container.result.read().await == Some(BuildResult::Built)
                                 }
                             })
-                            .fold((0, None), |current, (_, created, url_id)| async move {
+                            .fold((0, None), |current, (_, created, url_id, _)| async move {
                                 if created > current.0 {
                                     (created, Some(url_id.clone()))
                                 } else {
@@ -258,8 +385,42 @@ impl DeploymentMap {
         //     .collect()
         //     .await;
 
+        // with blue/green cutovers, the current prod deployment keeps serving
+        // until the next one has been started by the cutover worker
+        let Conf { prod_cutover, .. } = Conf::read_async().await;
+        self.pending_prod.clear();
+        let mut prod = HashMap::new();
+        for (project, slug) in next_prod {
+            let current = self
+                .prod
+                .get(&project)
+                .filter(|current| *current != &slug)
+                .and_then(|current| self.deployments.get(&(project.clone(), current.clone())));
+            let next = self.deployments.get(&(project.clone(), slug.clone()));
+            let keep_current = match (prod_cutover, current, next) {
+                (Cutover::BlueGreen, Some(current), Some(next)) => {
+                    is_ready(&current.app_container).await && !is_ready(&next.app_container).await
+                }
+                _ => false,
+            };
+            match current {
+                Some(current) if keep_current => {
+                    prod.insert(project.clone(), current.url_id.clone());
+                    self.pending_prod.insert(project, slug);
+                }
+                _ => {
+                    prod.insert(project, slug);
+                }
+            }
+        }
+        self.prod = prod;
+
         // force build prod containers
-        for deployment in self.iter_prod_deployments() {
+        let pending_prod_deployments = self
+            .pending_prod
+            .iter()
+            .filter_map(|(project, slug)| self.deployments.get(&(project.clone(), slug.clone())));
+        for deployment in self.iter_prod_deployments().chain(pending_prod_deployments) {
             let status = deployment.app_container.status.read().await.clone();
             match status {
                 // the logic to put containers into the queue is a bit duplicated.
@@ -273,48 +434,114 @@ impl DeploymentMap {
         }
 
         // downgrade unused containers
-        for container in self.get_all_non_prod_containers().await {
-            container.downgrade_if_unused().await;
+        for (container, idle_timeout) in self.get_idle_timeouts(&scaling).await {
+            if let Some(idle_timeout) = idle_timeout {
+                container.downgrade_if_unused(idle_timeout).await;
+            }
         }
 
         Ok(())
     }
 
     #[tracing::instrument]
-    fn iter_prod_deployments(&self) -> impl Iterator<Item = &Deployment> {
+    pub(crate) fn iter_prod_deployments(&self) -> impl Iterator<Item = &Deployment> {
         self.names
             .keys()
             .filter_map(|project| self.get_prod(project))
     }
 
-    // TODO: once I get this to be Send, change signature back to async fn ...
+    /// Idle timeout of every container according to the scale-to-zero policy of its deployment,
+    /// None meaning always on. Containers shared by several deployments get the longest timeout
+    /// among them, and the prod dbs follow the policy of the prod deployment of their project
     #[tracing::instrument]
-    fn get_all_non_prod_containers(&self) -> impl Future<Output = Vec<Arc<Container>>> + Send + '_ {
-        let prod_deployment_ids = self
-            .iter_prod_deployments()
-            .map(|deployment| deployment.id.clone())
-            .collect::<Vec<_>>();
-        let all_containers_from_non_prod_deployments = stream::iter(
-            self.deployments
-                .values()
-                .filter(move |deployment| !prod_deployment_ids.contains(&deployment.id)),
-        )
-        .flat_map(|deployment| deployment.iter_arc_containers());
-
-        let db_containers_from_prod_deployments = stream::iter(self.iter_prod_deployments())
-            .filter_map(|deployment| async {
-                // FIXME: some boilerplate here, could have deployment.get_db_container
-                deployment
-                    .app_container
-                    .status
-                    .read()
-                    .await
-                    .get_db_setup()
-                    .map(|setup| setup.container.clone())
-            });
-
-        all_containers_from_non_prod_deployments
-            .chain(db_containers_from_prod_deployments)
-            .collect()
+    async fn get_idle_timeouts(
+        &self,
+        scaling: &HashMap<NanoId, Scaling>,
+    ) -> Vec<(Arc<Container>, Option<Duration>)> {
+        let mut timeouts: Vec<(Arc<Container>, Option<Duration>)> = vec![];
+        for ((project, slug), deployment) in &self.deployments {
+            let project_scaling = scaling.get(project).copied().unwrap_or_default();
+            let policy = deployment.scaling.or(project_scaling);
+            // pending cutovers are about to serve prod traffic
+            let is_prod = self.prod.get(project) == Some(slug)
+                || self.pending_prod.get(project) == Some(slug);
+            let app_timeout = if is_prod {
+                policy.get_prod_timeout()
+            } else {
+                policy.get_preview_timeout()
+            };
+            let db_timeout = policy.get_db_timeout();
+            let db_containers = deployment.get_db_containers().await;
+            let containers = db_containers
+                .into_iter()
+                .map(|container| (container, db_timeout))
+                .chain([(deployment.app_container.clone(), app_timeout)]);
+            for (container, timeout) in containers {
+                insert_timeout(&mut timeouts, container, timeout);
+            }
+        }
+        let prod_dbs = self
+            .dbs
+            .iter()
+            .map(|(project, db)| (project, db.setup.container.clone()));
+        let prod_postgres = self
+            .postgres
+            .iter()
+            .map(|(project, db)| (project, db.setup.container.clone()));
+        let prod_s3 = self
+            .s3
+            .iter()
+            .map(|(project, s3)| (project, s3.setup.container.clone()));
+        let prod_redis = self
+            .redis
+            .iter()
+            .map(|(project, db)| (project, db.setup.container.clone()));
+        let prod_containers = prod_dbs
+            .chain(prod_postgres)
+            .chain(prod_s3)
+            .chain(prod_redis);
+        for (project, container) in prod_containers {
+            let timeout = self.get_prod_db_timeout(project, scaling);
+            insert_timeout(&mut timeouts, container, timeout);
+        }
+        timeouts
     }
+
+    fn get_prod_db_timeout(
+        &self,
+        project: &NanoId,
+        scaling: &HashMap<NanoId, Scaling>,
+    ) -> Option<Duration> {
+        let project_scaling = scaling.get(project).copied().unwrap_or_default();
+        let prod_scaling = self
+            .get_prod_from_id(project)
+            .map(|deployment| deployment.scaling)
+            .unwrap_or_default();
+        prod_scaling.or(project_scaling).get_db_timeout()
+    }
+}
+
+fn insert_timeout(
+    timeouts: &mut Vec<(Arc<Container>, Option<Duration>)>,
+    container: Arc<Container>,
+    timeout: Option<Duration>,
+) {
+    let existing = timeouts
+        .iter_mut()
+        .find(|(existing, _)| Arc::ptr_eq(existing, &container));
+    match existing {
+        Some((_, existing)) => *existing = longest(*existing, timeout),
+        None => timeouts.push((container, timeout)),
+    }
+}
+
+fn longest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    Some(a?.max(b?))
+}
+
+async fn is_ready(container: &Container) -> bool {
+    matches!(
+        *container.status.read().await,
+        ContainerStatus::Ready { .. }
+    )
 }
@@ -1,6 +1,7 @@
 pub(crate) mod config;
+pub(crate) mod cron;
 pub(crate) mod deployment;
 pub(crate) mod manager;
 mod map;
 pub(crate) mod worker;
-mod workers;
+pub(crate) mod workers;
@@ -22,7 +22,10 @@ use tokio::sync::{
 pub(crate) trait Worker: Sized + Sync + Send + 'static {
     fn start<F: FnOnce(WorkerHandle) -> Self>(constructor: F) -> WorkerHandle {
         let (sender, receiver) = channel::<Arc<Notify>>(1000); // TODO: review this size
-        let handle = WorkerHandle { sender };
+        let handle = WorkerHandle {
+            sender,
+            triggered: Default::default(),
+        };
         let worker = constructor(handle.clone());
 
         tokio::spawn(async move {
@@ -46,16 +49,25 @@ pub(crate) trait Worker: Sized + Sync + Send + 'static {
 #[derive(Debug, Clone)]
 pub(crate) struct WorkerHandle {
     sender: Sender<Arc<Notify>>,
+    triggered: Arc<Notify>,
 }
 
 impl WorkerHandle {
     pub(crate) fn trigger(&self) {
         let _ = self.sender.try_send(Notify::new().into());
+        self.triggered.notify_one();
     }
 
     pub(crate) async fn trigger_and_wait(&self) {
         let notify: Arc<_> = Notify::new().into();
         let _ = self.sender.try_send(notify.clone()); // FIXME: stop ignoring errors here?
+        self.triggered.notify_one();
         notify.notified().await;
     }
+
+    /// Resolves once the worker gets triggered, even if that happened while nobody was waiting.
+    /// Meant for workers that keep working for long and need to react to new triggers meanwhile
+    pub(crate) async fn triggered(&self) {
+        self.triggered.notified().await;
+    }
 }
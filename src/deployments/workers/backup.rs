@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use tracing::error;
+
+use crate::{
+    backups::{create_backup, list_backups, prune_backups},
+    db::{Db, Project},
+    deployments::{manager::InstrumentedRwLock, map::DeploymentMap, worker::Worker},
+    utils::now,
+};
+
+const BACKUP_INTERVAL: i64 = 24 * 60 * 60 * 1000; // one day in millis
+
+#[derive(Debug)]
+pub(crate) struct BackupWorker {
+    pub(crate) map: Arc<InstrumentedRwLock<DeploymentMap>>,
+    pub(crate) db: Db,
+}
+
+impl Worker for BackupWorker {
+    #[tracing::instrument]
+    fn work(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {
+            // if there is some error when trying to read from the db, we simply skip the work
+            let _ = self.backup_work().await.inspect_err(|e| error!("{e}"));
+        }
+    }
+}
+
+impl BackupWorker {
+    #[tracing::instrument]
+    async fn backup_work(&self) -> anyhow::Result<()> {
+        for Project {
+            id,
+            backup_retention,
+            ..
+        } in self.db.get_projects().await?
+        {
+            let Some(setup) = self.map.read().await.get_prod_db(&id) else {
+                continue;
+            };
+            let latest = list_backups(&id)
+                .await?
+                .first()
+                .map(|backup| backup.created);
+            if latest.is_none_or(|created| now() - created >= BACKUP_INTERVAL) {
+                if let Err(e) = create_backup(&id, &setup).await {
+                    error!("failed to backup prod db for project {id}: {e}");
+                    continue;
+                }
+            }
+            prune_backups(&id, backup_retention.max(0) as usize).await?;
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
-use std::{future::Future, sync::Arc};
+use std::{future::Future, sync::Arc, time::Instant};
 
 use futures::StreamExt;
 use rand::seq::SliceRandom;
+use tokio::task::JoinSet;
 
 use crate::{
+    conf::Conf,
     container::{Container, ContainerStatus},
     db::Db,
     deployments::{
@@ -11,7 +13,7 @@ use crate::{
         map::DeploymentMap,
         worker::{Worker, WorkerHandle},
     },
-    github::Github,
+    source::Sources,
     utils::LogError,
 };
 
@@ -20,65 +22,206 @@ pub(crate) struct BuildWorker {
     // TODO: define a new function instead of having these public, same for other workers
     pub(crate) map: Arc<InstrumentedRwLock<DeploymentMap>>,
     pub(crate) db: Db,
-    pub(crate) github: Github,
+    pub(crate) sources: Sources,
     pub(crate) build_queue: WorkerHandle,
+    pub(crate) cutover_queue: WorkerHandle,
+}
+
+/// Queued containers are built in this order
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum BuildPriority {
+    /// prod dbs and deployments from the default branch
+    Prod,
+    /// containers someone is waiting for, the ones accessed first go first
+    Accessed(Instant),
+    Rest,
 }
 
 impl Worker for BuildWorker {
     #[tracing::instrument]
     fn work(&self) -> impl Future<Output = ()> + Send {
         async {
-            loop {
-                if let Some(container) = self.get_container_to_build().await {
-                    container.setup_as_standby().await.ignore_logging();
-                    // we call this because the container we just built might be promoted to be the prod one
-                    self.map
-                        .write()
-                        .await
-                        .read_db_and_build_updates(&self.build_queue, &self.github, &self.db)
-                        .await
-                        .ignore_logging();
-                    // FIXME: this might produce and infinite loop if setup_as_standby always fails
-                    // at least should chose a differnet container every time, to avoid starvation
-                } else {
-                    break;
+            let Conf {
+                build_concurrency, ..
+            } = Conf::read_async().await;
+            let claim = || self.claim_container_to_build();
+            let build = |container| {
+                let worker = self.clone();
+                async move { worker.build(container).await }
+            };
+            run_builds(&self.build_queue, build_concurrency, claim, build).await;
+        }
+    }
+}
+
+/// Keeps up to `concurrency` builds running until there is nothing queued nor being built.
+/// New builds are claimed every time one finishes and every time the queue gets triggered,
+/// so a long build never holds back the containers queued after it started
+async fn run_builds<T, Claim, ClaimFuture, Build, BuildFuture>(
+    queue: &WorkerHandle,
+    concurrency: usize,
+    claim: Claim,
+    build: Build,
+) where
+    Claim: Fn() -> ClaimFuture,
+    ClaimFuture: Future<Output = Option<T>>,
+    Build: Fn(T) -> BuildFuture,
+    BuildFuture: Future<Output = ()> + Send + 'static,
+{
+    let mut builds = JoinSet::new();
+    loop {
+        while builds.len() < concurrency {
+            match claim().await {
+                Some(item) => {
+                    builds.spawn(build(item));
                 }
+                None => break,
             }
         }
+        if builds.is_empty() {
+            break;
+        }
+        tokio::select! {
+            _ = builds.join_next() => {}
+            _ = queue.triggered() => {}
+        }
     }
 }
 
 impl BuildWorker {
     #[tracing::instrument]
-    async fn get_container_to_build(&self) -> Option<Arc<Container>> {
+    async fn build(&self, container: Arc<Container>) {
+        container.setup_as_standby().await.ignore_logging();
+        // we call this because the container we just built might be promoted to be the prod one
+        self.map
+            .write()
+            .await
+            .read_db_and_build_updates(&self.build_queue, &self.sources, &self.db)
+            .await
+            .ignore_logging();
+        self.cutover_queue.trigger();
+    }
+
+    /// Returns the queued container with the highest priority, already claimed for building
+    #[tracing::instrument]
+    async fn claim_container_to_build(&self) -> Option<Arc<Container>> {
+        let mut candidates = self.get_queued_containers().await;
+        // containers with the same priority are taken randomly
+        candidates.shuffle(&mut rand::thread_rng());
+        candidates.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (_, container) in candidates {
+            // some other build might have claimed it in the meantime
+            if container.claim_build().await {
+                return Some(container);
+            }
+        }
+        None
+    }
+
+    #[tracing::instrument]
+    async fn get_queued_containers(&self) -> Vec<(BuildPriority, Arc<Container>)> {
         // this block helds this read guard
         let map = self.map.read().await;
-        let queued_containers = map
-            .iter_containers()
-            .filter_map(|container| async {
-                let status = container.status.read().await.clone();
-                if let ContainerStatus::Queued { trigger_access } = status {
-                    Some((container, trigger_access))
-                } else {
-                    None
+        let prod_containers = map
+            .dbs
+            .values()
+            .map(|db| db.setup.container.clone())
+            .chain(map.postgres.values().map(|db| db.setup.container.clone()))
+            .chain(map.s3.values().map(|s3| s3.setup.container.clone()))
+            .chain(map.redis.values().map(|db| db.setup.container.clone()))
+            .chain(
+                map.deployments
+                    .values()
+                    .filter(|deployment| deployment.default_branch)
+                    .map(|deployment| deployment.app_container.clone()),
+            )
+            .collect::<Vec<_>>();
+        map.iter_containers()
+            .filter_map(|container| {
+                let is_prod = prod_containers
+                    .iter()
+                    .any(|prod| Arc::ptr_eq(prod, &container));
+                async move {
+                    let status = container.status.read().await.clone();
+                    if let ContainerStatus::Queued { trigger_access } = status {
+                        let priority = match trigger_access {
+                            _ if is_prod => BuildPriority::Prod,
+                            Some(accessed) => BuildPriority::Accessed(accessed),
+                            None => BuildPriority::Rest,
+                        };
+                        Some((priority, container))
+                    } else {
+                        None
+                    }
                 }
             })
-            .collect::<Vec<_>>()
-            .await;
-
-        let first_container_accessed = queued_containers
-            .iter()
-            .filter_map(|(container, trigger_access)| Some((container, trigger_access.clone()?)))
-            .min_by_key(|(_, trigger_access)| trigger_access.clone())
-            .map(|(container, _)| container);
-
-        if first_container_accessed.is_some() {
-            first_container_accessed.cloned()
-        } else {
-            // if no container is accessed, just return a random one
-            queued_containers
-                .choose(&mut rand::thread_rng())
-                .map(|(container, _)| container.clone())
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod build_tests {
+    use std::{
+        future::Future,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use tokio::sync::{mpsc::unbounded_channel, Notify};
+
+    use crate::deployments::worker::Worker;
+
+    use super::run_builds;
+
+    struct IdleWorker;
+
+    impl Worker for IdleWorker {
+        fn work(&self) -> impl Future<Output = ()> + Send {
+            async {}
         }
     }
+
+    #[tokio::test]
+    async fn test_build_queued_during_long_build() {
+        let queue = IdleWorker::start(|_| IdleWorker);
+        let queued = Arc::new(Mutex::new(vec!["long"]));
+        let finish_long = Arc::new(Notify::new());
+        let (started_sender, mut started) = unbounded_channel();
+
+        let builds = {
+            let queue = queue.clone();
+            let queued = queued.clone();
+            let finish_long = finish_long.clone();
+            tokio::spawn(async move {
+                let claim = || {
+                    let item = queued.lock().unwrap().pop();
+                    async move { item }
+                };
+                let build = |item| {
+                    let started = started_sender.clone();
+                    let finish_long = finish_long.clone();
+                    async move {
+                        started.send(item).unwrap();
+                        if item == "long" {
+                            finish_long.notified().await;
+                        }
+                    }
+                };
+                run_builds(&queue, 2, claim, build).await;
+            })
+        };
+
+        assert_eq!(started.recv().await, Some("long"));
+        queued.lock().unwrap().push("short");
+        queue.trigger();
+        let short = tokio::time::timeout(Duration::from_secs(1), started.recv()).await;
+        assert_eq!(short.unwrap(), Some("short"));
+
+        finish_long.notify_one();
+        tokio::time::timeout(Duration::from_secs(1), builds)
+            .await
+            .unwrap()
+            .unwrap();
+    }
 }
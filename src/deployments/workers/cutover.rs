@@ -0,0 +1,63 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::future::join_all;
+
+use crate::{
+    container::{Container, ContainerStatus},
+    db::nano_id::NanoId,
+    deployments::{manager::InstrumentedRwLock, map::DeploymentMap, worker::Worker},
+    utils::LogError,
+};
+
+/// Time the previous prod container keeps running after the switch, to finish in-flight requests
+const DRAIN_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub(crate) struct CutoverWorker {
+    pub(crate) map: Arc<InstrumentedRwLock<DeploymentMap>>,
+}
+
+impl Worker for CutoverWorker {
+    fn work(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {
+            let pending = self.map.read().await.get_pending_cutovers();
+            let cutovers = pending
+                .into_iter()
+                .map(|(project, slug, container)| self.cut_over(project, slug, container));
+            join_all(cutovers).await;
+        }
+    }
+}
+
+impl CutoverWorker {
+    #[tracing::instrument]
+    async fn cut_over(&self, project: NanoId, slug: String, container: Arc<Container>) {
+        // deployments still being built are picked up once the build worker is done with them
+        let startable = matches!(
+            *container.status.read().await,
+            ContainerStatus::StandBy { .. }
+                | ContainerStatus::Starting { .. }
+                | ContainerStatus::Ready { .. }
+        );
+        if !startable {
+            return;
+        }
+        // the previous prod container keeps serving until the new one is up and healthy.
+        // If the start fails, it is tried again the next time the map is synced
+        if let Err(error) = container.start().await {
+            tracing::error!("cutover to {slug} failed: {error}");
+            return;
+        }
+        let previous = self.map.write().await.complete_cutover(&project, &slug);
+        if let Some(previous) = previous {
+            let map = self.map.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(DRAIN_GRACE_PERIOD).await;
+                // it might have been promoted back in the meantime
+                if !map.read().await.is_prod_container(&previous) {
+                    previous.with_stopped(async {}).await.ignore_logging();
+                }
+            });
+        }
+    }
+}
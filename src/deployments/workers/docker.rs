@@ -4,7 +4,10 @@ use futures::StreamExt;
 
 use crate::{
     deployments::{manager::InstrumentedRwLock, map::DeploymentMap, worker::Worker},
-    docker::{delete_container, list_managed_container_names, stop_container},
+    docker::{
+        delete_container, delete_managed_image, list_managed_container_names, list_managed_images,
+        stop_container,
+    },
     utils::LogError,
 };
 
@@ -24,9 +27,16 @@ impl Worker for DockerWorker {
                     }
                 }
             }
-            // TODO: remove all the images that are not in use.
-            // Careful don't remove an image that was just built but not wrote yet into an StandBy status
-            // I can probably aquire the lock for the docker builder
+            // an image is only removed once its deployment is gone from the map, so images
+            // just built but not written yet into an StandBy status are never affected
+            if let Ok(images) = list_managed_images().await {
+                for image in images {
+                    let deployment = image.as_str().to_owned().into();
+                    if !self.map.read().await.has_deployment_id(&deployment) {
+                        delete_managed_image(&image).await.ignore_logging();
+                    }
+                }
+            }
         }
     }
 }
@@ -38,7 +48,7 @@ impl DockerWorker {
         let map = self.map.read().await;
         let mut containers = map.iter_containers();
         while let Some(container) = containers.next().await {
-            if container.get_container_name().await.as_ref() == Some(name) {
+            if container.get_container_names().await.contains(name) {
                 return true;
             }
         }
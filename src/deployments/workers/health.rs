@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use futures::{future::join_all, StreamExt};
+
+use crate::{
+    deployments::{manager::InstrumentedRwLock, map::DeploymentMap, worker::Worker},
+    utils::LogError,
+};
+
+#[derive(Debug)]
+pub(crate) struct HealthWorker {
+    pub(crate) map: Arc<InstrumentedRwLock<DeploymentMap>>,
+}
+
+impl Worker for HealthWorker {
+    fn work(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {
+            let containers = self
+                .map
+                .read()
+                .await
+                .iter_containers()
+                .collect::<Vec<_>>()
+                .await;
+            // each container only gets checked once its own interval is over
+            let checks = containers.iter().map(|container| container.check_health());
+            let needs_restart = join_all(checks).await;
+            // restarts wait for the container to be online again,
+            // so they are not awaited here to avoid holding back the checks of the rest
+            for (container, restart) in containers.into_iter().zip(needs_restart) {
+                if restart {
+                    tokio::spawn(async move { container.restart().await.ignore_logging() });
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub(crate) mod backup;
 pub(crate) mod build;
+pub(crate) mod cutover;
 pub(crate) mod docker;
 pub(crate) mod files;
-pub(crate) mod github;
+pub(crate) mod health;
+pub(crate) mod processes;
+pub(crate) mod replicas;
+pub(crate) mod source;
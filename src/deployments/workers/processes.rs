@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use futures::future::join_all;
+
+use crate::deployments::{manager::InstrumentedRwLock, map::DeploymentMap, worker::Worker};
+
+#[derive(Debug)]
+pub(crate) struct ProcessesWorker {
+    pub(crate) map: Arc<InstrumentedRwLock<DeploymentMap>>,
+}
+
+impl Worker for ProcessesWorker {
+    fn work(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {
+            let containers = {
+                let map = self.map.read().await;
+                map.deployments
+                    .iter()
+                    .map(|((project, slug), deployment)| {
+                        let is_prod = map.prod.get(project) == Some(slug);
+                        (deployment.app_container.clone(), is_prod)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            // worker processes only run for the prod deployment, never scaled to zero,
+            // so the ones of the previous prod are stopped right after a cutover
+            join_all(containers.iter().map(|(container, is_prod)| async move {
+                if *is_prod {
+                    container.run_processes().await
+                } else {
+                    container.stop_processes().await
+                }
+            }))
+            .await;
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use futures::{future::join_all, StreamExt};
+
+use crate::deployments::{manager::InstrumentedRwLock, map::DeploymentMap, worker::Worker};
+
+#[derive(Debug)]
+pub(crate) struct ReplicasWorker {
+    pub(crate) map: Arc<InstrumentedRwLock<DeploymentMap>>,
+}
+
+impl Worker for ReplicasWorker {
+    fn work(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {
+            let containers = self
+                .map
+                .read()
+                .await
+                .iter_containers()
+                .collect::<Vec<_>>()
+                .await;
+            // only Ready containers are scaled, the rest get their first replica on demand
+            join_all(
+                containers
+                    .iter()
+                    .map(|container| container.scale_replicas()),
+            )
+            .await;
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+use tracing::error;
+
+use crate::{
+    db::{nano_id::NanoId, Db, Deployment, InsertDeployment, PreviewRules, Project},
+    deployments::{config::DeploymentConfig, worker::Worker},
+    source::{Commit, SourceProvider, Sources},
+    utils::{now, LogError},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SourceWorker {
+    pub(crate) sources: Sources,
+    pub(crate) db: Db,
+}
+
+impl Worker for SourceWorker {
+    #[tracing::instrument]
+    fn work(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {
+            // if there is some error when trying to read from the db, we simply skip the work
+            let _ = self.source_work().await.inspect_err(|e| error!("{e}"));
+        }
+    }
+}
+
+impl SourceWorker {
+    #[tracing::instrument]
+    async fn source_work(&self) -> anyhow::Result<()> {
+        for Project {
+            repo,
+            env,
+            id,
+            root,
+            name,
+            preview_rules,
+            ..
+        } in self.db.get_projects().await?
+        {
+            let provider = self.sources.provider(&repo).await;
+            let commit = get_default_branch_and_latest_commit(provider.as_ref()).await;
+            match commit {
+                Ok((default_branch, commit)) => {
+                    let deployment = InsertDeployment {
+                        env: env.to_owned(),
+                        sha: commit.sha,
+                        timestamp: commit.timestamp,
+                        branch: default_branch,
+                        default_branch: 1, // TODO: abstract this as a bool
+                        project: id.clone(),
+                        result: None,
+                    };
+                    add_deployment_to_db_if_missing(
+                        provider.as_ref(),
+                        &self.db,
+                        deployment,
+                        &root,
+                        &name,
+                    )
+                    .await
+                    .ignore_logging();
+                }
+                Err(error) => error!("{error}"),
+            }
+
+            let change_results = provider.get_open_change_requests().await;
+            // closed pulls can only be told apart if the open ones were fetched successfully
+            let open_branches = change_results.as_ref().ok().map(|changes| {
+                changes
+                    .iter()
+                    .map(|change| change.branch.clone())
+                    .collect::<HashSet<_>>()
+            });
+            let changes = change_results
+                .inspect_err(|error| error!("{error}"))
+                .unwrap_or(vec![]);
+            for change in changes {
+                let branch = change.branch;
+                // FIXME: some duplicated code in here as in above
+                match provider.get_latest_commit(&branch).await {
+                    Ok(commit) => {
+                        let deployment = InsertDeployment {
+                            env: env.to_owned(),
+                            sha: commit.sha,
+                            timestamp: commit.timestamp,
+                            branch,
+                            default_branch: 0, // TODO: abstract this as a bool
+                            project: id.clone(),
+                            result: None,
+                        };
+                        add_deployment_to_db_if_missing(
+                            provider.as_ref(),
+                            &self.db,
+                            deployment,
+                            &root,
+                            &name,
+                        )
+                        .await
+                        .ignore_logging();
+                    }
+                    Err(error) => error!("{error}"),
+                }
+            }
+
+            self.cleanup_previews(&id, &preview_rules, open_branches.as_ref())
+                .await
+                .ignore_logging();
+        }
+        Ok(())
+    }
+}
+
+impl SourceWorker {
+    /// Deleted deployments are then removed from the map on the next sync,
+    /// and their folders, containers and images reclaimed by the files and docker workers
+    #[tracing::instrument]
+    async fn cleanup_previews(
+        &self,
+        project: &NanoId,
+        rules: &PreviewRules,
+        open_branches: Option<&HashSet<String>>,
+    ) -> anyhow::Result<()> {
+        let previews: Vec<_> = self
+            .db
+            .get_deployments()
+            .await?
+            .into_iter()
+            .filter(|deployment| &deployment.project == project && !deployment.is_default_branch())
+            .collect();
+        for id in select_expired_previews(rules, &previews, open_branches, now()) {
+            self.db.delete_deployment(&id).await?;
+        }
+        Ok(())
+    }
+}
+
+fn select_expired_previews(
+    rules: &PreviewRules,
+    previews: &[Deployment],
+    open_branches: Option<&HashSet<String>>,
+    now: i64,
+) -> Vec<NanoId> {
+    let mut branches: HashMap<&str, Vec<&Deployment>> = HashMap::new();
+    for preview in previews {
+        branches.entry(&preview.branch).or_default().push(preview);
+    }
+    let ttl = rules.ttl_days * 24 * 60 * 60 * 1000;
+    branches
+        .into_iter()
+        .flat_map(|(branch, mut deployments)| {
+            deployments.sort_by_key(|deployment| -deployment.created);
+            let closed = rules.delete_closed
+                && open_branches.is_some_and(|open_branches| !open_branches.contains(branch));
+            deployments
+                .into_iter()
+                .enumerate()
+                .filter(move |(index, deployment)| {
+                    let beyond_retention = rules.retention > 0 && *index as i64 >= rules.retention;
+                    let expired = rules.ttl_days > 0 && now - deployment.created > ttl;
+                    closed || beyond_retention || expired
+                })
+                .map(|(_, deployment)| deployment.id.clone())
+        })
+        .collect()
+}
+
+#[tracing::instrument]
+async fn get_default_branch_and_latest_commit(
+    provider: &dyn SourceProvider,
+) -> anyhow::Result<(String, Commit)> {
+    let default_branch = provider.get_default_branch().await?;
+    let commit = provider.get_latest_commit(&default_branch).await?;
+    Ok((default_branch, commit))
+}
+
+/// Shared by the polling worker and the webhook so deployments are inserted the same way
+#[tracing::instrument]
+pub(crate) async fn add_deployment_to_db_if_missing(
+    provider: &dyn SourceProvider,
+    db: &Db,
+    mut deployment: InsertDeployment, // FIXME: don't like having this mut here
+    root: &str,
+    app_name: &str,
+) -> anyhow::Result<()> {
+    let exists = db
+        .hash_exists_for_project(&deployment.sha, &deployment.project)
+        .await?;
+    if !exists {
+        let (config, error) = match DeploymentConfig::fetch_from_repo(
+            provider,
+            &deployment.sha,
+            root,
+            app_name,
+        )
+        .await
+        {
+            Ok(config) => (config.unwrap_or_default(), None),
+            Err(error) => {
+                deployment.result = Some(crate::db::BuildResult::Failed);
+                (Default::default(), Some(error))
+            }
+        };
+        let (project, branch, timestamp) = (
+            deployment.project.clone(),
+            deployment.branch.clone(),
+            deployment.timestamp,
+        );
+        let id = db.insert_deployment(deployment, config.into()).await?;
+        db.supersede_older_deployments(&project, &branch, timestamp)
+            .await?;
+        if let Some(error) = error {
+            db.insert_deployment_build_log(&id, &error.to_string(), true)
+                .await
+                .ignore_logging();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod source_worker_tests {
+    use std::collections::HashSet;
+
+    use crate::db::{Deployment, PreviewRules};
+
+    use super::select_expired_previews;
+
+    fn preview(id: &str, branch: &str, created: i64) -> Deployment {
+        Deployment {
+            id: id.to_owned().into(),
+            url_id: id.to_owned(),
+            timestamp: created,
+            created,
+            sha: id.to_owned(),
+            branch: branch.to_owned(),
+            default_branch: 0,
+            result: None,
+            build_started: None,
+            build_finished: None,
+            project: "project".to_owned().into(),
+            config: Default::default(),
+            env: vec![],
+        }
+    }
+
+    #[test]
+    fn test_select_expired_previews() {
+        let day = 24 * 60 * 60 * 1000;
+        let previews = vec![
+            preview("a1", "a", 10 * day),
+            preview("a2", "a", 11 * day),
+            preview("a3", "a", 12 * day),
+            preview("b1", "b", 1 * day),
+            preview("c1", "c", 12 * day),
+        ];
+        let rules = PreviewRules {
+            delete_closed: true,
+            retention: 2,
+            ttl_days: 5,
+        };
+        let open: HashSet<_> = ["a".to_owned(), "b".to_owned()].into();
+
+        let mut expired: Vec<String> =
+            select_expired_previews(&rules, &previews, Some(&open), 12 * day)
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        expired.sort();
+        assert_eq!(expired, vec!["a1", "b1", "c1"]);
+
+        // without knowing which pulls are open, closed branches are left alone
+        let expired = select_expired_previews(&rules, &previews, None, 12 * day);
+        assert_eq!(expired.len(), 2);
+    }
+}
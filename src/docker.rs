@@ -5,10 +5,13 @@ use bollard::models::BuildInfoAux;
 use bollard::{
     container::{
         Config, CreateContainerOptions, ListContainersOptions, LogOutput, LogsOptions,
-        NetworkingConfig, StartContainerOptions,
+        NetworkingConfig, StartContainerOptions, UpdateContainerOptions, WaitContainerOptions,
+    },
+    image::{BuildImageOptions, CreateImageOptions, ListImagesOptions},
+    secret::{
+        BuildInfo, HostConfig, ImageInspect, RestartPolicy as DockerRestartPolicy,
+        RestartPolicyNameEnum,
     },
-    image::{BuildImageOptions, CreateImageOptions},
-    secret::{BuildInfo, HostConfig, ImageInspect},
     Docker,
 };
 use chrono::{DateTime, Utc};
@@ -24,7 +27,16 @@ use std::{
 };
 use utoipa::ToSchema;
 
-use crate::{env::EnvVars, utils::LOWERCASE_PLUS_NUMBERS};
+use crate::{
+    deployments::config::{ResourceLimits, RestartPolicy},
+    env::EnvVars,
+    utils::{now, LOWERCASE_PLUS_NUMBERS},
+    volumes::Volume,
+};
+
+/// CFS scheduler period in microseconds used to translate a number of cpus into a quota
+const CPU_PERIOD: u64 = 100_000;
+const MEGABYTE: i64 = 1024 * 1024;
 
 #[tracing::instrument]
 pub(crate) fn docker_client() -> Docker {
@@ -51,6 +63,10 @@ impl ImageName {
     fn to_docker_name(&self) -> String {
         format!("{CONTAINER_PREFIX}{}", self.0)
     }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 impl From<String> for ImageName {
     fn from(value: String) -> Self {
@@ -81,6 +97,22 @@ pub(crate) enum LogType {
     Err,
 }
 
+/// Time at which the container was killed for running out of memory, if that was the reason it stopped
+#[tracing::instrument]
+pub(crate) async fn get_container_oom_kill(id: &str) -> Option<i64> {
+    let docker = docker_client();
+    let response = docker.inspect_container(id, None).await.ok()?;
+    let state = response.state?;
+    if state.oom_killed != Some(true) {
+        return None;
+    }
+    let finished = state
+        .finished_at
+        .and_then(|finished| finished.parse::<DateTime<Utc>>().ok())
+        .map(|finished| finished.timestamp_millis());
+    Some(finished.unwrap_or(now()))
+}
+
 #[tracing::instrument]
 pub(crate) async fn get_container_execution_logs(id: &str) -> impl Iterator<Item = DockerLog> {
     let docker = docker_client();
@@ -165,15 +197,18 @@ pub(crate) async fn create_container<'a, I: Iterator<Item = &'a PathBuf>>(
     image: String,
     env: EnvVars,
     host_folders: I,
+    volumes: &[Volume],
     command: Option<String>,
+    limits: ResourceLimits,
 ) -> anyhow::Result<String> {
     let binds = host_folders
         .map(|folder| {
             let path = folder.display().to_string();
             format!("{path}:{path}")
         })
+        .chain(volumes.iter().map(Volume::get_bind))
         .collect();
-    create_container_with_explicit_binds(name, image, env, binds, command).await
+    create_container_with_explicit_binds(name, image, env, binds, command, limits).await
 }
 
 pub(crate) async fn create_container_with_explicit_binds(
@@ -182,6 +217,7 @@ pub(crate) async fn create_container_with_explicit_binds(
     env: EnvVars,
     binds: Vec<String>,
     command: Option<String>,
+    limits: ResourceLimits,
 ) -> anyhow::Result<String> {
     let entrypoint = command
         .is_some()
@@ -202,6 +238,18 @@ pub(crate) async fn create_container_with_explicit_binds(
                 env: Some(env.into()),
                 host_config: Some(HostConfig {
                     binds: Some(binds),
+                    memory: limits.memory.map(|megabytes| megabytes as i64 * MEGABYTE),
+                    memory_swap: limits.memory_swap.map(|megabytes| {
+                        if megabytes < 0 {
+                            -1
+                        } else {
+                            megabytes * MEGABYTE
+                        }
+                    }),
+                    cpu_shares: limits.cpu_shares.map(|shares| shares as i64),
+                    cpu_period: limits.cpus.map(|_| CPU_PERIOD as i64),
+                    cpu_quota: limits.cpus.map(|cpus| (cpus * CPU_PERIOD as f64) as i64),
+                    pids_limit: limits.pids,
                     ..Default::default()
                 }),
                 networking_config: Some(NetworkingConfig {
@@ -273,6 +321,40 @@ pub(crate) async fn stop_container(name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Waits for the container to exit, returning its exit code
+#[tracing::instrument]
+pub(crate) async fn wait_container(name: &str) -> anyhow::Result<i64> {
+    let docker = docker_client();
+    let mut responses = docker.wait_container(name, None::<WaitContainerOptions<String>>);
+    match responses.next().await {
+        Some(Ok(response)) => Ok(response.status_code),
+        // non zero exit codes are reported as errors
+        Some(Err(bollard::errors::Error::DockerContainerWaitError { code, .. })) => Ok(code),
+        Some(Err(error)) => Err(error.into()),
+        None => Err(anyhow!("container {name} could not be waited for")),
+    }
+}
+
+/// Containers are never restarted by docker after being stopped through `stop_container`
+#[tracing::instrument]
+pub(crate) async fn set_restart_policy(name: &str, policy: RestartPolicy) -> anyhow::Result<()> {
+    let name_enum = match policy {
+        RestartPolicy::Always => RestartPolicyNameEnum::UNLESS_STOPPED,
+        RestartPolicy::OnFailure => RestartPolicyNameEnum::ON_FAILURE,
+        RestartPolicy::Never => RestartPolicyNameEnum::NO,
+    };
+    let docker = docker_client();
+    let options = UpdateContainerOptions::<String> {
+        restart_policy: Some(DockerRestartPolicy {
+            name: Some(name_enum),
+            maximum_retry_count: None,
+        }),
+        ..Default::default()
+    };
+    docker.update_container(name, options).await?;
+    Ok(())
+}
+
 #[tracing::instrument]
 pub(crate) async fn delete_container(name: &str) -> anyhow::Result<()> {
     let docker = docker_client();
@@ -280,6 +362,20 @@ pub(crate) async fn delete_container(name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tracing::instrument]
+pub(crate) async fn pause_container(name: &str) -> anyhow::Result<()> {
+    let docker = docker_client();
+    docker.pause_container(name).await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+pub(crate) async fn unpause_container(name: &str) -> anyhow::Result<()> {
+    let docker = docker_client();
+    docker.unpause_container(name).await?;
+    Ok(())
+}
+
 #[tracing::instrument]
 pub(crate) async fn delete_image(name: &str) -> anyhow::Result<()> {
     let docker = docker_client();
@@ -309,6 +405,28 @@ pub(crate) async fn list_managed_container_names() -> anyhow::Result<impl Iterat
         }))
 }
 
+/// Returns the images built for deployments, which are named after the deployment id
+#[tracing::instrument]
+pub(crate) async fn list_managed_images() -> anyhow::Result<impl Iterator<Item = ImageName>> {
+    let docker = docker_client();
+    let images = docker
+        .list_images(Some(ListImagesOptions::<String>::default()))
+        .await?;
+    Ok(images
+        .into_iter()
+        .flat_map(|summary| summary.repo_tags)
+        .filter_map(|tag| {
+            let name = tag.strip_suffix(":latest")?;
+            let id = name.strip_prefix(CONTAINER_PREFIX)?;
+            Some(ImageName(id.to_owned()))
+        }))
+}
+
+#[tracing::instrument]
+pub(crate) async fn delete_managed_image(name: &ImageName) -> anyhow::Result<()> {
+    delete_image(&name.to_docker_name()).await
+}
+
 #[cfg(test)]
 mod docker_tests {
     // use crate::docker::{create_container, get_bollard_container_ipv4, run_container};
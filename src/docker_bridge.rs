@@ -6,7 +6,7 @@ use tokio::{
     net::{TcpListener, TcpStream, UnixStream},
 };
 
-use crate::listener::{Access, Listener};
+use crate::listener::{Access, Listener, Port};
 
 pub(crate) const DOCKER_PORT: u16 = 5046;
 
@@ -38,7 +38,7 @@ impl Default for DockerBridge {
 
 #[async_trait]
 impl Listener for DockerBridge {
-    async fn access(&self) -> anyhow::Result<Access> {
+    async fn access(&self, _port: &Port) -> anyhow::Result<Access> {
         Ok(self.socket.into())
     }
 
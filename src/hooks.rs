@@ -2,17 +2,13 @@ use std::{collections::HashMap, fmt};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use octocrab::{
-    models::issues::Comment,
-    params::checks::{CheckRunConclusion, CheckRunStatus},
-};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     conf::Conf,
-    db::{nano_id::NanoId, BuildResult, Db},
-    github::Github,
+    db::{nano_id::NanoId, BuildResult, Db, InsertAttempt},
     provider,
+    source::{Comment, CommitStatus, Sources},
     tokens::{decode_token, generate_token},
     utils::now,
 };
@@ -21,8 +17,13 @@ use crate::{
 pub(crate) trait DeploymentHooks: 'static + Send + Sync + fmt::Debug {
     async fn on_build_log(&self, output: &str, error: bool);
     async fn on_build_started(&self);
-    async fn on_build_finished(&self);
+    async fn on_build_finished(&self, image: &str);
     async fn on_build_failed(&self);
+    async fn on_build_timed_out(&self);
+    async fn on_build_superseded(&self);
+    async fn on_attempt_finished(&self, attempt: InsertAttempt);
+    /// called with the docker container of every replica serving requests, empty once stopped
+    async fn on_containers_changed(&self, containers: &[String]);
 }
 
 #[derive(Debug)]
@@ -32,28 +33,32 @@ pub(crate) struct NoopHooks;
 impl DeploymentHooks for NoopHooks {
     async fn on_build_log(&self, _output: &str, _error: bool) {}
     async fn on_build_started(&self) {}
-    async fn on_build_finished(&self) {}
+    async fn on_build_finished(&self, _image: &str) {}
     async fn on_build_failed(&self) {}
+    async fn on_build_timed_out(&self) {}
+    async fn on_build_superseded(&self) {}
+    async fn on_attempt_finished(&self, _attempt: InsertAttempt) {}
+    async fn on_containers_changed(&self, _containers: &[String]) {}
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct StatusHooks {
     db: Db,
     id: NanoId,
-    github: Github,
+    sources: Sources,
 }
 
 impl StatusHooks {
-    pub(crate) fn new(deployment_id: NanoId, db: Db, github: Github) -> Self {
+    pub(crate) fn new(deployment_id: NanoId, db: Db, sources: Sources) -> Self {
         Self {
             db,
             id: deployment_id,
-            github,
+            sources,
         }
     }
 }
 
-// TODO: write also error status to db, and send updates to github!!
+// TODO: write also error status to db, and send updates to the repo!!
 #[async_trait]
 impl DeploymentHooks for StatusHooks {
     async fn on_build_log(&self, output: &str, error: bool) {
@@ -67,16 +72,17 @@ impl DeploymentHooks for StatusHooks {
         let _ = self.db.clear_deployment_build_logs(&self.id).await;
         let _ = self.db.update_deployment_build_start(&self.id, now()).await;
         let _ = self.db.reset_deployment_build_end(&self.id).await;
-        self.update_github(Status::Building);
+        self.update_source(Status::Building);
     }
 
-    async fn on_build_finished(&self) {
+    async fn on_build_finished(&self, image: &str) {
         let _ = self.db.update_deployment_build_end(&self.id, now()).await;
+        let _ = self.db.update_deployment_image(&self.id, image).await;
         let _ = self
             .db
             .update_deployment_result(&self.id, BuildResult::Built) // FIXME: the db should maybe only have a flag error: bool
             .await;
-        self.update_github(Status::Ready);
+        self.update_source(Status::Ready);
     }
 
     async fn on_build_failed(&self) {
@@ -85,7 +91,33 @@ impl DeploymentHooks for StatusHooks {
             .db
             .update_deployment_result(&self.id, BuildResult::Failed)
             .await;
-        self.update_github(Status::Failed);
+        self.update_source(Status::Failed);
+    }
+
+    async fn on_build_timed_out(&self) {
+        let _ = self.db.update_deployment_build_end(&self.id, now()).await;
+        let _ = self
+            .db
+            .update_deployment_result(&self.id, BuildResult::Failed)
+            .await;
+        self.update_source(Status::TimedOut);
+    }
+
+    async fn on_build_superseded(&self) {
+        let _ = self.db.update_deployment_build_end(&self.id, now()).await;
+        let _ = self.db.supersede_deployment(&self.id).await;
+        self.update_source(Status::Superseded);
+    }
+
+    async fn on_attempt_finished(&self, attempt: InsertAttempt) {
+        let _ = self.db.insert_deployment_attempt(&self.id, attempt).await;
+    }
+
+    async fn on_containers_changed(&self, containers: &[String]) {
+        let _ = self
+            .db
+            .update_deployment_containers(&self.id, containers)
+            .await;
     }
 }
 
@@ -94,24 +126,28 @@ enum Status {
     Building,
     Ready,
     Failed,
+    TimedOut,
+    Superseded,
 }
 
-impl From<Status> for CheckRunStatus {
+impl From<Status> for CommitStatus {
     fn from(value: Status) -> Self {
         match value {
-            Status::Building => Self::InProgress,
-            Status::Ready => Self::Completed,
-            Status::Failed => Self::Completed,
+            Status::Building => Self::Running,
+            Status::Ready => Self::Success,
+            Status::Failed => Self::Failure,
+            Status::TimedOut => Self::TimedOut,
+            Status::Superseded => Self::Cancelled,
         }
     }
 }
 
 impl StatusHooks {
     // TODO: record updated time before the async code and check it to avoid overwriting a newer comment
-    fn update_github(&self, status: Status) {
+    fn update_source(&self, status: Status) {
         let hooks = self.clone();
         tokio::spawn(async move {
-            let bot = hooks.github.allocate_bot().await;
+            let _guard = hooks.sources.lock_comments().await;
             let Conf {
                 hostname,
                 provider,
@@ -124,28 +160,29 @@ impl StatusHooks {
                 .await
                 .unwrap()
                 .unwrap();
-            let repo_id = deployment.project.repo_id;
-            let prs = hooks.github.get_open_pulls(repo_id).await.unwrap();
-            for pr in prs {
-                if deployment.branch == pr.head.ref_field {
+            let source = hooks.sources.provider(&deployment.project.repo).await;
+            let changes = source.get_open_change_requests().await.unwrap();
+            for change in changes {
+                if deployment.branch == change.branch {
                     let team = provider::get_team_name().await.unwrap();
 
-                    let comment = bot
-                        .read_pull_comment_with_prefix(repo_id, pr.number, "[prezel]: ey")
+                    let comment = source
+                        .find_comment(change.number, "[prezel]: ey")
                         .await
                         .unwrap();
 
                     let project_name = &deployment.project.name;
                     let slug = &deployment.url_id;
-                    let app_comment = GithubCommentApp {
+                    let provider_url = format!("{provider}/{team}/{project_name}/{slug}");
+                    let app_comment = CommentApp {
                         status,
-                        provider_url: format!("{provider}/{team}/{project_name}/{slug}"),
+                        provider_url: provider_url.clone(),
                         diff_url: format!("{provider}/{team}/{project_name}/{slug}/diff"),
                         preview_url: deployment.get_app_base_url(&hostname),
                         updated: chrono::offset::Utc::now(),
                     };
 
-                    if let Some(comment) = comment {
+                    let (comment_id, updated_info) = if let Some(comment) = comment {
                         let updated_info =
                             if let Some(mut info) = get_comment_info(&comment, &secret) {
                                 info.insert(project_name.clone(), app_comment);
@@ -153,34 +190,18 @@ impl StatusHooks {
                             } else {
                                 HashMap::from([(project_name.clone(), app_comment)])
                             };
-                        let content = create_comment(updated_info, &secret); // TODO: this
-                        let _ = bot
-                            .update_pull_comment(repo_id, pr.number, comment.id, &content)
-                            .await;
+                        (Some(comment.id), updated_info)
                     } else {
-                        let info = HashMap::from([(project_name.clone(), app_comment)]);
-                        let content = create_comment(info, &secret);
-                        let _ = bot.create_pull_comment(repo_id, pr.number, &content).await;
+                        (None, HashMap::from([(project_name.clone(), app_comment)]))
                     };
+                    let content = create_comment(updated_info, &secret);
+                    let _ = source
+                        .upsert_comment(change.number, comment_id, &content)
+                        .await;
 
-                    let (status, conclusion) = match status {
-                        Status::Building => (CheckRunStatus::InProgress, None),
-                        Status::Ready => {
-                            (CheckRunStatus::Completed, Some(CheckRunConclusion::Success))
-                        }
-                        Status::Failed => {
-                            (CheckRunStatus::Completed, Some(CheckRunConclusion::Failure))
-                        }
-                    };
                     let check_name = format!("Prezel - {project_name}");
-                    let _ = bot
-                        .upsert_pull_check(
-                            repo_id,
-                            &deployment.sha,
-                            &check_name,
-                            status,
-                            conclusion,
-                        )
+                    let _ = source
+                        .report_status(&deployment.sha, &check_name, status.into(), &provider_url)
                         .await;
                 }
             }
@@ -189,7 +210,7 @@ impl StatusHooks {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct GithubCommentApp {
+struct CommentApp {
     status: Status,
     provider_url: String,
     preview_url: String,
@@ -197,22 +218,23 @@ struct GithubCommentApp {
     updated: DateTime<Utc>,
 }
 
-type GithubCommentInfo = HashMap<String, GithubCommentApp>;
+type CommentInfo = HashMap<String, CommentApp>;
 
-fn get_comment_info(comment: &Comment, secret: &[u8]) -> Option<GithubCommentInfo> {
-    let body = comment.body.as_ref()?;
-    let header = body.split("\n").next()?;
+fn get_comment_info(comment: &Comment, secret: &[u8]) -> Option<CommentInfo> {
+    let header = comment.body.split("\n").next()?;
     let jwt = header.split("[prezel]: ").last()?;
     decode_token(jwt, secret, false).ok()
 }
 
-fn create_comment(info: GithubCommentInfo, secret: &[u8]) -> String {
-    let rows = info.iter().map(|(name, GithubCommentApp{status, provider_url, preview_url, diff_url, updated})| {
+fn create_comment(info: CommentInfo, secret: &[u8]) -> String {
+    let rows = info.iter().map(|(name, CommentApp{status, provider_url, preview_url, diff_url, updated})| {
         let formatted_status = match status {
             // Status::Queued => "⏳ Queued",
             Status::Building => "🔨 Building",
             Status::Ready => "✅ Ready",
             Status::Failed => "❌ Failed",
+            Status::TimedOut => "⏱️ Timed out",
+            Status::Superseded => "⏭️ Superseded",
         };
         let updated = updated.format("%b %e, %Y %l:%M%P").to_string();
         format!("| **{name}** | {formatted_status} ([Inspect]({provider_url})) | [Visit Preview]({preview_url}) | [See diff]({diff_url}) | {updated} |")
@@ -1,34 +1,103 @@
 use anyhow::{anyhow, ensure};
 
-use crate::db::nano_id::NanoId;
+use crate::{db::nano_id::NanoId, listener::Port};
 
 /// The prefix of the hostname that refers to a resource of a particular app hosted in the server
 #[derive(Debug, PartialEq)]
 pub(crate) enum Label {
-    Prod { project: String },
-    ProdDb { project: NanoId },
-    Deployment { project: String, deployment: String },
-    DeploymentInsert { project: String, deployment: String },
-    BranchDb { project: NanoId, deployment: String },
+    Prod {
+        project: String,
+    },
+    ProdPort {
+        project: String,
+        port: String,
+    },
+    ProdDb {
+        project: NanoId,
+    },
+    Deployment {
+        project: String,
+        deployment: String,
+    },
+    DeploymentInsert {
+        project: String,
+        deployment: String,
+    },
+    DeploymentPort {
+        project: String,
+        deployment: String,
+        port: String,
+    },
+    BranchDb {
+        project: NanoId,
+        deployment: String,
+    },
+    ProdPostgres {
+        project: NanoId,
+    },
+    BranchPostgres {
+        project: NanoId,
+        deployment: String,
+    },
+    ProdS3 {
+        project: NanoId,
+    },
+    ProdRedis {
+        project: NanoId,
+    },
+    BranchRedis {
+        project: NanoId,
+        deployment: String,
+    },
+    BranchS3 {
+        project: NanoId,
+        deployment: String,
+    },
 }
 
 impl Label {
     pub(crate) fn format_hostname(&self, box_domain: &str) -> String {
+        let label = self.format_label();
+        format!("{label}.{box_domain}")
+    }
+
+    pub(crate) fn format_label(&self) -> String {
         match self {
-            Label::Prod { project } => format!("{project}.{box_domain}"),
-            Label::ProdDb { project } => format!("{project}--libsql.{box_domain}"),
+            Label::Prod { project } => project.to_string(),
+            Label::ProdPort { project, port } => format!("{project}--port-{port}"),
+            Label::ProdDb { project } => format!("{project}--libsql"),
             Label::Deployment {
                 project,
                 deployment,
-            } => format!("{project}--{deployment}.{box_domain}"),
+            } => format!("{project}--{deployment}"),
             Label::DeploymentInsert {
                 project,
                 deployment,
-            } => format!("{project}--{deployment}-insert.{box_domain}"),
+            } => format!("{project}--{deployment}-insert"),
+            Label::DeploymentPort {
+                project,
+                deployment,
+                port,
+            } => format!("{project}--{deployment}-port-{port}"),
             Label::BranchDb {
                 project,
                 deployment,
-            } => format!("{project}--{deployment}-libsql.{box_domain}"),
+            } => format!("{project}--{deployment}-libsql"),
+            Label::ProdPostgres { project } => format!("{project}--postgres"),
+            Label::BranchPostgres {
+                project,
+                deployment,
+            } => format!("{project}--{deployment}-postgres"),
+            Label::ProdS3 { project } => format!("{project}--s3"),
+            Label::ProdRedis { project } => format!("{project}--redis"),
+            Label::BranchRedis {
+                project,
+                deployment,
+            } => format!("{project}--{deployment}-redis"),
+            Label::BranchS3 {
+                project,
+                deployment,
+            } => format!("{project}--{deployment}-s3"),
         }
     }
 
@@ -42,13 +111,25 @@ impl Label {
             label.find(".").is_none(),
             "invalid label, more dots than expected"
         );
-        let parsed = parse_label(label).ok_or(anyhow!("invalid label"))?;
-        Ok(parsed)
+        Self::parse(label)
+    }
+
+    pub(crate) fn parse(label: &str) -> anyhow::Result<Self> {
+        parse_label(label).ok_or(anyhow!("invalid label"))
     }
 
     pub(crate) fn insert_enabled(&self) -> bool {
         matches!(self, Self::DeploymentInsert { .. })
     }
+
+    pub(crate) fn port(&self) -> Port {
+        match self {
+            Self::ProdPort { port, .. } | Self::DeploymentPort { port, .. } => {
+                Port::Named(port.clone())
+            }
+            _ => Port::Http,
+        }
+    }
 }
 
 fn parse_label(label: &str) -> Option<Label> {
@@ -60,6 +141,15 @@ fn parse_label(label: &str) -> Option<Label> {
             ["libsql"] => Some(Label::ProdDb {
                 project: project.to_string().into(),
             }),
+            ["postgres"] => Some(Label::ProdPostgres {
+                project: project.to_string().into(),
+            }),
+            ["s3"] => Some(Label::ProdS3 {
+                project: project.to_string().into(),
+            }),
+            ["redis"] => Some(Label::ProdRedis {
+                project: project.to_string().into(),
+            }),
             [deployment] => Some(Label::Deployment {
                 project: project.to_string(),
                 deployment: deployment.to_string(),
@@ -72,6 +162,27 @@ fn parse_label(label: &str) -> Option<Label> {
                 project: project.to_string().into(),
                 deployment: deployment.to_string(),
             }),
+            [deployment, "postgres"] => Some(Label::BranchPostgres {
+                project: project.to_string().into(),
+                deployment: deployment.to_string(),
+            }),
+            [deployment, "s3"] => Some(Label::BranchS3 {
+                project: project.to_string().into(),
+                deployment: deployment.to_string(),
+            }),
+            [deployment, "redis"] => Some(Label::BranchRedis {
+                project: project.to_string().into(),
+                deployment: deployment.to_string(),
+            }),
+            ["port", port] => Some(Label::ProdPort {
+                project: project.to_string(),
+                port: port.to_string(),
+            }),
+            [deployment, "port", port] => Some(Label::DeploymentPort {
+                project: project.to_string(),
+                deployment: deployment.to_string(),
+                port: port.to_string(),
+            }),
             _ => None,
         },
         _ => None,
@@ -97,6 +208,15 @@ mod label_tests {
                 project: "test-project".to_owned(),
                 deployment: "3fg6fdhj".to_owned(),
             },
+            Label::ProdPort {
+                project: "test-project".to_owned(),
+                port: "metrics".to_owned(),
+            },
+            Label::DeploymentPort {
+                project: "test-project".to_owned(),
+                deployment: "3fg6fdhj".to_owned(),
+                port: "metrics".to_owned(),
+            },
             Label::ProdDb {
                 project: "test-uuid".to_owned().into(),
             },
@@ -104,6 +224,27 @@ mod label_tests {
                 project: "test-uuid".to_owned().into(),
                 deployment: "3fg6fdhj".to_owned(),
             },
+            Label::ProdPostgres {
+                project: "test-uuid".to_owned().into(),
+            },
+            Label::BranchPostgres {
+                project: "test-uuid".to_owned().into(),
+                deployment: "3fg6fdhj".to_owned(),
+            },
+            Label::ProdS3 {
+                project: "test-uuid".to_owned().into(),
+            },
+            Label::BranchS3 {
+                project: "test-uuid".to_owned().into(),
+                deployment: "3fg6fdhj".to_owned(),
+            },
+            Label::ProdRedis {
+                project: "test-uuid".to_owned().into(),
+            },
+            Label::BranchRedis {
+                project: "test-uuid".to_owned().into(),
+                deployment: "3fg6fdhj".to_owned(),
+            },
         ] {
             let formatted = label.format_hostname(box_domain);
             assert_eq!(
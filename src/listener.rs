@@ -1,20 +1,55 @@
-use std::net::SocketAddrV4;
+use std::{
+    net::SocketAddrV4,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use async_trait::async_trait;
 
 pub(crate) enum Access {
-    Socket(SocketAddrV4),
+    Socket(SocketAddrV4, InFlight),
     Loading,
 }
 
 impl From<SocketAddrV4> for Access {
     fn from(value: SocketAddrV4) -> Self {
-        Self::Socket(value)
+        Self::Socket(value, Default::default())
     }
 }
 
+/// Counts a request as in flight for the replica serving it until dropped
+#[derive(Debug, Default)]
+pub(crate) struct InFlight(Option<Arc<AtomicUsize>>);
+
+impl InFlight {
+    pub(crate) fn new(counter: Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(Some(counter))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(counter) = &self.0 {
+            counter.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Port of the listener requests are routed to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) enum Port {
+    /// the main HTTP port
+    #[default]
+    Http,
+    /// an extra port declared by the app under this name
+    Named(String),
+}
+
 #[async_trait]
 pub(crate) trait Listener: Send {
-    async fn access(&self) -> anyhow::Result<Access>;
+    async fn access(&self, port: &Port) -> anyhow::Result<Access>;
     fn is_public(&self) -> bool;
 }
@@ -52,6 +52,8 @@ pub(crate) struct Log {
     pub(crate) path: Option<String>,
     pub(crate) status: Option<u16>,
     pub(crate) message: Option<String>,
+    /// worker process the log comes from, None for the app itself
+    pub(crate) process: Option<String>,
 }
 
 impl Log {
@@ -72,6 +74,14 @@ impl Log {
             path: None,
             status: None,
             message: Some(value.message),
+            process: None,
+        }
+    }
+
+    pub(crate) fn from_process(value: DockerLog, deployment: NanoId, process: String) -> Self {
+        Self {
+            process: Some(process),
+            ..Self::from_docker(value, deployment)
         }
     }
 }
@@ -87,6 +97,7 @@ impl From<RequestLog> for Log {
             path: Some(value.path),
             status: Some(value.status),
             message: None,
+            process: None,
         }
     }
 }
@@ -108,6 +119,7 @@ impl From<BuildLog> for Log {
             path: None,
             status: None,
             message: Some(value.content),
+            process: None,
         }
     }
 }
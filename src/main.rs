@@ -2,34 +2,46 @@ use api::server::run_api_server;
 use conf::Conf;
 use db::Db;
 use deployments::manager::Manager;
-use github::Github;
+use postgres_proxy::run_postgres_proxy;
+use redis_proxy::run_redis_proxy;
 use proxy::run_proxy;
+use source::Sources;
 use tls::CertificateStore;
 use traces::init_tracing_subscriber;
 use tracing::info;
+use utils::LogError;
 
 mod api;
+mod backups;
+mod branching;
 mod conf;
 mod container;
+mod cron;
 mod db;
 mod deployments;
 mod docker;
 mod docker_bridge;
 mod env;
-mod github;
 mod hooks;
 mod label;
 mod listener;
 mod logging;
 mod nixpacks;
 mod paths;
+mod postgres_db;
+mod postgres_proxy;
 mod provider;
 mod proxy;
+mod redis_db;
+mod redis_proxy;
+mod s3_storage;
+mod source;
 mod sqlite_db;
 mod tls;
 mod tokens;
 mod traces;
 mod utils;
+mod volumes;
 
 #[tokio::main]
 async fn main() {
@@ -40,14 +52,14 @@ async fn main() {
     let cloned_conf = conf.clone();
 
     let db = Db::setup().await.unwrap();
-    let github = Github::new().await;
+    let sources = Sources::new().await;
 
     provider::setup_ip_address().await.unwrap();
 
     let certificates = CertificateStore::load(&conf).await;
     let manager = Manager::new(
         conf.hostname.clone(),
-        github.clone(),
+        sources.clone(),
         db.clone(),
         certificates.clone(),
     );
@@ -55,9 +67,15 @@ async fn main() {
 
     tokio::task::spawn_blocking(|| run_proxy(cloned_manager, cloned_conf, certificates));
 
-    manager.full_sync_with_github().await;
+    let cloned_manager = manager.clone();
+    tokio::spawn(async move { run_postgres_proxy(cloned_manager).await.ignore_logging() });
+
+    let cloned_manager = manager.clone();
+    tokio::spawn(async move { run_redis_proxy(cloned_manager).await.ignore_logging() });
+
+    manager.full_sync_with_repos().await;
 
-    run_api_server(manager, db, github, &conf.api_hostname(), conf.secret)
+    run_api_server(manager, db, sources, &conf.api_hostname(), conf.secret)
         .await
         .unwrap();
 }
@@ -4,27 +4,36 @@ use api::server::get_open_api;
 use conf::Conf;
 
 mod api;
+mod backups;
+mod branching;
 mod conf;
 mod container;
+mod cron;
 mod db;
 mod deployments;
 mod docker;
 mod docker_bridge;
 mod env;
-mod github;
 mod hooks;
 mod label;
 mod listener;
 mod logging;
 mod nixpacks;
 mod paths;
+mod postgres_db;
+mod postgres_proxy;
 mod provider;
 mod proxy;
+mod redis_db;
+mod redis_proxy;
+mod s3_storage;
+mod source;
 mod sqlite_db;
 mod tls;
 mod tokens;
 mod traces;
 mod utils;
+mod volumes;
 
 fn main() {
     let openapi = get_open_api();
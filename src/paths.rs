@@ -21,11 +21,24 @@ prezel
 ├── apps
 │    └── 6220587f-4888-4709-989e-95ac08056a5e
 │          ├── libsql -> this is the prod libsql db
-│          └── postgres
+│          ├── libsql-keys
+│          │     └── 1746871200000.pk8 -> signing keys for the prod libsql db, newest is active
+│          ├── postgres -> this is the prod postgres data
+│          ├── s3 -> this is the prod object storage data
+│          ├── redis -> this is the prod redis data, if the app asks for redis
+│          ├── volumes
+│          │     └── uploads -> prod data of the volume named uploads in prezel.json
+│          └── backups
+│                └── 1746871200000.tar.gz -> snapshot of the prod libsql db
 ├── deployments
 │    └── 10c1b2a4-39f6-4144-8620-a11e56b3232c
 │          ├── libsql -> this is the branch libsql db, if any
-│          └── postgres
+│          ├── libsql-keys -> signing keys for the branch libsql db, if any
+│          ├── postgres -> this is the branch postgres data, if any
+│          ├── s3 -> snapshot of the prod object storage, if any
+│          ├── redis -> this is the branch redis data, if any
+│          └── volumes
+│                └── uploads -> copy of the prod volume for preview deployments
 
 */
 
@@ -85,6 +98,33 @@ pub(crate) fn get_propd_libqsl_dir(id: &str) -> PathBuf {
     get_app_dir(id).join("libsql").create_if_missing()
 }
 
+pub(crate) fn get_prod_libsql_keys_dir(id: &str) -> PathBuf {
+    get_app_dir(id).join("libsql-keys").create_if_missing()
+}
+
+pub(crate) fn get_backups_dir(id: &str) -> PathBuf {
+    get_app_dir(id).join("backups").create_if_missing()
+}
+
+pub(crate) fn get_prod_postgres_dir(id: &str) -> PathBuf {
+    get_app_dir(id).join("postgres").create_if_missing()
+}
+
+pub(crate) fn get_prod_s3_dir(id: &str) -> PathBuf {
+    get_app_dir(id).join("s3").create_if_missing()
+}
+
+pub(crate) fn get_prod_redis_dir(id: &str) -> PathBuf {
+    get_app_dir(id).join("redis").create_if_missing()
+}
+
+pub(crate) fn get_prod_volume_dir(id: &str, name: &str) -> PathBuf {
+    get_app_dir(id)
+        .join("volumes")
+        .join(name)
+        .create_if_missing()
+}
+
 // TODO: make this return PathBuf ?
 pub(crate) fn get_deployments_dir() -> PathBuf {
     get_root().join("deployments").create_if_missing()
@@ -104,6 +144,37 @@ pub(crate) fn get_libsql_branch_dir(deployment: &str) -> PathBuf {
         .create_if_missing()
 }
 
+pub(crate) fn get_libsql_branch_keys_dir(deployment: &str) -> PathBuf {
+    get_deployment_dir(deployment)
+        .join("libsql-keys")
+        .create_if_missing()
+}
+
+pub(crate) fn get_postgres_branch_dir(deployment: &str) -> PathBuf {
+    get_deployment_dir(deployment)
+        .join("postgres")
+        .create_if_missing()
+}
+
+pub(crate) fn get_s3_branch_dir(deployment: &str) -> PathBuf {
+    get_deployment_dir(deployment)
+        .join("s3")
+        .create_if_missing()
+}
+
+pub(crate) fn get_redis_branch_dir(deployment: &str) -> PathBuf {
+    get_deployment_dir(deployment)
+        .join("redis")
+        .create_if_missing()
+}
+
+pub(crate) fn get_volume_branch_dir(deployment: &str, name: &str) -> PathBuf {
+    get_deployment_dir(deployment)
+        .join("volumes")
+        .join(name)
+        .create_if_missing()
+}
+
 fn iter_dir(path: &Path) -> impl Iterator<Item = PathBuf> {
     let paths = read_dir(path)
         .map(|paths| paths.collect::<Vec<_>>())
@@ -0,0 +1,144 @@
+use std::net::Ipv4Addr;
+
+use anyhow::{anyhow, bail, ensure};
+use tokio::{
+    io::{copy_bidirectional, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    deployments::manager::Manager,
+    listener::{Access, Listener, Port},
+    utils::LogError,
+};
+
+pub(crate) const POSTGRES_PORT: u16 = 5432;
+
+const SSL_REQUEST_CODE: i32 = 80877103;
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+const MAX_STARTUP_MESSAGE_LEN: usize = 10_000;
+/// the database name the client asks for is the container label, not a real database
+const DATABASE: &str = "postgres";
+
+/// Routes postgres connections to the right postgres container based on the database name
+/// sent by the client in the startup message, starting the container if needed
+pub(crate) async fn run_postgres_proxy(manager: Manager) -> anyhow::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, POSTGRES_PORT)).await?;
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            forward(socket, manager).await.ignore_logging();
+        });
+    }
+}
+
+async fn forward(mut inbound: TcpStream, manager: Manager) -> anyhow::Result<()> {
+    let mut startup = read_startup_message(&mut inbound).await?;
+    let label = startup
+        .get("database")
+        .or_else(|| startup.get("user")) // postgres defaults the database to the user name
+        .ok_or(anyhow!("startup message has no database"))?
+        .to_owned();
+    let container = manager
+        .get_postgres_container(&label)
+        .await
+        .ok_or(anyhow!("no postgres database found for {label}"))?;
+    let socket = match container.access(&Port::Http).await? {
+        Access::Socket(socket, _) => socket,
+        Access::Loading => bail!("postgres database {label} is not ready yet"),
+    };
+
+    startup.set("database", DATABASE);
+    let mut outbound = TcpStream::connect(socket).await?;
+    outbound.write_all(&startup.to_bytes()).await?;
+    copy_bidirectional(&mut inbound, &mut outbound).await?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct StartupMessage {
+    protocol: i32,
+    params: Vec<(String, String)>,
+}
+
+impl StartupMessage {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        self.params.retain(|(name, _)| name != key);
+        self.params.push((key.to_owned(), value.to_owned()));
+    }
+
+    fn parse(protocol: i32, body: &[u8]) -> anyhow::Result<Self> {
+        let mut fields = body.split(|byte| *byte == 0).map(String::from_utf8_lossy);
+        let mut params = vec![];
+        while let Some(name) = fields.next().filter(|name| !name.is_empty()) {
+            let value = fields.next().ok_or(anyhow!("missing startup parameter value"))?;
+            params.push((name.into_owned(), value.into_owned()));
+        }
+        Ok(Self { protocol, params })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut body = self.protocol.to_be_bytes().to_vec();
+        for (name, value) in &self.params {
+            body.extend_from_slice(name.as_bytes());
+            body.push(0);
+            body.extend_from_slice(value.as_bytes());
+            body.push(0);
+        }
+        body.push(0);
+        let len = body.len() as i32 + 4;
+        [len.to_be_bytes().to_vec(), body].concat()
+    }
+}
+
+/// SSL and GSS encryption requests are declined, as the connection never leaves the docker network
+async fn read_startup_message(stream: &mut TcpStream) -> anyhow::Result<StartupMessage> {
+    loop {
+        let len = stream.read_i32().await? as usize;
+        ensure!(
+            (8..=MAX_STARTUP_MESSAGE_LEN).contains(&len),
+            "invalid startup message length"
+        );
+        let code = stream.read_i32().await?;
+        let mut body = vec![0; len - 8];
+        stream.read_exact(&mut body).await?;
+        if code == SSL_REQUEST_CODE || code == GSSENC_REQUEST_CODE {
+            stream.write_all(b"N").await?;
+        } else {
+            return StartupMessage::parse(code, &body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod postgres_proxy_tests {
+    use super::StartupMessage;
+
+    #[test]
+    fn test_startup_message_roundtrip() {
+        let mut message = StartupMessage {
+            protocol: 196608,
+            params: vec![
+                ("user".to_owned(), "postgres".to_owned()),
+                ("database".to_owned(), "test-uuid--postgres".to_owned()),
+            ],
+        };
+        let bytes = message.to_bytes();
+        let len = i32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, bytes.len());
+        let parsed = StartupMessage::parse(196608, &bytes[8..]).unwrap();
+        assert_eq!(parsed, message);
+
+        message.set("database", "postgres");
+        assert_eq!(message.get("database"), Some("postgres"));
+        assert_eq!(message.get("user"), Some("postgres"));
+    }
+}
@@ -22,7 +22,7 @@ use crate::api::API_PORT;
 use crate::conf::Conf;
 use crate::db::nano_id::NanoId;
 use crate::deployments::manager::Manager;
-use crate::listener::{Access, Listener};
+use crate::listener::{Access, InFlight, Listener, Port};
 use crate::logging::{Level, RequestLog, RequestLogger};
 use crate::tls::{CertificateStore, TlsState};
 use crate::tokens::decode_auth_token;
@@ -33,7 +33,7 @@ struct ApiListener;
 // TODO: move this to api mod
 #[async_trait]
 impl Listener for ApiListener {
-    async fn access(&self) -> anyhow::Result<Access> {
+    async fn access(&self, _port: &Port) -> anyhow::Result<Access> {
         Ok(SocketAddrV4::new(Ipv4Addr::LOCALHOST, API_PORT).into())
     }
     fn is_public(&self) -> bool {
@@ -45,6 +45,7 @@ struct Peer {
     listener: Box<dyn Listener>,
     deployment_id: Option<NanoId>,
     insert_enabled: bool,
+    port: Port,
 }
 
 impl<L: Listener + 'static> From<L> for Peer {
@@ -53,6 +54,7 @@ impl<L: Listener + 'static> From<L> for Peer {
             listener: Box::new(value),
             deployment_id: None,
             insert_enabled: false,
+            port: Port::Http,
         }
     }
 }
@@ -73,12 +75,14 @@ impl ProxyApp {
         if host == self.config.api_hostname() {
             Some(ApiListener.into())
         } else {
-            let (container, insert_enabled) = self.manager.get_container_by_hostname(host).await?;
+            let (container, insert_enabled, port) =
+                self.manager.get_container_by_hostname(host).await?;
             let deployment_id = container.logging_deployment_id.clone();
             Some(Peer {
                 listener: Box::new(container),
                 deployment_id,
                 insert_enabled,
+                port,
             })
         }
     }
@@ -112,6 +116,8 @@ struct RequestCtx {
     deployment: Option<NanoId>,
     insert_enabled: bool,
     socket: Option<SocketAddrV4>,
+    /// keeps the request counted against the replica until the ctx is dropped
+    in_flight: InFlight,
 }
 
 #[async_trait]
@@ -140,6 +146,7 @@ impl ProxyHttp for ProxyApp {
             listener,
             deployment_id,
             insert_enabled,
+            port,
         } = self.get_listener(session).await?;
         ctx.deployment = deployment_id;
         ctx.insert_enabled = insert_enabled;
@@ -152,7 +159,7 @@ impl ProxyHttp for ProxyApp {
                     .unwrap();
             }
 
-            let access = listener.access().await.map_err(|error| {
+            let access = listener.access(&port).await.map_err(|error| {
                 Error::create(
                     Custom("Failed to aquire socket"),
                     ErrorSource::Unset, // FIXME: is this correct ??
@@ -161,8 +168,9 @@ impl ProxyHttp for ProxyApp {
                 )
             })?;
             match access {
-                Access::Socket(socket) => {
+                Access::Socket(socket, in_flight) => {
                     ctx.socket = Some(socket);
+                    ctx.in_flight = in_flight;
                     Ok(false)
                 }
                 Access::Loading => {
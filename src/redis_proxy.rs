@@ -0,0 +1,145 @@
+use std::net::Ipv4Addr;
+
+use anyhow::{anyhow, bail, ensure};
+use tokio::{
+    io::{copy_bidirectional, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    deployments::manager::Manager,
+    listener::{Access, Listener, Port},
+    utils::LogError,
+};
+
+pub(crate) const REDIS_PORT: u16 = 6379;
+
+const MAX_ARGS: usize = 16;
+const MAX_ARG_LEN: usize = 10_000;
+/// the user name the client sends is the container label, not a real redis user
+const USER: &str = "default";
+
+/// Routes redis connections to the right redis container based on the user name
+/// sent by the client to authenticate, starting the container if needed
+pub(crate) async fn run_redis_proxy(manager: Manager) -> anyhow::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, REDIS_PORT)).await?;
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            forward(socket, manager).await.ignore_logging();
+        });
+    }
+}
+
+async fn forward(inbound: TcpStream, manager: Manager) -> anyhow::Result<()> {
+    let mut inbound = BufReader::new(inbound);
+    let mut command = Command::read(&mut inbound).await?;
+    let Some(label) = command.take_user() else {
+        let error = b"-NOAUTH Authentication required with the user from PREZEL_REDIS_URL\r\n";
+        inbound.write_all(error).await?;
+        bail!("redis client did not authenticate with a user first");
+    };
+    let container = manager
+        .get_redis_container(&label)
+        .await
+        .ok_or(anyhow!("no redis database found for {label}"))?;
+    let socket = match container.access(&Port::Http).await? {
+        Access::Socket(socket, _) => socket,
+        Access::Loading => bail!("redis database {label} is not ready yet"),
+    };
+
+    let mut outbound = TcpStream::connect(socket).await?;
+    outbound.write_all(&command.to_bytes()).await?;
+    // commands pipelined right after the authentication might be already buffered
+    outbound.write_all(inbound.buffer()).await?;
+    let mut inbound = inbound.into_inner();
+    copy_bidirectional(&mut inbound, &mut outbound).await?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct Command(Vec<Vec<u8>>);
+
+impl Command {
+    fn name(&self) -> String {
+        let name = self.0.first().map(Vec::as_slice).unwrap_or_default();
+        String::from_utf8_lossy(name).to_uppercase()
+    }
+
+    /// Extracts the user name from `AUTH <user> <password>` or `HELLO <version> AUTH <user> <password>`,
+    /// replacing it with the default redis user so the command is accepted by the container
+    fn take_user(&mut self) -> Option<String> {
+        let index = match self.name().as_str() {
+            "AUTH" if self.0.len() == 3 => 1,
+            "HELLO" if self.0.len() >= 5 && self.0[2].eq_ignore_ascii_case(b"AUTH") => 3,
+            _ => return None,
+        };
+        let user = std::mem::replace(&mut self.0[index], USER.as_bytes().to_vec());
+        String::from_utf8(user).ok()
+    }
+
+    async fn read(stream: &mut BufReader<TcpStream>) -> anyhow::Result<Self> {
+        let len = read_header(stream, b'*').await?;
+        ensure!(len <= MAX_ARGS, "too many arguments in redis command");
+        let mut args = Vec::with_capacity(len);
+        for _ in 0..len {
+            let len = read_header(stream, b'$').await?;
+            ensure!(len <= MAX_ARG_LEN, "redis command argument too long");
+            let mut arg = vec![0; len + 2];
+            stream.read_exact(&mut arg).await?;
+            ensure!(arg.ends_with(b"\r\n"), "invalid redis command argument");
+            arg.truncate(len);
+            args.push(arg);
+        }
+        Ok(Self(args))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("*{}\r\n", self.0.len()).into_bytes();
+        for arg in &self.0 {
+            bytes.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            bytes.extend_from_slice(arg);
+            bytes.extend_from_slice(b"\r\n");
+        }
+        bytes
+    }
+}
+
+/// Reads a line like `*3\r\n` returning the number after the expected prefix
+async fn read_header(stream: &mut BufReader<TcpStream>, prefix: u8) -> anyhow::Result<usize> {
+    let mut line = vec![];
+    (&mut *stream).take(32).read_until(b'\n', &mut line).await?;
+    let line = line
+        .strip_prefix(&[prefix])
+        .and_then(|line| line.strip_suffix(b"\r\n"))
+        .ok_or(anyhow!("invalid redis command"))?;
+    Ok(std::str::from_utf8(line)?.parse()?)
+}
+
+#[cfg(test)]
+mod redis_proxy_tests {
+    use super::Command;
+
+    fn command(args: &[&str]) -> Command {
+        Command(args.iter().map(|arg| arg.as_bytes().to_vec()).collect())
+    }
+
+    #[test]
+    fn test_take_user() {
+        let mut auth = command(&["auth", "test-uuid--redis", "secret"]);
+        assert_eq!(auth.take_user().as_deref(), Some("test-uuid--redis"));
+        assert_eq!(auth, command(&["auth", "default", "secret"]));
+        assert_eq!(
+            auth.to_bytes(),
+            b"*3\r\n$4\r\nauth\r\n$7\r\ndefault\r\n$6\r\nsecret\r\n"
+        );
+
+        let mut hello = command(&["HELLO", "3", "AUTH", "test-uuid--abc-redis", "secret"]);
+        assert_eq!(hello.take_user().as_deref(), Some("test-uuid--abc-redis"));
+        assert_eq!(hello, command(&["HELLO", "3", "AUTH", "default", "secret"]));
+
+        assert_eq!(command(&["AUTH", "secret"]).take_user(), None);
+        assert_eq!(command(&["PING"]).take_user(), None);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    branching::create_branch,
+    container::{s3::S3Container, Container},
+    db::nano_id::NanoId,
+    deployments::worker::WorkerHandle,
+    paths::{get_prod_s3_dir, get_s3_branch_dir},
+    postgres_db::derive_secret,
+};
+
+const ACCESS_KEY: &str = "prezel";
+/// The storage server accepts any region, this is only here for clients requiring one
+pub(crate) const REGION: &str = "us-east-1";
+
+#[derive(Debug)]
+pub(crate) struct ProdS3Storage {
+    pub(crate) setup: S3Setup,
+    build_queue: WorkerHandle,
+}
+
+impl ProdS3Storage {
+    #[tracing::instrument]
+    pub(crate) fn new(project_id: &NanoId, build_queue: WorkerHandle) -> Self {
+        let folder = get_prod_s3_dir(project_id.as_str());
+        let secret_key = derive_secret("s3", project_id);
+        let container =
+            S3Container::new(folder.clone(), ACCESS_KEY, &secret_key, build_queue.clone());
+        Self {
+            setup: S3Setup {
+                folder,
+                container: container.into(),
+                secret_key,
+            },
+            build_queue,
+        }
+    }
+
+    /// The root credentials are only taken from the env, so the copy of the prod data
+    /// is served with a secret key of its own and previews never get to know the prod one
+    #[tracing::instrument]
+    pub(crate) fn branch(&self, deployment_id: &NanoId) -> BranchS3Storage {
+        BranchS3Storage {
+            base_folder: self.setup.folder.clone(),
+            base_container: self.setup.container.clone(),
+            branch_folder: get_s3_branch_dir(deployment_id.as_str()),
+            build_queue: self.build_queue.clone(),
+            secret_key: derive_secret("s3", deployment_id),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct BranchS3Storage {
+    base_folder: PathBuf,
+    base_container: Arc<Container>,
+    branch_folder: PathBuf,
+    build_queue: WorkerHandle,
+    secret_key: String,
+}
+
+impl std::fmt::Debug for BranchS3Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BranchS3Storage")
+            .field("base_folder", &self.base_folder)
+            .field("branch_folder", &self.branch_folder)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BranchS3Storage {
+    /// The branch starts as a snapshot of every prod bucket, taken only the first time.
+    /// The prod container is paused during the copy, so no object is copied half written
+    #[tracing::instrument]
+    pub(crate) async fn setup(&self) -> anyhow::Result<S3Setup> {
+        let copy = create_branch(&self.base_folder, &self.branch_folder, |_| false);
+        let created = self.base_container.with_paused(copy).await??;
+        if created {
+            // uploads in progress in prod are of no use for the branch
+            let tmp = self.branch_folder.join(".minio.sys").join("tmp");
+            let _ = tokio::fs::remove_dir_all(tmp).await;
+        }
+        let container = S3Container::new(
+            self.branch_folder.clone(),
+            ACCESS_KEY,
+            &self.secret_key,
+            self.build_queue.clone(),
+        );
+        Ok(S3Setup {
+            folder: self.branch_folder.clone(),
+            container: container.into(),
+            secret_key: self.secret_key.clone(),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct S3Setup {
+    pub(crate) folder: PathBuf,
+    pub(crate) container: Arc<Container>,
+    secret_key: String,
+}
+
+impl std::fmt::Debug for S3Setup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Setup")
+            .field("folder", &self.folder)
+            .field("container", &self.container)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Setup {
+    pub(crate) fn get_access_key(&self) -> &str {
+        ACCESS_KEY
+    }
+
+    pub(crate) fn get_secret_key(&self) -> &str {
+        &self.secret_key
+    }
+}
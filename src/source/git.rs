@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use anyhow::{anyhow, ensure};
+use async_trait::async_trait;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+use super::{ChangeRequest, Comment, Commit, CommitStatus, SourceProvider};
+
+/// Any repository reachable by the `git` binary over HTTPS or SSH.
+/// Credentials are taken from the host (embedded in the url, ssh keys, credential helpers...).
+/// There is no concept of change requests nor commit statuses, so only the
+/// default branch gets deployed
+#[derive(Debug)]
+pub(crate) struct GitRepo {
+    url: String,
+}
+
+impl GitRepo {
+    pub(crate) fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+        }
+    }
+
+    /// Fetches a single commit, branch or sha, into a fresh repository at `path`.
+    /// Arguments coming from the user always go after `--`, so they are never taken as options
+    async fn fetch(&self, path: &Path, commitish: &str) -> anyhow::Result<()> {
+        git(path, &["init", "--quiet"]).await?;
+        git(path, &["remote", "add", "--", "origin", &self.url]).await?;
+        git(
+            path,
+            &[
+                "fetch", "--quiet", "--depth", "1", "--", "origin", commitish,
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SourceProvider for GitRepo {
+    #[tracing::instrument]
+    async fn get_default_branch(&self) -> anyhow::Result<String> {
+        let output = git(
+            Path::new("."),
+            &["ls-remote", "--symref", "--", &self.url, "HEAD"],
+        )
+        .await?;
+        // the first line looks like "ref: refs/heads/main\tHEAD"
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix("ref: refs/heads/"))
+            .and_then(|line| line.split_whitespace().next())
+            .map(ToOwned::to_owned)
+            .ok_or(anyhow!(
+                "could not find the default branch for {}",
+                self.url
+            ))
+    }
+
+    #[tracing::instrument]
+    async fn get_latest_commit(&self, branch: &str) -> anyhow::Result<Commit> {
+        let tempdir = TempDir::new()?;
+        self.fetch(tempdir.path(), branch).await?;
+        let output = git(
+            tempdir.path(),
+            &["log", "-1", "--format=%H %ct", "FETCH_HEAD"],
+        )
+        .await?;
+        let (sha, seconds) = output
+            .trim()
+            .split_once(' ')
+            .ok_or(anyhow!("unexpected git log output {output}"))?;
+        Ok(Commit {
+            timestamp: seconds.parse::<i64>()? * 1000,
+            sha: sha.to_owned(),
+        })
+    }
+
+    async fn get_open_change_requests(&self) -> anyhow::Result<Vec<ChangeRequest>> {
+        Ok(vec![])
+    }
+
+    #[tracing::instrument]
+    async fn download_file(&self, sha: &str, path: &str) -> anyhow::Result<Option<String>> {
+        let tempdir = TempDir::new()?;
+        self.fetch(tempdir.path(), sha).await?;
+        let listed = git(tempdir.path(), &["ls-tree", "--name-only", sha, "--", path]).await?;
+        if listed.trim().is_empty() {
+            Ok(None)
+        } else {
+            let object = format!("{sha}:{path}");
+            Ok(Some(git(tempdir.path(), &["show", &object]).await?))
+        }
+    }
+
+    #[tracing::instrument]
+    async fn download_commit(&self, sha: &str, path: &Path) -> anyhow::Result<()> {
+        self.fetch(path, sha).await?;
+        git(path, &["checkout", "--quiet", "FETCH_HEAD"]).await?;
+        let submodules = [
+            "submodule",
+            "update",
+            "--init",
+            "--recursive",
+            "--depth",
+            "1",
+        ];
+        // if some submodule is not accessible, we just assume is fine not downloading it
+        let _ = git(path, &submodules).await;
+        tokio::fs::remove_dir_all(path.join(".git")).await?;
+        Ok(())
+    }
+
+    async fn report_status(
+        &self,
+        _sha: &str,
+        _name: &str,
+        _status: CommitStatus,
+        _details_url: &str,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn find_comment(&self, _change: u64, _prefix: &str) -> anyhow::Result<Option<Comment>> {
+        Ok(None)
+    }
+
+    async fn upsert_comment(
+        &self,
+        _change: u64,
+        _comment: Option<u64>,
+        _content: &str,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+async fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await?;
+    ensure!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.first().unwrap_or(&""),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8(output.stdout)?)
+}
@@ -0,0 +1,211 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::DateTime;
+use http::{Method, StatusCode};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::utils::now;
+
+use super::{unpack_tarball, ChangeRequest, Comment, Commit, CommitStatus, SourceProvider};
+
+#[derive(Deserialize)]
+struct ApiRepository {
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct ApiBranchCommit {
+    id: String,
+    timestamp: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiBranch {
+    commit: ApiBranchCommit,
+}
+
+#[derive(Deserialize)]
+struct ApiPullHead {
+    #[serde(rename = "ref")]
+    git_ref: String,
+}
+
+#[derive(Deserialize)]
+struct ApiPull {
+    number: u64,
+    head: ApiPullHead,
+}
+
+#[derive(Deserialize)]
+struct ApiComment {
+    id: u64,
+    body: String,
+}
+
+/// Gitea (or Forgejo) repository. Private repositories need a token for the host
+/// in `source_tokens` inside config.json
+#[derive(Debug)]
+pub(crate) struct GiteaRepo {
+    host: String,
+    owner: String,
+    name: String,
+    token: Option<String>,
+    client: Client,
+}
+
+impl GiteaRepo {
+    pub(crate) fn new(host: &str, owner: &str, name: &str, token: Option<String>) -> Self {
+        Self {
+            host: host.to_owned(),
+            owner: owner.to_owned(),
+            name: name.to_owned(),
+            token,
+            client: Client::new(),
+        }
+    }
+
+    fn url(&self, segments: &[&str]) -> anyhow::Result<Url> {
+        let mut url = Url::parse(&format!("https://{}/api/v1/repos", self.host))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("invalid gitea host {}", self.host))?
+            .push(&self.owner)
+            .push(&self.name)
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let builder = self.client.request(method, url);
+        match &self.token {
+            Some(token) => builder.header("Authorization", format!("token {token}")),
+            None => builder,
+        }
+    }
+}
+
+#[async_trait]
+impl SourceProvider for GiteaRepo {
+    #[tracing::instrument]
+    async fn get_default_branch(&self) -> anyhow::Result<String> {
+        let url = self.url(&[])?;
+        let response = self.request(Method::GET, url).send().await?;
+        let repository: ApiRepository = response.error_for_status()?.json().await?;
+        Ok(repository.default_branch)
+    }
+
+    #[tracing::instrument]
+    async fn get_latest_commit(&self, branch: &str) -> anyhow::Result<Commit> {
+        let url = self.url(&["branches", branch])?;
+        let response = self.request(Method::GET, url).send().await?;
+        let branch: ApiBranch = response.error_for_status()?.json().await?;
+        let timestamp = branch
+            .commit
+            .timestamp
+            .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+            .map(|date| date.timestamp_millis())
+            .unwrap_or(now());
+        Ok(Commit {
+            timestamp,
+            sha: branch.commit.id,
+        })
+    }
+
+    #[tracing::instrument]
+    async fn get_open_change_requests(&self) -> anyhow::Result<Vec<ChangeRequest>> {
+        let mut url = self.url(&["pulls"])?;
+        url.query_pairs_mut()
+            .append_pair("state", "open")
+            .append_pair("limit", "100");
+        let response = self.request(Method::GET, url).send().await?;
+        let pulls: Vec<ApiPull> = response.error_for_status()?.json().await?;
+        Ok(pulls
+            .into_iter()
+            .map(|pull| ChangeRequest {
+                number: pull.number,
+                branch: pull.head.git_ref,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument]
+    async fn download_file(&self, sha: &str, path: &str) -> anyhow::Result<Option<String>> {
+        let segments: Vec<_> = ["raw"].into_iter().chain(path.split('/')).collect();
+        let mut url = self.url(&segments)?;
+        url.query_pairs_mut().append_pair("ref", sha);
+        let response = self.request(Method::GET, url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Ok(Some(response.error_for_status()?.text().await?))
+        }
+    }
+
+    #[tracing::instrument]
+    async fn download_commit(&self, sha: &str, path: &Path) -> anyhow::Result<()> {
+        let url = self.url(&["archive", &format!("{sha}.tar.gz")])?;
+        let response = self.request(Method::GET, url).send().await?;
+        let bytes = response.error_for_status()?.bytes().await?;
+        unpack_tarball(&bytes, path)
+    }
+
+    #[tracing::instrument]
+    async fn report_status(
+        &self,
+        sha: &str,
+        name: &str,
+        status: CommitStatus,
+        details_url: &str,
+    ) -> anyhow::Result<()> {
+        let state = match status {
+            CommitStatus::Running => "pending",
+            CommitStatus::Success => "success",
+            CommitStatus::Failure => "failure",
+            CommitStatus::TimedOut => "error",
+            // gitea has no cancelled state
+            CommitStatus::Cancelled => "warning",
+        };
+        let url = self.url(&["statuses", sha])?;
+        let body = json!({ "state": state, "context": name, "target_url": details_url });
+        let response = self.request(Method::POST, url).json(&body).send().await?;
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn find_comment(&self, change: u64, prefix: &str) -> anyhow::Result<Option<Comment>> {
+        let url = self.url(&["issues", &change.to_string(), "comments"])?;
+        let response = self.request(Method::GET, url).send().await?;
+        let comments: Vec<ApiComment> = response.error_for_status()?.json().await?;
+        Ok(comments
+            .into_iter()
+            .find(|comment| comment.body.starts_with(prefix))
+            .map(|comment| Comment {
+                id: comment.id,
+                body: comment.body,
+            }))
+    }
+
+    #[tracing::instrument]
+    async fn upsert_comment(
+        &self,
+        change: u64,
+        comment: Option<u64>,
+        content: &str,
+    ) -> anyhow::Result<()> {
+        let request = if let Some(comment) = comment {
+            let url = self.url(&["issues", "comments", &comment.to_string()])?;
+            self.request(Method::PATCH, url)
+        } else {
+            let url = self.url(&["issues", &change.to_string(), "comments"])?;
+            self.request(Method::POST, url)
+        };
+        let response = request.json(&json!({ "body": content })).send().await?;
+        response.error_for_status()?;
+        Ok(())
+    }
+}
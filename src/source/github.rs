@@ -1,10 +1,10 @@
 use anyhow::{anyhow, bail};
-use flate2::read::GzDecoder;
+use async_trait::async_trait;
 use gitmodules::read_gitmodules;
 use http::StatusCode;
 use http_body_util::BodyExt;
 use octocrab::{
-    models::{issues::Comment, pulls::PullRequest, CommentId, IssueState, Repository},
+    models::{pulls::PullRequest, CommentId, IssueState, Repository},
     params::{
         checks::{CheckRunConclusion, CheckRunStatus},
         repos::Commitish,
@@ -12,17 +12,13 @@ use octocrab::{
     repos::GetContentBuilder,
     Error as OctoError, Octocrab,
 };
-use std::{collections::HashMap, io::Cursor, path::Path, sync::Arc};
-use tar::Archive;
-use tokio::sync::{Mutex, MutexGuard, RwLock};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::sync::RwLock;
 use url::Url;
 
 use crate::{provider, utils::now};
 
-pub(crate) struct Commit {
-    pub(crate) timestamp: i64,
-    pub(crate) sha: String,
-}
+use super::{unpack_tarball, ChangeRequest, Comment, Commit, CommitStatus, SourceProvider};
 
 #[derive(Debug, Clone)]
 struct Token {
@@ -33,14 +29,12 @@ struct Token {
 #[derive(Clone, Debug)]
 pub(crate) struct Github {
     tokens: Arc<RwLock<HashMap<i64, Token>>>,
-    bot_mutex: Arc<Mutex<()>>,
 }
 
 impl Github {
     pub(crate) async fn new() -> Self {
         Self {
             tokens: Default::default(),
-            bot_mutex: Mutex::new(()).into(),
         }
     }
 
@@ -174,13 +168,6 @@ impl Github {
             }
         }
     }
-
-    pub(crate) async fn allocate_bot(&self) -> GithubBot {
-        GithubBot {
-            github: self.clone(),
-            _guard: self.bot_mutex.lock().await,
-        }
-    }
 }
 
 #[derive(Debug)]
@@ -202,16 +189,7 @@ async fn download_commit_from_ref(
         .download_tarball(sha.clone())
         .await?;
     let bytes = response.into_body().collect().await?.to_bytes();
-    let content = Cursor::new(bytes);
-    let mut archive = Archive::new(GzDecoder::new(content));
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let entry_path = entry.path()?;
-        let mut components = entry_path.components();
-        components.next();
-        let inner_path = components.as_path();
-        entry.unpack(&path.join(inner_path))?;
-    }
+    unpack_tarball(&bytes, path)?;
 
     if let Ok(content) = tokio::fs::read_to_string(path.join(".gitmodules")).await {
         let modules = read_gitmodules(content.as_bytes())?;
@@ -253,107 +231,140 @@ async fn parse_submodule<'octo, 'r>(builder: GetContentBuilder<'octo, 'r>) -> Op
     }
 }
 
+/// GitHub repository, accessed with the tokens handed out by the prezel GitHub app
 #[derive(Debug)]
-pub(crate) struct GithubBot<'a> {
+pub(crate) struct GithubRepo {
     github: Github,
-    _guard: MutexGuard<'a, ()>,
+    id: i64,
+}
+
+impl GithubRepo {
+    pub(crate) fn new(github: Github, id: i64) -> Self {
+        Self { github, id }
+    }
 }
 
-impl<'a> GithubBot<'a> {
+#[async_trait]
+impl SourceProvider for GithubRepo {
+    async fn get_default_branch(&self) -> anyhow::Result<String> {
+        self.github.get_default_branch(self.id).await
+    }
+
+    async fn get_latest_commit(&self, branch: &str) -> anyhow::Result<Commit> {
+        self.github.get_latest_commit(self.id, branch).await
+    }
+
+    async fn get_open_change_requests(&self) -> anyhow::Result<Vec<ChangeRequest>> {
+        let pulls = self.github.get_open_pulls(self.id).await?;
+        Ok(pulls
+            .into_iter()
+            .map(|pull| ChangeRequest {
+                number: pull.number,
+                branch: pull.head.ref_field,
+            })
+            .collect())
+    }
+
+    async fn download_file(&self, sha: &str, path: &str) -> anyhow::Result<Option<String>> {
+        self.github.download_file(self.id, sha, path).await
+    }
+
+    async fn download_commit(&self, sha: &str, path: &Path) -> anyhow::Result<()> {
+        self.github
+            .download_commit(self.id, sha.to_owned(), path)
+            .await
+    }
+
     #[tracing::instrument]
-    pub(crate) async fn upsert_pull_check(
+    async fn report_status(
         &self,
-        repo_id: i64,
         sha: &str,
-        check_name: &str,
-        status: CheckRunStatus,
-        conclusion: Option<CheckRunConclusion>,
+        name: &str,
+        status: CommitStatus,
+        details_url: &str,
     ) -> anyhow::Result<()> {
-        let crab = self.github.get_crab(repo_id).await?;
-        let (owner, name) = self.github.get_owner_and_name(repo_id).await?;
-        let check_handler = crab.checks(owner, name);
+        let (status, conclusion) = match status {
+            CommitStatus::Running => (CheckRunStatus::InProgress, None),
+            CommitStatus::Success => (CheckRunStatus::Completed, Some(CheckRunConclusion::Success)),
+            CommitStatus::Failure => (CheckRunStatus::Completed, Some(CheckRunConclusion::Failure)),
+            CommitStatus::TimedOut => (
+                CheckRunStatus::Completed,
+                Some(CheckRunConclusion::TimedOut),
+            ),
+            CommitStatus::Cancelled => (
+                CheckRunStatus::Completed,
+                Some(CheckRunConclusion::Cancelled),
+            ),
+        };
+        let crab = self.github.get_crab(self.id).await?;
+        let (owner, repo) = self.github.get_owner_and_name(self.id).await?;
+        let check_handler = crab.checks(owner, repo);
         let checks = check_handler
             .list_check_runs_for_git_ref(Commitish(sha.into()))
             .send()
-            .await
-            .unwrap();
+            .await?;
 
-        let app_check = checks
-            .check_runs
-            .iter()
-            .find(|check| check.name == check_name);
+        let app_check = checks.check_runs.iter().find(|check| check.name == name);
 
         match app_check {
             Some(check) => {
-                let mut builder = check_handler.update_check_run(check.id).status(status);
+                let mut builder = check_handler
+                    .update_check_run(check.id)
+                    .details_url(details_url)
+                    .status(status);
                 if let Some(conclusion) = conclusion {
                     builder = builder.conclusion(conclusion);
                 }
-                builder.send().await.unwrap();
+                builder.send().await?;
             }
             None => {
                 let mut builder = check_handler
-                    .create_check_run(check_name, sha)
-                    // .details_url(details_url) // TODO: add this -> have a look at the vercel details URL
+                    .create_check_run(name, sha)
+                    .details_url(details_url)
                     .status(status);
-
                 if let Some(conclusion) = conclusion {
                     builder = builder.conclusion(conclusion);
                 }
-                builder.send().await.unwrap();
+                builder.send().await?;
             }
         }
         Ok(())
     }
 
-    pub(crate) async fn read_pull_comment_with_prefix(
-        &self,
-        repo_id: i64,
-        pull: u64,
-        prefix: &str,
-    ) -> anyhow::Result<Option<Comment>> {
-        let crab = self.github.get_crab(repo_id).await?;
-        let (owner, name) = self.github.get_owner_and_name(repo_id).await?;
+    #[tracing::instrument]
+    async fn find_comment(&self, change: u64, prefix: &str) -> anyhow::Result<Option<Comment>> {
+        let crab = self.github.get_crab(self.id).await?;
+        let (owner, name) = self.github.get_owner_and_name(self.id).await?;
         let comments = crab
             .issues(&owner, &name)
-            .list_comments(pull)
+            .list_comments(change)
             .send()
             .await?;
-        let comment = comments.items.into_iter().find(|comment| {
-            let body = comment.body.as_ref();
-            body.is_some_and(|body| body.starts_with(prefix))
+        let comment = comments.items.into_iter().find_map(|comment| {
+            let body = comment.body.filter(|body| body.starts_with(prefix))?;
+            Some(Comment {
+                id: comment.id.into_inner(),
+                body,
+            })
         });
         Ok(comment)
     }
 
     #[tracing::instrument]
-    pub(crate) async fn create_pull_comment(
+    async fn upsert_comment(
         &self,
-        repo_id: i64,
-        pull: u64,
+        change: u64,
+        comment: Option<u64>,
         content: &str,
     ) -> anyhow::Result<()> {
-        let crab = self.github.get_crab(repo_id).await?;
-        let (owner, name) = self.github.get_owner_and_name(repo_id).await?;
-        crab.issues(owner, name)
-            .create_comment(pull, content)
-            .await?;
-        Ok(())
-    }
-
-    #[tracing::instrument]
-    pub(crate) async fn update_pull_comment(
-        &self,
-        repo_id: i64,
-        pull: u64,
-        comment: CommentId,
-        content: &str,
-    ) -> anyhow::Result<()> {
-        let crab = self.github.get_crab(repo_id).await?;
-        let (owner, name) = self.github.get_owner_and_name(repo_id).await?;
-        crab.issues(owner, name)
-            .update_comment(comment, content)
-            .await?;
+        let crab = self.github.get_crab(self.id).await?;
+        let (owner, name) = self.github.get_owner_and_name(self.id).await?;
+        let issues = crab.issues(owner, name);
+        if let Some(comment) = comment {
+            issues.update_comment(CommentId(comment), content).await?;
+        } else {
+            issues.create_comment(change, content).await?;
+        }
         Ok(())
     }
 }
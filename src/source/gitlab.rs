@@ -0,0 +1,197 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::DateTime;
+use http::{Method, StatusCode};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::utils::now;
+
+use super::{unpack_tarball, ChangeRequest, Comment, Commit, CommitStatus, SourceProvider};
+
+#[derive(Deserialize)]
+struct ApiProject {
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct ApiCommit {
+    id: String,
+    committed_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiMergeRequest {
+    iid: u64,
+    source_branch: String,
+}
+
+#[derive(Deserialize)]
+struct ApiNote {
+    id: u64,
+    body: String,
+}
+
+/// GitLab project, either on gitlab.com or on a self-hosted instance.
+/// Private projects need a token for the host in `source_tokens` inside config.json
+#[derive(Debug)]
+pub(crate) struct GitlabRepo {
+    host: String,
+    project: String,
+    token: Option<String>,
+    client: Client,
+}
+
+impl GitlabRepo {
+    pub(crate) fn new(host: &str, project: &str, token: Option<String>) -> Self {
+        Self {
+            host: host.to_owned(),
+            project: project.to_owned(),
+            token,
+            client: Client::new(),
+        }
+    }
+
+    /// The project path is sent url-encoded as a single segment, as expected by the GitLab API
+    fn url(&self, segments: &[&str]) -> anyhow::Result<Url> {
+        let mut url = Url::parse(&format!("https://{}/api/v4/projects", self.host))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("invalid gitlab host {}", self.host))?
+            .push(&self.project)
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let builder = self.client.request(method, url);
+        match &self.token {
+            Some(token) => builder.header("PRIVATE-TOKEN", token),
+            None => builder,
+        }
+    }
+}
+
+#[async_trait]
+impl SourceProvider for GitlabRepo {
+    #[tracing::instrument]
+    async fn get_default_branch(&self) -> anyhow::Result<String> {
+        let url = self.url(&[])?;
+        let response = self.request(Method::GET, url).send().await?;
+        let project: ApiProject = response.error_for_status()?.json().await?;
+        Ok(project.default_branch)
+    }
+
+    #[tracing::instrument]
+    async fn get_latest_commit(&self, branch: &str) -> anyhow::Result<Commit> {
+        let url = self.url(&["repository", "commits", branch])?;
+        let response = self.request(Method::GET, url).send().await?;
+        let commit: ApiCommit = response.error_for_status()?.json().await?;
+        let timestamp = commit
+            .committed_date
+            .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+            .map(|date| date.timestamp_millis())
+            .unwrap_or(now());
+        Ok(Commit {
+            timestamp,
+            sha: commit.id,
+        })
+    }
+
+    #[tracing::instrument]
+    async fn get_open_change_requests(&self) -> anyhow::Result<Vec<ChangeRequest>> {
+        let mut url = self.url(&["merge_requests"])?;
+        url.query_pairs_mut()
+            .append_pair("state", "opened")
+            .append_pair("per_page", "100");
+        let response = self.request(Method::GET, url).send().await?;
+        let merge_requests: Vec<ApiMergeRequest> = response.error_for_status()?.json().await?;
+        Ok(merge_requests
+            .into_iter()
+            .map(|merge_request| ChangeRequest {
+                number: merge_request.iid,
+                branch: merge_request.source_branch,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument]
+    async fn download_file(&self, sha: &str, path: &str) -> anyhow::Result<Option<String>> {
+        let mut url = self.url(&["repository", "files", path, "raw"])?;
+        url.query_pairs_mut().append_pair("ref", sha);
+        let response = self.request(Method::GET, url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Ok(Some(response.error_for_status()?.text().await?))
+        }
+    }
+
+    #[tracing::instrument]
+    async fn download_commit(&self, sha: &str, path: &Path) -> anyhow::Result<()> {
+        let mut url = self.url(&["repository", "archive.tar.gz"])?;
+        url.query_pairs_mut().append_pair("sha", sha);
+        let response = self.request(Method::GET, url).send().await?;
+        let bytes = response.error_for_status()?.bytes().await?;
+        unpack_tarball(&bytes, path)
+    }
+
+    #[tracing::instrument]
+    async fn report_status(
+        &self,
+        sha: &str,
+        name: &str,
+        status: CommitStatus,
+        details_url: &str,
+    ) -> anyhow::Result<()> {
+        let state = match status {
+            CommitStatus::Running => "running",
+            CommitStatus::Success => "success",
+            CommitStatus::Failure | CommitStatus::TimedOut => "failed",
+            CommitStatus::Cancelled => "canceled",
+        };
+        let url = self.url(&["statuses", sha])?;
+        let body = json!({ "state": state, "name": name, "target_url": details_url });
+        let response = self.request(Method::POST, url).json(&body).send().await?;
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn find_comment(&self, change: u64, prefix: &str) -> anyhow::Result<Option<Comment>> {
+        let mut url = self.url(&["merge_requests", &change.to_string(), "notes"])?;
+        url.query_pairs_mut().append_pair("per_page", "100");
+        let response = self.request(Method::GET, url).send().await?;
+        let notes: Vec<ApiNote> = response.error_for_status()?.json().await?;
+        Ok(notes
+            .into_iter()
+            .find(|note| note.body.starts_with(prefix))
+            .map(|note| Comment {
+                id: note.id,
+                body: note.body,
+            }))
+    }
+
+    #[tracing::instrument]
+    async fn upsert_comment(
+        &self,
+        change: u64,
+        comment: Option<u64>,
+        content: &str,
+    ) -> anyhow::Result<()> {
+        let change = change.to_string();
+        let request = if let Some(comment) = comment {
+            let url = self.url(&["merge_requests", &change, "notes", &comment.to_string()])?;
+            self.request(Method::PUT, url)
+        } else {
+            let url = self.url(&["merge_requests", &change, "notes"])?;
+            self.request(Method::POST, url)
+        };
+        let response = request.json(&json!({ "body": content })).send().await?;
+        response.error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+use std::{fmt, io::Cursor, path::Path, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use tar::Archive;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::conf::Conf;
+
+pub(crate) mod git;
+pub(crate) mod gitea;
+pub(crate) mod github;
+pub(crate) mod gitlab;
+
+use git::GitRepo;
+use gitea::GiteaRepo;
+use github::{Github, GithubRepo};
+use gitlab::GitlabRepo;
+
+pub(crate) struct Commit {
+    pub(crate) timestamp: i64,
+    pub(crate) sha: String,
+}
+
+/// A pull request on GitHub and Gitea or a merge request on GitLab
+#[derive(Debug)]
+pub(crate) struct ChangeRequest {
+    pub(crate) number: u64,
+    pub(crate) branch: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Comment {
+    pub(crate) id: u64,
+    pub(crate) body: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum CommitStatus {
+    Running,
+    Success,
+    Failure,
+    TimedOut,
+    /// the build was cancelled in favour of a newer commit
+    Cancelled,
+}
+
+/// Everything prezel needs from the place hosting the code of a project.
+/// Each instance is bound to a single repository
+#[async_trait]
+pub(crate) trait SourceProvider: Send + Sync + fmt::Debug {
+    async fn get_default_branch(&self) -> anyhow::Result<String>;
+    async fn get_latest_commit(&self, branch: &str) -> anyhow::Result<Commit>;
+    async fn get_open_change_requests(&self) -> anyhow::Result<Vec<ChangeRequest>>;
+    /// None means the file does not exist
+    async fn download_file(&self, sha: &str, path: &str) -> anyhow::Result<Option<String>>;
+    /// Writes the content of the commit, without any git metadata, into `path`
+    async fn download_commit(&self, sha: &str, path: &Path) -> anyhow::Result<()>;
+    async fn report_status(
+        &self,
+        sha: &str,
+        name: &str,
+        status: CommitStatus,
+        details_url: &str,
+    ) -> anyhow::Result<()>;
+    async fn find_comment(&self, change: u64, prefix: &str) -> anyhow::Result<Option<Comment>>;
+    /// Creates a new comment if `comment` is None, otherwise replaces its content
+    async fn upsert_comment(
+        &self,
+        change: u64,
+        comment: Option<u64>,
+        content: &str,
+    ) -> anyhow::Result<()>;
+}
+
+/// Provider-qualified reference to a repository, stored as a string like:
+/// - `github:<repo id>`
+/// - `gitlab:<host>/<namespace>/<project>`
+/// - `gitea:<host>/<owner>/<repo>`
+/// - `git:<https or ssh url>`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) enum RepoRef {
+    Github(i64),
+    Gitlab {
+        host: String,
+        project: String,
+    },
+    Gitea {
+        host: String,
+        owner: String,
+        name: String,
+    },
+    Git(String),
+}
+
+impl FromStr for RepoRef {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (provider, location) = value
+            .split_once(':')
+            .ok_or(anyhow!("missing provider in repo reference {value}"))?;
+        match provider {
+            "github" => Ok(Self::Github(location.parse()?)),
+            "gitlab" => match location.split_once('/') {
+                Some((host, project)) if !host.is_empty() && project.contains('/') => {
+                    Ok(Self::Gitlab {
+                        host: host.to_owned(),
+                        project: project.trim_end_matches('/').to_owned(),
+                    })
+                }
+                _ => bail!("invalid gitlab repo reference {value}"),
+            },
+            "gitea" => match location.split('/').collect::<Vec<_>>().as_slice() {
+                &[host, owner, name] if [host, owner, name].iter().all(|s| !s.is_empty()) => {
+                    Ok(Self::Gitea {
+                        host: host.to_owned(),
+                        owner: owner.to_owned(),
+                        name: name.to_owned(),
+                    })
+                }
+                _ => bail!("invalid gitea repo reference {value}"),
+            },
+            "git" if !location.is_empty() => Ok(Self::Git(location.to_owned())),
+            _ => bail!("invalid repo reference {value}"),
+        }
+    }
+}
+
+impl fmt::Display for RepoRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Github(id) => write!(f, "github:{id}"),
+            Self::Gitlab { host, project } => write!(f, "gitlab:{host}/{project}"),
+            Self::Gitea { host, owner, name } => write!(f, "gitea:{host}/{owner}/{name}"),
+            Self::Git(url) => write!(f, "git:{url}"),
+        }
+    }
+}
+
+impl TryFrom<String> for RepoRef {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RepoRef> for String {
+    fn from(value: RepoRef) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Sources {
+    github: Github,
+    comments_mutex: Arc<Mutex<()>>,
+}
+
+impl Sources {
+    pub(crate) async fn new() -> Self {
+        Self {
+            github: Github::new().await,
+            comments_mutex: Mutex::new(()).into(),
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) async fn provider(&self, repo: &RepoRef) -> Box<dyn SourceProvider> {
+        let Conf { source_tokens, .. } = Conf::read_async().await;
+        let token = |host: &str| source_tokens.get(host).cloned();
+        match repo {
+            RepoRef::Github(id) => Box::new(GithubRepo::new(self.github.clone(), *id)),
+            RepoRef::Gitlab { host, project } => {
+                Box::new(GitlabRepo::new(host, project, token(host)))
+            }
+            RepoRef::Gitea { host, owner, name } => {
+                Box::new(GiteaRepo::new(host, owner, name, token(host)))
+            }
+            RepoRef::Git(url) => Box::new(GitRepo::new(url)),
+        }
+    }
+
+    /// Comments are read and then updated, so concurrent status updates need to be serialized
+    /// to avoid several comments being created for the same change request
+    pub(crate) async fn lock_comments(&self) -> MutexGuard<'_, ()> {
+        self.comments_mutex.lock().await
+    }
+}
+
+/// Unpacks a tarball whose content is nested inside a single top level folder, as returned
+/// by the archive endpoints of GitHub, GitLab and Gitea
+pub(crate) fn unpack_tarball(bytes: &[u8], path: &Path) -> anyhow::Result<()> {
+    let mut archive = Archive::new(GzDecoder::new(Cursor::new(bytes)));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?;
+        let mut components = entry_path.components();
+        components.next();
+        let inner_path = components.as_path();
+        if !inner_path.as_os_str().is_empty() {
+            entry.unpack(&path.join(inner_path))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod source_tests {
+    use super::RepoRef;
+
+    #[test]
+    fn test_repo_ref_round_trip() {
+        let refs = [
+            "github:123",
+            "gitlab:gitlab.com/group/subgroup/project",
+            "gitea:codeberg.org/owner/repo",
+            "git:https://example.com/repo.git",
+            "git:git@example.com:owner/repo.git",
+        ];
+        for value in refs {
+            let parsed: RepoRef = value.parse().unwrap();
+            assert_eq!(parsed.to_string(), value);
+        }
+        assert_eq!(
+            "git:git@example.com:owner/repo.git"
+                .parse::<RepoRef>()
+                .unwrap(),
+            RepoRef::Git("git@example.com:owner/repo.git".to_owned())
+        );
+        assert!("123".parse::<RepoRef>().is_err());
+        assert!("github:abc".parse::<RepoRef>().is_err());
+        assert!("gitlab:gitlab.com/project".parse::<RepoRef>().is_err());
+        assert!("gitea:codeberg.org/owner".parse::<RepoRef>().is_err());
+    }
+}
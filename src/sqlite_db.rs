@@ -1,27 +1,38 @@
 use std::{
-    ops::Deref,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use anyhow::{anyhow, bail};
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use jsonwebtoken::EncodingKey;
-use ring::{
-    pkcs8,
-    signature::{Ed25519KeyPair, KeyPair},
-};
-use walkdir::WalkDir;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use tracing::error;
 
 use crate::{
-    container::{sqld::SqldContainer, Container},
+    branching::create_branch,
+    container::{
+        sqld::{SqldContainer, JWT_KEY_VAR},
+        Container,
+    },
     db::nano_id::NanoId,
-    deployments::worker::WorkerHandle,
-    paths::{get_libsql_branch_dir, get_propd_libqsl_dir},
+    deployments::{config::ResourceLimits, worker::WorkerHandle},
+    env::EnvVars,
+    paths::{
+        get_libsql_branch_dir, get_libsql_branch_keys_dir, get_prod_libsql_keys_dir,
+        get_propd_libqsl_dir,
+    },
     tokens::Role,
-    utils::now_in_seconds,
+    utils::{now, now_in_seconds},
 };
 
+const KEY_EXTENSION: &str = ".pk8";
+/// superseded keys are accepted for as long as the expiring tokens signed with them
+pub(crate) const KEY_GRACE_PERIOD: i64 = 24 * 60 * 60 * 1000;
+/// DER header of an Ed25519 SubjectPublicKeyInfo, followed by the raw 32 bytes key
+const ED25519_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
 #[derive(Debug)]
 pub(crate) struct ProdSqliteDb {
     pub(crate) setup: SqliteDbSetup,
@@ -34,11 +45,12 @@ impl ProdSqliteDb {
     #[tracing::instrument]
     pub(crate) fn new(project_id: &NanoId, build_queue: WorkerHandle) -> anyhow::Result<Self> {
         let folder = get_propd_libqsl_dir(project_id.as_str());
-        let auth = SqldAuth::new();
+        let auth = SqldAuth::load(&get_prod_libsql_keys_dir(project_id.as_str()));
         let container = SqldContainer::new(
             folder.clone(),
-            &auth.get_url_safe_key(),
+            auth.get_jwt_keys(),
             build_queue.clone(),
+            Default::default(),
         )
         .into();
 
@@ -52,15 +64,31 @@ impl ProdSqliteDb {
         })
     }
 
+    /// Loads the keys from disk again, returning the env vars the sqld container
+    /// needs to be restarted with for them to be accepted
+    #[tracing::instrument]
+    pub(crate) fn reload_keys(&mut self, project_id: &NanoId) -> EnvVars {
+        self.setup.auth = SqldAuth::load(&get_prod_libsql_keys_dir(project_id.as_str()));
+        [(JWT_KEY_VAR, self.setup.auth.get_jwt_keys())]
+            .as_ref()
+            .into()
+    }
+
     #[tracing::instrument]
-    pub(crate) fn branch(&self, deployment_id: &NanoId) -> BranchSqliteDb {
+    /// The branch db container gets the resource limits of the deployment
+    pub(crate) fn branch(
+        &self,
+        deployment_id: &NanoId,
+        resources: ResourceLimits,
+    ) -> BranchSqliteDb {
         let branch_folder = get_libsql_branch_dir(deployment_id.as_str());
-        let auth = SqldAuth::new();
+        let auth = SqldAuth::load(&get_libsql_branch_keys_dir(deployment_id.as_str()));
         BranchSqliteDb {
             base_folder: self.setup.folder.clone(),
             branch_folder,
             build_queue: self.build_queue.clone(),
             auth,
+            resources,
         }
     }
 }
@@ -71,16 +99,18 @@ pub(crate) struct BranchSqliteDb {
     pub(crate) branch_folder: PathBuf,
     build_queue: WorkerHandle,
     pub(crate) auth: SqldAuth,
+    resources: ResourceLimits,
 }
 
 impl BranchSqliteDb {
     #[tracing::instrument]
     pub(crate) async fn setup(&self) -> anyhow::Result<SqliteDbSetup> {
-        recursive_copy(&self.base_folder, &self.branch_folder).await?;
+        create_branch(&self.base_folder, &self.branch_folder, is_immutable).await?;
         let container = SqldContainer::new(
             self.branch_folder.clone(),
-            &self.auth.get_url_safe_key(),
+            self.auth.get_jwt_keys(),
             self.build_queue.clone(),
+            self.resources,
         )
         .into();
         Ok(SqliteDbSetup {
@@ -100,7 +130,10 @@ pub(crate) struct SqliteDbSetup {
 
 #[derive(Clone)]
 pub(crate) struct SqldAuth {
-    key_pair: Arc<pkcs8::Document>,
+    /// pkcs8 document of the active key, the one used to sign new tokens
+    key_pair: Arc<Vec<u8>>,
+    /// public keys accepted by sqld, including the ones still in their grace period
+    jwt_keys: String,
     permanent_token: String,
 }
 
@@ -111,21 +144,26 @@ impl std::fmt::Debug for SqldAuth {
 }
 
 impl SqldAuth {
+    /// Keys are persisted so tokens keep working across restarts. If that is not possible,
+    /// a temporary key is used instead so the db is still usable
     #[tracing::instrument]
-    fn new() -> Self {
-        let key_pair = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+    fn load(folder: &Path) -> Self {
+        let keys = read_or_generate_keys(folder).unwrap_or_else(|e| {
+            error!("could not load libsql keys from {folder:?}, using a temporary key: {e}");
+            vec![generate_key_pair()]
+        });
+        let key_pair = keys.last().unwrap().clone();
         let permanent_token = generate_token(&key_pair, DbAccess::Rw, false);
         Self {
             key_pair: key_pair.into(),
+            jwt_keys: format_jwt_keys(&keys),
             permanent_token,
         }
     }
 
     #[tracing::instrument]
-    fn get_url_safe_key(&self) -> String {
-        let key_pair = self.key_pair.deref();
-        let pair = Ed25519KeyPair::from_pkcs8(key_pair.as_ref()).unwrap();
-        URL_SAFE_NO_PAD.encode(pair.public_key().as_ref())
+    fn get_jwt_keys(&self) -> &str {
+        &self.jwt_keys
     }
 
     #[tracing::instrument]
@@ -139,9 +177,82 @@ impl SqldAuth {
     }
 }
 
-fn generate_token(key_pair: &pkcs8::Document, a: DbAccess, expire: bool) -> String {
+/// Adds a new active key. Unless `revoke` is set, the previous keys are still accepted
+/// during `KEY_GRACE_PERIOD`, otherwise they are removed straight away
+#[tracing::instrument]
+pub(crate) fn rotate_keys(folder: &Path, revoke: bool) -> anyhow::Result<()> {
+    let previous = list_key_files(folder)?;
+    write_key(folder, &generate_key_pair())?;
+    if revoke {
+        for (_, path) in previous {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the keys still accepted sorted from oldest to newest, removing the ones
+/// that have been superseded for longer than the grace period
+fn read_or_generate_keys(folder: &Path) -> anyhow::Result<Vec<Vec<u8>>> {
+    let files = list_key_files(folder)?;
+    let mut keys = vec![];
+    for (index, (_, path)) in files.iter().enumerate() {
+        let superseded = files.get(index + 1).map(|(created, _)| *created);
+        if superseded.is_some_and(|created| now() - created > KEY_GRACE_PERIOD) {
+            std::fs::remove_file(path)?;
+        } else {
+            keys.push(std::fs::read(path)?);
+        }
+    }
+    if keys.is_empty() {
+        let key_pair = generate_key_pair();
+        write_key(folder, &key_pair)?;
+        keys.push(key_pair);
+    }
+    Ok(keys)
+}
+
+fn list_key_files(folder: &Path) -> anyhow::Result<Vec<(i64, PathBuf)>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(folder)? {
+        let path = entry?.path();
+        let created = path
+            .file_name()
+            .and_then(|name| name.to_str()?.strip_suffix(KEY_EXTENSION)?.parse().ok());
+        if let Some(created) = created {
+            files.push((created, path));
+        }
+    }
+    files.sort_by_key(|(created, _)| *created);
+    Ok(files)
+}
+
+fn write_key(folder: &Path, key_pair: &[u8]) -> anyhow::Result<()> {
+    let path = folder.join(format!("{}{KEY_EXTENSION}", now()));
+    std::fs::write(path, key_pair)?;
+    Ok(())
+}
+
+fn generate_key_pair() -> Vec<u8> {
+    let document = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+    document.as_ref().to_vec()
+}
+
+/// sqld accepts several public keys as a list of PEM blocks
+fn format_jwt_keys(keys: &[Vec<u8>]) -> String {
+    keys.iter()
+        .map(|key_pair| {
+            let pair = Ed25519KeyPair::from_pkcs8(key_pair).unwrap();
+            let der = [ED25519_SPKI_PREFIX, pair.public_key().as_ref()].concat();
+            let encoded = STANDARD.encode(der);
+            format!("-----BEGIN PUBLIC KEY-----\n{encoded}\n-----END PUBLIC KEY-----\n")
+        })
+        .collect()
+}
+
+fn generate_token(key_pair: &[u8], a: DbAccess, expire: bool) -> String {
     let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::EdDSA);
-    let encoding_key = EncodingKey::from_ed_der(key_pair.as_ref());
+    let encoding_key = EncodingKey::from_ed_der(key_pair);
     let iat = now_in_seconds();
     let claims = Claims {
         a,
@@ -180,44 +291,8 @@ pub struct Claims {
     exp: Option<i64>, // epoch in seconds
 }
 
-#[tracing::instrument]
-async fn recursive_copy(from: &Path, to: &Path) -> anyhow::Result<()> {
-    for entry_result in WalkDir::new(from) {
-        let entry = entry_result?;
-        let relative = entry.path().strip_prefix(from)?;
-        let new_path = to.join(relative);
-        if entry.file_type().is_dir() {
-            // we ignore errors expecting file creations to fail later on if something
-            // maybe I should check the error is actually:File exists
-            // or maybe check if the directory already exists, if not, create it
-            let _ = tokio::fs::create_dir_all(&new_path).await;
-        } else if entry.file_type().is_file() {
-            tokio::fs::copy(entry.path(), &new_path)
-                .await
-                .map_err(|e| {
-                    anyhow!(
-                        "error when trying to copy file from {:?} to {new_path:?}: {e}",
-                        entry.path(),
-                    )
-                })?;
-        } else if entry.file_type().is_symlink() {
-            let points_to = std::fs::read_link(entry.path())?;
-            if points_to.is_relative() {
-                let as_absolute = entry.path().join(&points_to);
-                let inside_folder = as_absolute.strip_prefix(from);
-                if inside_folder.is_err() {
-                    bail!("trying to copy folder that contains relative link pointing outside the folder")
-                }
-                std::os::unix::fs::symlink(points_to, new_path)?;
-            } else {
-                bail!("trying to copy folder that contains absolute links")
-            }
-        } else {
-            bail!(
-                "trying to copy folder that contains unsupported file type {:?}",
-                entry.file_type()
-            )
-        }
-    }
-    Ok(())
+/// sqld snapshot files are written once and never modified afterwards
+fn is_immutable(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "snap")
 }
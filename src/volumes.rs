@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use walkdir::WalkDir;
+
+use crate::{
+    branching::{create_branch, remove_branch},
+    db::nano_id::NanoId,
+    paths::{get_prod_volume_dir, get_volume_branch_dir},
+};
+
+/// Persistent folder declared in prezel.json and mounted into the app container
+#[derive(Debug, Clone)]
+pub(crate) struct Volume {
+    pub(crate) name: String,
+    /// mount path inside the container
+    pub(crate) target: String,
+    pub(crate) folder: PathBuf,
+    /// prod folder the volume is cloned from, only for preview deployments
+    base_folder: Option<PathBuf>,
+}
+
+impl Volume {
+    /// Preview deployments get their own copy of the prod volume,
+    /// while deployments from the default branch share the prod one
+    pub(crate) fn new(
+        project: &NanoId,
+        deployment: &NanoId,
+        branch: bool,
+        name: &str,
+        target: &str,
+    ) -> Self {
+        let prod_folder = get_prod_volume_dir(project.as_str(), name);
+        let (folder, base_folder) = if branch {
+            let folder = get_volume_branch_dir(deployment.as_str(), name);
+            (folder, Some(prod_folder))
+        } else {
+            (prod_folder, None)
+        };
+        Self {
+            name: name.to_owned(),
+            target: target.to_owned(),
+            folder,
+            base_folder,
+        }
+    }
+
+    /// Deployments from the default branch mount the prod folder instead of a copy
+    pub(crate) fn is_prod(&self) -> bool {
+        self.base_folder.is_none()
+    }
+
+    pub(crate) fn get_bind(&self) -> String {
+        format!("{}:{}", self.folder.display(), self.target)
+    }
+
+    /// Clones the prod volume for preview deployments, only the first time
+    #[tracing::instrument]
+    pub(crate) async fn setup(&self) -> anyhow::Result<()> {
+        if let Some(base_folder) = &self.base_folder {
+            create_branch(base_folder, &self.folder, |_| false).await?;
+        }
+        Ok(())
+    }
+
+    /// Preview volumes go back to a fresh copy of the prod one, prod volumes are emptied
+    #[tracing::instrument]
+    pub(crate) async fn reset(&self) -> anyhow::Result<()> {
+        remove_branch(&self.folder).await?;
+        self.setup().await
+    }
+
+    /// Size in bytes of the files in the volume
+    #[tracing::instrument]
+    pub(crate) async fn get_size(&self) -> anyhow::Result<u64> {
+        let folder = self.folder.clone();
+        tokio::task::spawn_blocking(move || {
+            WalkDir::new(folder)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| Ok(entry.metadata()?.len()))
+                .sum()
+        })
+        .await?
+    }
+}